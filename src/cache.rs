@@ -0,0 +1,56 @@
+use crate::output::ResultSet;
+use std::fs;
+use std::path::PathBuf;
+
+const LAST_SCOPE_FILE: &str = "last-scope.json";
+const HISTORY_FILE: &str = "history.json";
+
+/// Location of a cache file, kept next to the config file
+fn cache_path(file: &str) -> Option<PathBuf> {
+    confy::get_configuration_file_path(env!("CARGO_PKG_NAME"), None)
+        .ok()
+        .and_then(|config| config.parent().map(|dir| dir.join(file)))
+}
+
+/// Remember the repos that matched the current command for `--scope-from-last`.
+/// The cache is best effort, failing to write it never fails the command.
+pub fn save_last_scope(repos: &[String]) {
+    if let Some(path) = cache_path(LAST_SCOPE_FILE) {
+        if let Ok(json) = serde_json::to_string_pretty(repos) {
+            let _ = fs::write(path, json);
+        }
+    }
+}
+
+/// Repos that matched the previous multi-repo command
+pub fn load_last_scope() -> Result<Vec<String>, String> {
+    let path = cache_path(LAST_SCOPE_FILE).ok_or("could not locate the grepo config directory")?;
+    let json = fs::read_to_string(&path)
+        .map_err(|_| "no previous results to scope from, run a search or branch command first".to_string())?;
+    serde_json::from_str(&json).map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+/// Previously recorded result sets, newest first
+pub fn load_results() -> Vec<ResultSet> {
+    cache_path(HISTORY_FILE)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Add a result set to the history, keeping at most `limit` entries. A set recorded
+/// earlier by the same command invocation is replaced.
+pub fn save_result(set: &ResultSet, limit: usize) {
+    if limit == 0 {
+        return;
+    }
+    let mut history = load_results();
+    if history.first().is_some_and(|last| last.invocation == set.invocation && last.command == set.command) {
+        history.remove(0);
+    }
+    history.insert(0, set.clone());
+    history.truncate(limit);
+    if let (Some(path), Ok(json)) = (cache_path(HISTORY_FILE), serde_json::to_string(&history)) {
+        let _ = fs::write(path, json);
+    }
+}
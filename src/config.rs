@@ -0,0 +1,334 @@
+use crate::{dates, lint};
+use confy::ConfyError;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tabled::Tabled;
+use thiserror::Error;
+
+/// Base path used when no home directory can be found
+const BASE_PATH: &str = "/repos";
+
+#[derive(Tabled, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    #[tabled(rename = "Base Path")]
+    pub base_path: Arc<str>,
+    #[tabled(rename = "Repos", display_with = "concatenate_values")]
+    pub repos: Vec<String>,
+    /// Use the git binary instead of libgit2 for operations both support
+    #[tabled(skip)]
+    pub prefer_cli: bool,
+    /// Directory where `repo archive` writes archives
+    #[tabled(skip)]
+    pub archive_dir: Arc<str>,
+    /// Rules used by `lint commits`
+    #[tabled(skip)]
+    pub commit_lint: lint::CommitLintRules,
+    /// Email domains `audit identity` accepts for user.email, any domain when empty
+    #[tabled(skip)]
+    pub allowed_email_domains: Vec<String>,
+    /// Sub-projects of monorepos listed and searched as repos of their own
+    #[tabled(skip)]
+    pub virtual_repos: Vec<VirtualRepo>,
+    /// Normalized origin URL of each watched repo, used by `doctor` to find moved repos
+    #[tabled(skip)]
+    pub origin_fingerprints: BTreeMap<String, String>,
+    /// Commits `search commit --deepen` fetches into shallow clones when no count is given
+    #[tabled(skip)]
+    pub deepen_by: u32,
+    /// How dates are shown: iso, local, relative or a strftime pattern
+    #[tabled(skip)]
+    pub date_format: String,
+    /// How many previous result sets `grepo last` can replay, 0 disables the history
+    #[tabled(skip)]
+    pub history_size: usize,
+    /// Command `search content --edit` opens a match with, where {file} and {line} are
+    /// replaced; without one $VISUAL or $EDITOR is run with `+{line} {file}`
+    #[tabled(skip)]
+    pub editor_command: Option<String>,
+    /// Treat branch and commit search patterns as regexes without passing --regex
+    #[tabled(skip)]
+    pub search_regex: bool,
+    /// Ignore case in branch and commit searches without passing --ignore-case
+    #[tabled(skip)]
+    pub ignore_case: bool,
+    /// Branch names or `*` patterns that branch deletion never touches, even with --force
+    #[tabled(skip)]
+    pub protected_branches: Vec<String>,
+    /// Directories content search skips in every repo: names match at any depth, paths
+    /// containing `/` only from the repo root
+    #[tabled(skip)]
+    pub vendored_dirs: Vec<String>,
+    /// Per-repo replacements for vendored_dirs, an empty list searches everything
+    #[tabled(skip)]
+    pub repo_vendored_dirs: BTreeMap<String, Vec<String>>,
+    /// Named sets of repos made with `repo tag`, picked with --group
+    #[tabled(skip)]
+    pub groups: BTreeMap<String, RepoGroup>,
+    /// Flags for the current invocation, never written to the config file
+    #[serde(skip)]
+    #[tabled(skip)]
+    pub runtime: RunOptions,
+}
+
+/// A path inside a watched repo that grepo treats as a repo of its own. Commit search
+/// only matches commits touching the path, other commands see the whole repo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtualRepo {
+    /// Name used in listings and with scope flags, e.g. "mono/payments"
+    pub name: String,
+    /// Watched repo (or path) containing the sub-project
+    pub repo: String,
+    /// Path prefix of the sub-project inside the repo, e.g. "services/payments"
+    pub path: String,
+}
+
+/// A `repo tag` group and the defaults its repos run with
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoGroup {
+    /// Watched repos (or virtual repos) in the group
+    pub repos: Vec<String>,
+    /// `repo fetch` skips these repos unless the group is picked with --group
+    pub exclude_from_fetch: bool,
+    /// Commit searches only walk the checked-out branch of these repos
+    pub head_only: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Fail at the first repo error instead of reporting failures at the end
+    pub strict: bool,
+    /// Modify repos even when they are dirty or have an operation in progress
+    pub force: bool,
+    /// The repos were picked by a scope flag, so virtual repos are not added
+    pub scoped: bool,
+    /// How many repos to process at once, 0 for one per CPU
+    pub jobs: usize,
+    /// Only run against the repos in this group
+    pub group: Option<String>,
+}
+
+impl RunOptions {
+    /// How many repos (or commands) to run at once
+    pub fn job_count(&self) -> usize {
+        match self.jobs {
+            0 => std::thread::available_parallelism().map_or(1, usize::from),
+            jobs => jobs,
+        }
+    }
+}
+
+impl ConfigFile {
+    /// The configured date format, checked at startup
+    pub fn date_format(&self) -> dates::DateFormat {
+        dates::DateFormat::parse(&self.date_format).unwrap_or(dates::DateFormat::Iso)
+    }
+
+    /// Whether a branch matches one of the protected_branches patterns
+    pub fn is_protected(&self, branch: &str) -> bool {
+        self.protecting_pattern(branch).is_some()
+    }
+
+    /// The first protected_branches pattern a branch matches, where `*` matches any run
+    /// of characters
+    pub fn protecting_pattern(&self, branch: &str) -> Option<&str> {
+        self.protected_branches.iter().map(String::as_str).find(|pattern| glob(pattern).is_match(branch))
+    }
+
+    /// The vendored directories content search skips in a repo
+    pub fn vendored_dirs(&self, repo: &str) -> &[String] {
+        self.repo_vendored_dirs.get(repo).unwrap_or(&self.vendored_dirs)
+    }
+
+    /// The groups a repo is in
+    pub fn groups_of<'a>(&'a self, repo: &'a str) -> impl Iterator<Item = &'a RepoGroup> + 'a {
+        self.groups.values().filter(move |group| group.repos.iter().any(|name| name == repo))
+    }
+
+    /// Working directory of the repo a watched or virtual repo lives in
+    pub fn repo_root(&self, name: &str) -> PathBuf {
+        let base = Path::new(&*self.base_path);
+        match self.virtual_repos.iter().find(|v| v.name == name) {
+            Some(v) => base.join(&v.repo),
+            None => base.join(name),
+        }
+    }
+
+    /// A watch list entry as it is stored: trimmed, without `.` components or trailing
+    /// slashes, and relative to the base directory when it is inside it
+    pub fn normalize_entry(&self, entry: &str) -> String {
+        let path = Path::new(entry.trim()).components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .collect::<PathBuf>();
+        let path = path.strip_prefix(&*self.base_path).map(Path::to_path_buf).unwrap_or(path);
+        path.display().to_string()
+    }
+
+    /// Normalize the watch list and the names groups, fingerprints and virtual repos use
+    /// for its entries, dropping entries that are then listed twice. Returns the dropped
+    /// entries as they were written.
+    pub fn normalize_repos(&mut self) -> Vec<String> {
+        let mut repos: Vec<String> = Vec::with_capacity(self.repos.len());
+        let mut dropped = Vec::new();
+        for entry in std::mem::take(&mut self.repos) {
+            let normalized = self.normalize_entry(&entry);
+            if repos.contains(&normalized) {
+                dropped.push(entry);
+                continue;
+            }
+            if normalized != entry {
+                if let Some(fingerprint) = self.origin_fingerprints.remove(&entry) {
+                    self.origin_fingerprints.entry(normalized.clone()).or_insert(fingerprint);
+                }
+            }
+            repos.push(normalized);
+        }
+        self.repos = repos;
+        let virtual_repos = std::mem::take(&mut self.virtual_repos);
+        self.virtual_repos = virtual_repos.into_iter()
+            .map(|v| VirtualRepo { repo: self.normalize_entry(&v.repo), ..v })
+            .collect();
+        let groups = std::mem::take(&mut self.groups);
+        for (name, mut group) in groups {
+            let mut members: Vec<String> = Vec::with_capacity(group.repos.len());
+            for member in group.repos.iter().map(|member| self.normalize_entry(member)) {
+                if !members.contains(&member) {
+                    members.push(member);
+                }
+            }
+            group.repos = members;
+            self.groups.insert(name, group);
+        }
+        dropped
+    }
+
+    /// Directory of a watched repo, or of the sub-project for a virtual repo
+    pub fn repo_dir(&self, name: &str) -> PathBuf {
+        let base = Path::new(&*self.base_path);
+        match self.virtual_repos.iter().find(|v| v.name == name) {
+            Some(v) => base.join(&v.repo).join(&v.path),
+            None => base.join(name),
+        }
+    }
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Self {
+            base_path: Arc::from(default_base_path()),
+            repos: Vec::new(),
+            prefer_cli: false,
+            archive_dir: Arc::from("."),
+            commit_lint: lint::CommitLintRules::default(),
+            allowed_email_domains: Vec::new(),
+            virtual_repos: Vec::new(),
+            origin_fingerprints: BTreeMap::new(),
+            deepen_by: 500,
+            date_format: "iso".to_string(),
+            history_size: 10,
+            editor_command: None,
+            search_regex: false,
+            ignore_case: false,
+            protected_branches: vec!["main".to_string(), "master".to_string(), "release/*".to_string()],
+            vendored_dirs: ["node_modules", "vendor", "target", "dist"].map(String::from).to_vec(),
+            repo_vendored_dirs: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            runtime: RunOptions::default(),
+        }
+    }
+}
+
+/// Regex for a branch name pattern where `*` matches any run of characters
+pub fn glob(pattern: &str) -> regex::Regex {
+    let pattern = pattern.split('*').map(regex::escape).collect::<Vec<_>>().join(".*");
+    regex::Regex::new(&format!("^{}$", pattern)).expect("an escaped pattern is a valid regex")
+}
+
+/// `repos` in the user's home directory (`%USERPROFILE%\repos` on Windows, `~/repos`
+/// elsewhere), falling back to `/repos`
+fn default_base_path() -> String {
+    std::env::home_dir()
+        .filter(|home| !home.as_os_str().is_empty())
+        .map(|home| home.join("repos").display().to_string())
+        .unwrap_or_else(|| BASE_PATH.to_string())
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not read the config file: {0}")]
+    LoadFailure(#[source] ConfyError),
+    #[error("Could not write the config file: {0}")]
+    StoreFailure(#[source] ConfyError),
+    #[error("Could not locate the config file: {0}")]
+    PathUnknown(#[source] ConfyError),
+}
+
+/// Load the config file, replacing it with defaults when it cannot be parsed
+pub fn load() -> Result<ConfigFile, ConfigError> {
+    match confy::load(env!("CARGO_PKG_NAME"), None) {
+        Ok(cf) => Ok(cf),
+        Err(ConfyError::BadYamlData(_)) => {
+            let newcfg = ConfigFile {
+                ..Default::default()
+            };
+            store(&newcfg)?;
+            Ok(newcfg)
+        }
+        Err(e) => Err(ConfigError::LoadFailure(e)),
+    }
+}
+
+/// Write the config, with the watch list normalized. The watch list and group members
+/// are written sorted, so the file only changes where the lists do.
+pub fn store(cfg: &ConfigFile) -> Result<(), ConfigError> {
+    let mut cfg = cfg.clone();
+    cfg.normalize_repos();
+    cfg.repos.sort();
+    cfg.groups.values_mut().for_each(|group| group.repos.sort());
+    confy::store(env!("CARGO_PKG_NAME"), None, &cfg).map_err(ConfigError::StoreFailure)
+}
+
+pub fn path() -> Result<PathBuf, ConfigError> {
+    confy::get_configuration_file_path(env!("CARGO_PKG_NAME"), None).map_err(ConfigError::PathUnknown)
+}
+
+fn concatenate_values(values: &[String]) -> String {
+    values.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_configs_use_the_default_base_path() {
+        assert_eq!(&*ConfigFile::default().base_path, default_base_path());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn default_base_path_is_under_the_user_profile() {
+        let profile = std::env::var("USERPROFILE").expect("USERPROFILE is set");
+        assert_eq!(default_base_path(), format!("{}\\repos", profile.trim_end_matches('\\')));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn default_base_path_is_under_home() {
+        match std::env::var("HOME") {
+            Ok(home) if !home.is_empty() => {
+                assert_eq!(Path::new(&default_base_path()), Path::new(&home).join("repos"));
+            }
+            _ => assert_eq!(default_base_path(), BASE_PATH),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn default_base_path_is_not_on_the_read_only_system_volume() {
+        assert_ne!(default_base_path(), BASE_PATH);
+        assert!(default_base_path().starts_with('/'));
+    }
+}
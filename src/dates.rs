@@ -0,0 +1,217 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{FixedOffset, Local, NaiveDate, TimeZone};
+
+/// Parse a point in time given either as a date (`2024-01-01`) or as a span back
+/// from now (`3m`, `2w`, `10d`, `1y`, `2.weeks`), returning a unix timestamp
+pub fn parse_since(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+        return Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|dt| dt.timestamp())
+            .ok_or_else(|| format!("'{}' is not a valid local date", value));
+    }
+
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("'{}' is not a date (YYYY-MM-DD) or a span like 3m, 2w, 10d or 2.weeks", value))?;
+    let days_per_unit = match unit.trim_start_matches('.') {
+        "d" | "day" | "days" => 1,
+        "w" | "week" | "weeks" => 7,
+        "m" | "month" | "months" => 30,
+        "y" | "year" | "years" => 365,
+        _ => return Err(format!("unknown time unit in '{}', expected d, w, m or y", value)),
+    };
+    amount
+        .checked_mul(days_per_unit * 24 * 60 * 60)
+        .and_then(|span| Local::now().timestamp().checked_sub(span))
+        .ok_or_else(|| format!("span too large in '{}'", value))
+}
+
+/// Like `parse_since`, but a date means the end of that day, so `--until 2024-01-31`
+/// includes commits made on the 31st
+pub fn parse_until(value: &str) -> Result<i64, String> {
+    let end_of_day = NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").is_ok();
+    parse_since(value).map(|timestamp| if end_of_day { timestamp + 24 * 60 * 60 - 1 } else { timestamp })
+}
+
+/// Check that a `--since`/`--until` window is not empty, as it would be with `until`
+/// before `since`
+pub fn check_window(since: Option<i64>, until: Option<i64>) -> Result<(), String> {
+    match (since, until) {
+        (Some(since), Some(until)) if until < since => Err(format!(
+            "--until ({}) is before --since ({}), so no commit could match",
+            DateFormat::Local.format(until, 0),
+            DateFormat::Local.format(since, 0),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// How timestamps are written in output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `2024-05-01T14:03:00+02:00`, in the timezone the time was recorded in
+    Iso,
+    /// `2024-05-01 14:03`, in the local timezone
+    Local,
+    /// `3 days ago`
+    Relative,
+    /// A strftime pattern applied in the local timezone, e.g. `%d %b %Y`
+    Custom(String),
+}
+
+impl DateFormat {
+    /// Parse `iso`, `local`, `relative` or a strftime pattern
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "iso" => Ok(DateFormat::Iso),
+            "local" => Ok(DateFormat::Local),
+            "relative" => Ok(DateFormat::Relative),
+            "" => Err("date format is empty, expected iso, local, relative or a strftime pattern".to_string()),
+            pattern => {
+                let invalid = StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error));
+                if invalid || !pattern.contains('%') {
+                    return Err(format!("'{}' is not iso, local, relative or a valid strftime pattern", pattern));
+                }
+                Ok(DateFormat::Custom(pattern.to_string()))
+            }
+        }
+    }
+
+    /// Format a unix timestamp recorded on this machine
+    pub fn format_local(&self, seconds: i64) -> String {
+        let offset = Local.timestamp_opt(seconds, 0).single().map_or(0, |dt| dt.offset().local_minus_utc() / 60);
+        self.format(seconds, offset)
+    }
+
+    /// Format a unix timestamp recorded with a UTC offset in minutes (as git stores them)
+    pub fn format(&self, seconds: i64, offset_minutes: i32) -> String {
+        let Some(utc) = chrono::DateTime::from_timestamp(seconds, 0) else {
+            return "-".to_string();
+        };
+        match self {
+            DateFormat::Iso => FixedOffset::east_opt(offset_minutes * 60)
+                .map(|offset| utc.with_timezone(&offset).to_rfc3339())
+                .unwrap_or_else(|| utc.to_rfc3339()),
+            DateFormat::Local => utc.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+            DateFormat::Relative => relative(Local::now().timestamp() - seconds),
+            DateFormat::Custom(pattern) => utc.with_timezone(&Local).format(pattern).to_string(),
+        }
+    }
+}
+
+/// Describe an age in seconds the way `git log --date=relative` does
+fn relative(age: i64) -> String {
+    if age < 0 {
+        return "in the future".to_string();
+    }
+    let (amount, unit) = match age {
+        0..=89 => return "just now".to_string(),
+        90..=5399 => (age / 60, "minute"),
+        5400..=129_599 => (age / 3600, "hour"),
+        129_600..=1_209_599 => (age / 86_400, "day"),
+        1_209_600..=5_183_999 => (age / 604_800, "week"),
+        5_184_000..=31_535_999 => (age / 2_592_000, "month"),
+        _ => (age / 31_536_000, "year"),
+    };
+    format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn since_a_date_is_local_midnight() {
+        let midnight = Local.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).earliest().unwrap().timestamp();
+        assert_eq!(parse_since("2024-01-31"), Ok(midnight));
+        assert_eq!(parse_since(" 2024-01-31 "), Ok(midnight));
+    }
+
+    #[test]
+    fn since_a_span_counts_back_from_now() {
+        for (span, days) in [("10d", 10), ("2w", 14), ("2.weeks", 14), ("1week", 7), ("3m", 90), ("1y", 365), ("2.days", 2)] {
+            let expected = Local::now().timestamp() - days * DAY;
+            let parsed = parse_since(span).unwrap();
+            assert!((expected - parsed).abs() <= 2, "{}: {} vs {}", span, parsed, expected);
+        }
+    }
+
+    #[test]
+    fn since_rejects_bad_values() {
+        assert_eq!(parse_since("soon"), Err("'soon' is not a date (YYYY-MM-DD) or a span like 3m, 2w, 10d or 2.weeks".to_string()));
+        assert_eq!(parse_since("3h"), Err("unknown time unit in '3h', expected d, w, m or y".to_string()));
+        assert!(parse_since("2024-02-30").is_err());
+    }
+
+    #[test]
+    fn since_rejects_spans_that_overflow() {
+        assert_eq!(parse_since("99999999999999999d"), Err("span too large in '99999999999999999d'".to_string()));
+        assert_eq!(parse_since("9223372036854775807y"), Err("span too large in '9223372036854775807y'".to_string()));
+    }
+
+    #[test]
+    fn until_a_date_is_the_end_of_that_day() {
+        let since = parse_since("2024-01-31").unwrap();
+        assert_eq!(parse_until("2024-01-31"), Ok(since + DAY - 1));
+    }
+
+    #[test]
+    fn until_a_span_is_the_same_as_since() {
+        let since = parse_since("2w").unwrap();
+        let until = parse_until("2w").unwrap();
+        assert!((until - since).abs() <= 2);
+        assert!(parse_until("99999999999999999d").is_err());
+    }
+
+    #[test]
+    fn window_rejects_until_before_since() {
+        assert_eq!(check_window(Some(10), Some(20)), Ok(()));
+        assert_eq!(check_window(Some(10), Some(10)), Ok(()));
+        assert_eq!(check_window(None, Some(10)), Ok(()));
+        assert_eq!(check_window(Some(10), None), Ok(()));
+        let since = parse_since("2024-05-01").unwrap();
+        let until = parse_until("2024-01-01").unwrap();
+        let err = check_window(Some(since), Some(until)).unwrap_err();
+        assert_eq!(err, "--until (2024-01-01 23:59) is before --since (2024-05-01 00:00), so no commit could match");
+    }
+
+    #[test]
+    fn date_formats() {
+        assert_eq!(DateFormat::parse("iso"), Ok(DateFormat::Iso));
+        assert_eq!(DateFormat::parse(" local "), Ok(DateFormat::Local));
+        assert_eq!(DateFormat::parse("relative"), Ok(DateFormat::Relative));
+        assert_eq!(DateFormat::parse("%d %b %Y"), Ok(DateFormat::Custom("%d %b %Y".to_string())));
+        assert_eq!(DateFormat::parse(""), Err("date format is empty, expected iso, local, relative or a strftime pattern".to_string()));
+        assert_eq!(DateFormat::parse("short"), Err("'short' is not iso, local, relative or a valid strftime pattern".to_string()));
+        assert!(DateFormat::parse("%Q").is_err());
+    }
+
+    #[test]
+    fn iso_keeps_the_recorded_offset() {
+        assert_eq!(DateFormat::Iso.format(1_714_572_180, 120), "2024-05-01T16:03:00+02:00");
+        assert_eq!(DateFormat::Iso.format(1_714_572_180, 0), "2024-05-01T14:03:00+00:00");
+    }
+
+    #[test]
+    fn relative_ages() {
+        assert_eq!(relative(-5), "in the future");
+        assert_eq!(relative(0), "just now");
+        assert_eq!(relative(89), "just now");
+        assert_eq!(relative(90), "1 minute ago");
+        assert_eq!(relative(45 * 60), "45 minutes ago");
+        assert_eq!(relative(5400), "1 hour ago");
+        assert_eq!(relative(3 * DAY), "3 days ago");
+        assert_eq!(relative(14 * DAY), "2 weeks ago");
+        assert_eq!(relative(90 * DAY), "3 months ago");
+        assert_eq!(relative(365 * DAY), "1 year ago");
+        assert_eq!(relative(800 * DAY), "2 years ago");
+    }
+}
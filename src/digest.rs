@@ -0,0 +1,115 @@
+use crate::git::DigestEntry;
+use std::collections::BTreeMap;
+
+/// Output formats for `grepo digest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DigestFormat {
+    Markdown,
+    Html,
+}
+
+/// Top-level sections of a digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DigestGroup {
+    Author,
+    Repo,
+}
+
+/// A digest section: its heading and the entries listed under it
+type Section<'a> = (&'a str, Vec<&'a DigestEntry>);
+
+fn sections(entries: &[DigestEntry], group_by: DigestGroup) -> Vec<Section<'_>> {
+    let mut grouped: BTreeMap<&str, Vec<&DigestEntry>> = BTreeMap::new();
+    for entry in entries {
+        let key = match group_by {
+            DigestGroup::Author => &*entry.author,
+            DigestGroup::Repo => &*entry.repo,
+        };
+        grouped.entry(key).or_default().push(entry);
+    }
+    grouped.into_iter().collect()
+}
+
+fn row_label(entry: &DigestEntry, group_by: DigestGroup) -> &str {
+    match group_by {
+        DigestGroup::Author => &entry.repo,
+        DigestGroup::Repo => &entry.author,
+    }
+}
+
+fn row_heading(group_by: DigestGroup) -> &'static str {
+    match group_by {
+        DigestGroup::Author => "Repo",
+        DigestGroup::Repo => "Author",
+    }
+}
+
+/// Render a digest covering `period` (e.g. "since 2024-05-01")
+pub fn render(entries: &[DigestEntry], group_by: DigestGroup, format: DigestFormat, period: &str) -> String {
+    match format {
+        DigestFormat::Markdown => render_markdown(entries, group_by, period),
+        DigestFormat::Html => render_html(entries, group_by, period),
+    }
+}
+
+fn render_markdown(entries: &[DigestEntry], group_by: DigestGroup, period: &str) -> String {
+    let mut out = format!("# Grepo digest {}\n", period);
+    if entries.is_empty() {
+        out.push_str("\nNo activity.\n");
+        return out;
+    }
+    for (heading, rows) in sections(entries, group_by) {
+        let commits: usize = rows.iter().map(|r| r.commits).sum();
+        out.push_str(&format!("\n## {} ({} commits)\n\n", heading, commits));
+        out.push_str(&format!("| {} | Commits | Merged | New Branches |\n|---|---:|---|---|\n", row_heading(group_by)));
+        for row in rows {
+            out.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                markdown_cell(row_label(row, group_by)),
+                row.commits,
+                markdown_cell(&row.merged.join(", ")),
+                markdown_cell(&row.created.join(", "))
+            ));
+        }
+    }
+    out
+}
+
+fn render_html(entries: &[DigestEntry], group_by: DigestGroup, period: &str) -> String {
+    let mut out = format!("<h1>Grepo digest {}</h1>\n", escape_html(period));
+    if entries.is_empty() {
+        out.push_str("<p>No activity.</p>\n");
+        return out;
+    }
+    for (heading, rows) in sections(entries, group_by) {
+        let commits: usize = rows.iter().map(|r| r.commits).sum();
+        out.push_str(&format!("<h2>{} ({} commits)</h2>\n<table>\n", escape_html(heading), commits));
+        out.push_str(&format!(
+            "<tr><th>{}</th><th>Commits</th><th>Merged</th><th>New Branches</th></tr>\n",
+            row_heading(group_by)
+        ));
+        for row in rows {
+            out.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(row_label(row, group_by)),
+                row.commits,
+                escape_html(&row.merged.join(", ")),
+                escape_html(&row.created.join(", "))
+            ));
+        }
+        out.push_str("</table>\n");
+    }
+    out
+}
+
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,167 @@
+use crate::{git, ConfigFile};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tabled::Tabled;
+
+/// A problem with the watch list and what to do about it
+#[derive(Tabled, Clone)]
+pub struct Finding {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Problem")]
+    pub problem: Arc<str>,
+    #[tabled(rename = "Suggestion")]
+    pub suggestion: Arc<str>,
+    /// Unwatched repo with the same origin fingerprint that `doctor --fix` re-links to
+    #[tabled(skip)]
+    pub relink: Option<String>,
+}
+
+/// Check that every watched entry still resolves to a repo and that no two entries are
+/// the same directory. Entries that do not resolve are matched against unwatched repos
+/// in the base directory with the same origin fingerprint, or failing that whose origin
+/// URL names them.
+pub fn diagnose(cfg: &ConfigFile) -> Vec<Finding> {
+    let mut findings = duplicates(cfg);
+    let missing = cfg.repos.iter()
+        .filter(|name| !git::get_valid_repo(cfg.clone(), name.to_string()))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return findings;
+    }
+
+    let candidates = unwatched_repos(cfg)
+        .into_iter()
+        .filter_map(|dir| git::origin_url(cfg.clone(), dir.clone()).map(|url| (dir, url)))
+        .collect::<Vec<_>>();
+    missing
+        .into_iter()
+        .map(|name| {
+            let same_origin = cfg.origin_fingerprints.get(name.as_str())
+                .map(|stored| candidates.iter().filter(|(_, url)| fingerprint(url) == *stored).collect::<Vec<_>>())
+                .unwrap_or_default();
+            if let [(dir, _)] = same_origin.as_slice() {
+                return Finding {
+                    repo: Arc::from(name.as_str()),
+                    problem: Arc::from("not a git repo"),
+                    suggestion: Arc::from(format!("same origin as {}, run doctor --fix to re-link", dir)),
+                    relink: Some(dir.clone()),
+                };
+            }
+            let renamed = candidates.iter()
+                .filter(|(_, url)| url_repo_name(url).eq_ignore_ascii_case(entry_name(name)))
+                .map(|(dir, _)| dir.as_str())
+                .collect::<Vec<_>>();
+            let suggestion = match renamed.as_slice() {
+                [] => "re-add it or remove it from the watch list".to_string(),
+                [dir] => format!("renamed? grepo repo rename {} {}", name, dir),
+                dirs => format!("renamed to one of: {}", dirs.join(", ")),
+            };
+            Finding {
+                repo: Arc::from(name.as_str()),
+                problem: Arc::from("not a git repo"),
+                suggestion: Arc::from(suggestion),
+                relink: None,
+            }
+        })
+        .for_each(|finding| findings.push(finding));
+    findings
+}
+
+/// Entries that differ in spelling but resolve to the directory of an earlier entry,
+/// through symlinks, `..` or a case-insensitive file system
+fn duplicates(cfg: &ConfigFile) -> Vec<Finding> {
+    let base = Path::new(&*cfg.base_path);
+    let mut seen: Vec<(PathBuf, &str)> = Vec::new();
+    let mut findings = Vec::new();
+    for name in &cfg.repos {
+        let Ok(dir) = fs::canonicalize(base.join(name)) else { continue };
+        match seen.iter().find(|(seen_dir, _)| *seen_dir == dir) {
+            Some((_, first)) => findings.push(Finding {
+                repo: Arc::from(name.as_str()),
+                problem: Arc::from(format!("same directory as {}", first)),
+                suggestion: Arc::from(format!("grepo repo remove --exact {}", name)),
+                relink: None,
+            }),
+            None => seen.push((dir, name)),
+        }
+    }
+    findings
+}
+
+/// Record the origin fingerprint of every watched repo that resolves. Entries that no
+/// longer resolve keep their last fingerprint so `doctor` can find where they went.
+pub fn refresh_fingerprints(cfg: &mut ConfigFile) {
+    update_fingerprints(cfg, true);
+}
+
+/// Like `refresh_fingerprints`, but only opens the entries without a fingerprint yet,
+/// so editing the watch list does not touch every watched repo
+pub fn record_new_fingerprints(cfg: &mut ConfigFile) {
+    update_fingerprints(cfg, false);
+}
+
+fn update_fingerprints(cfg: &mut ConfigFile, all: bool) {
+    let mut fingerprints = cfg.origin_fingerprints.clone();
+    fingerprints.retain(|name, _| cfg.repos.contains(name));
+    for name in &cfg.repos {
+        if !all && fingerprints.contains_key(name) {
+            continue;
+        }
+        if !git::get_valid_repo(cfg.clone(), name.clone()) {
+            continue;
+        }
+        match git::origin_url(cfg.clone(), name.clone()) {
+            Some(url) => fingerprints.insert(name.clone(), fingerprint(&url)),
+            None => fingerprints.remove(name),
+        };
+    }
+    cfg.origin_fingerprints = fingerprints;
+}
+
+/// Origin URL reduced to host and path, so the ssh and https URLs of a repo match:
+/// "git@github.com:org/api.git" and "https://github.com/org/api" -> "github.com/org/api"
+pub fn fingerprint(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        // scp-like syntax: [user@]host:path
+        None if !url.starts_with('/') && url.contains(':') => url.replacen(':', "/", 1),
+        None => url.to_string(),
+    };
+    let rest = rest.rsplit_once('@').filter(|(user, _)| !user.contains('/')).map_or(rest.as_str(), |(_, host)| host);
+    match rest.split_once('/') {
+        Some((host, path)) => format!("{}/{}", host.to_lowercase(), path),
+        None => rest.to_lowercase(),
+    }
+}
+
+/// Directories in the base directory that are repos but not on the watch list
+fn unwatched_repos(cfg: &ConfigFile) -> Vec<String> {
+    let mut dirs = fs::read_dir(&*cfg.base_path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| !cfg.repos.contains(name))
+                .filter(|name| git::get_valid_repo(cfg.clone(), name.clone()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// Last path component of a watch list entry, e.g. "api" for "team/api"
+fn entry_name(entry: &str) -> &str {
+    Path::new(entry).file_name().and_then(|name| name.to_str()).unwrap_or(entry)
+}
+
+/// Repo name at the end of a remote URL: "git@host:org/api.git" -> "api"
+fn url_repo_name(url: &str) -> &str {
+    let name = url.trim_end_matches('/').rsplit(['/', ':']).next().unwrap_or(url);
+    name.strip_suffix(".git").unwrap_or(name)
+}
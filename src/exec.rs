@@ -0,0 +1,183 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Output of one `--exec-each` command
+pub struct ExecOutcome {
+    pub command: String,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Why the command failed, if it did
+    pub failure: Option<String>,
+}
+
+/// Replace each `{name}` in the template with its shell-quoted value. Placeholders
+/// without a value are left in place.
+pub fn expand(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_string(), |command, (name, value)| {
+        command.replace(&format!("{{{}}}", name), &shell_quote(value))
+    })
+}
+
+#[cfg(not(windows))]
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn shell_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+fn shell(command: &str) -> Command {
+    let (program, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut shell = Command::new(program);
+    shell.args([flag, command]);
+    shell
+}
+
+/// Run a command through the shell attached to the terminal, e.g. an editor
+pub fn run_attached(command: &str) -> std::io::Result<std::process::ExitStatus> {
+    shell(command).status()
+}
+
+/// Run a command through the shell attached to the terminal with `input` on its stdin,
+/// e.g. a pager
+pub fn run_with_input(command: &str, input: &str) -> std::io::Result<std::process::ExitStatus> {
+    let mut child = shell(command).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager stops reading when it is quit early
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    child.wait()
+}
+
+/// One command line for program arguments: a single argument is taken as a shell command
+/// line as it is, several are quoted so the shell passes them on unchanged
+pub fn command_line(args: &[String]) -> String {
+    match args {
+        [command] => command.clone(),
+        _ => args.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" "),
+    }
+}
+
+/// Run the commands through the shell, at most `jobs` at a time, returning their
+/// outcomes in the order given. Once interrupted no further commands are started, so
+/// there may be fewer outcomes than commands.
+pub fn run_each(commands: Vec<String>, jobs: usize) -> Vec<ExecOutcome> {
+    let outcomes = Mutex::new(Vec::with_capacity(commands.len()));
+    run_parallel(commands.len(), jobs, |index| run(&commands[index], None), |index, outcome| {
+        outcomes.lock().unwrap_or_else(PoisonError::into_inner).push((index, outcome));
+    });
+    let mut outcomes = outcomes.into_inner().unwrap_or_else(PoisonError::into_inner);
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+/// Run one command through the shell in each directory, at most `jobs` at a time, handing
+/// each outcome and the index of its directory to `done` as soon as it finishes. Once
+/// interrupted no further directories are started.
+pub fn run_in_dirs(command: &str, dirs: &[PathBuf], jobs: usize, done: impl Fn(usize, ExecOutcome) + Sync) {
+    run_parallel(dirs.len(), jobs, |index| run(command, Some(&dirs[index])), done);
+}
+
+fn run_parallel(count: usize, jobs: usize, run: impl Fn(usize) -> ExecOutcome + Sync, done: impl Fn(usize, ExecOutcome) + Sync) {
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(count) {
+            scope.spawn(|| loop {
+                if crate::git::interrupted() {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= count {
+                    break;
+                }
+                done(index, run(index));
+            });
+        }
+    });
+}
+
+fn run(command: &str, dir: Option<&Path>) -> ExecOutcome {
+    let mut shell = shell(command);
+    if let Some(dir) = dir {
+        shell.current_dir(dir);
+    }
+    match shell.output() {
+        Ok(output) => ExecOutcome {
+            command: command.to_string(),
+            failure: (!output.status.success()).then(|| output.status.to_string()),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        },
+        Err(e) => ExecOutcome {
+            command: command.to_string(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            failure: Some(format!("could not start: {}", e)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AWKWARD: [&str; 4] = ["it's", "two words", "$(touch pwned)", "a'b\"c $HOME `id`"];
+
+    #[test]
+    fn placeholder_without_a_value_is_left_in_place() {
+        assert_eq!(expand("echo {repo} {line}", &[("repo", "api")]), format!("echo {} {{line}}", shell_quote("api")));
+        assert_eq!(expand("echo {}", &[("repo", "api")]), "echo {}");
+    }
+
+    #[test]
+    fn every_occurrence_is_replaced() {
+        let quoted = shell_quote("api");
+        assert_eq!(expand("{repo}/{repo}", &[("repo", "api")]), format!("{}/{}", quoted, quoted));
+    }
+
+    #[test]
+    fn single_argument_is_a_command_line_as_is() {
+        assert_eq!(command_line(&["git fetch && git status".to_string()]), "git fetch && git status");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn unix_quoting() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("$(touch pwned)"), "'$(touch pwned)'");
+        assert_eq!(command_line(&["git".to_string(), "log".to_string(), "--grep=it's".to_string()]), r"'git' 'log' '--grep=it'\''s'");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn shell_gets_values_back_unchanged() {
+        let commands = AWKWARD.iter().map(|value| expand("printf %s {value}", &[("value", value)])).collect();
+        let outcomes = run_each(commands, 2);
+        for (value, outcome) in AWKWARD.iter().zip(outcomes) {
+            assert_eq!(outcome.failure, None, "{}", outcome.command);
+            assert_eq!(String::from_utf8_lossy(&outcome.stdout), *value);
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn arguments_reach_the_program_unchanged() {
+        let args = ["printf", "%s|"].iter().chain(AWKWARD.iter()).map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let outcome = run_each(vec![command_line(&args)], 1).remove(0);
+        assert_eq!(String::from_utf8_lossy(&outcome.stdout), format!("{}|", AWKWARD.join("|")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_quoting() {
+        assert_eq!(shell_quote("two words"), "\"two words\"");
+        assert_eq!(shell_quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(shell_quote("it's"), "\"it's\"");
+    }
+}
@@ -0,0 +1,54 @@
+/// Similarity a `--fuzzy` search needs before text counts as a match
+pub const MIN_SIMILARITY: f64 = 0.6;
+
+/// How closely `text` matches a possibly misspelled `pattern`, from 0 (nothing alike)
+/// to 1 (text containing the pattern). Otherwise each pattern word is compared with its
+/// closest word in the text by shared letter pairs and the scores are averaged, so
+/// "paymetn retry" still scores high against "Retry failed payments".
+pub fn similarity(pattern: &str, text: &str) -> f64 {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    if pattern.trim().is_empty() {
+        return 0.0;
+    }
+    if text.contains(pattern.trim()) {
+        return 1.0;
+    }
+    let text_words = words(&text).map(bigrams).collect::<Vec<_>>();
+    let pattern_words = words(&pattern).map(bigrams).collect::<Vec<_>>();
+    if pattern_words.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = pattern_words
+        .iter()
+        .map(|wanted| text_words.iter().map(|word| dice(wanted, word)).fold(0.0, f64::max))
+        .sum();
+    total / pattern_words.len() as f64
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty())
+}
+
+/// Letter pairs of a word padded with a space on each side, so a one-letter word still has
+/// pairs and the first and last letters count as much as the others
+fn bigrams(word: &str) -> Vec<(char, char)> {
+    let padded = std::iter::once(' ').chain(word.chars()).chain(std::iter::once(' ')).collect::<Vec<_>>();
+    padded.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+/// Dice coefficient of two bigram multisets
+fn dice(a: &[(char, char)], b: &[(char, char)]) -> f64 {
+    let mut unmatched = b.to_vec();
+    let shared = a
+        .iter()
+        .filter(|pair| match unmatched.iter().position(|other| other == *pair) {
+            Some(index) => {
+                unmatched.swap_remove(index);
+                true
+            }
+            None => false,
+        })
+        .count();
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
@@ -1,247 +1,3439 @@
-use std::{collections::HashMap, fmt, rc::Rc};
-use crate::ConfigFile;
-use git2::{ErrorCode, Repository, Commit, ObjectType};
-use std::path::PathBuf;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::graph::{BranchNode, GraphCommit, RepoGraph};
+use crate::hooks::{self, HookError};
+use crate::{patch, query, script, sync};
+use crate::matcher::Matcher;
+use crate::{dates, lint, ConfigFile};
+use git2::build::CheckoutBuilder;
+use git2::{ConfigLevel, ErrorCode, Repository, RepositoryState, Commit, DiffOptions, ObjectType, Sort, StatusOptions};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
 use tabled::Tabled;
+use thiserror::Error;
 
 #[derive(Tabled, Debug)]
 pub struct RepoBranchCommit {
-    pub repo: Rc<str>,
-    pub branch: Rc<str>,
-    pub commit: Rc<str>,
-    pub author: Rc<str>,
-    pub message: Rc<str>,
+    pub repo: Arc<str>,
+    pub branch: Arc<str>,
+    /// Remote of a remote-tracking branch, empty for local branches
+    pub remote: Arc<str>,
+    pub commit: Arc<str>,
+    pub author: Arc<str>,
+    pub date: Arc<str>,
+    pub message: Arc<str>,
+    /// Commit time in unix seconds
+    #[tabled(skip)]
+    pub time: i64,
 }
+
+impl RepoBranchCommit {
+    /// How well the commit matches a search pattern, higher first: a match in the subject
+    /// outweighs one in the body, a whole-word match one inside a word, and newer commits
+    /// get a boost that halves every 90 days
+    pub fn relevance(&self, pattern: &Matcher, now: i64) -> f64 {
+        let (subject, body) = self.message.split_once('\n').unwrap_or((&self.message, ""));
+        let score = |text: &str, weight: f64| {
+            let ranges = pattern.match_ranges(text);
+            if ranges.is_empty() {
+                return 0.0;
+            }
+            let whole_word = ranges.iter().any(|&(start, end)| {
+                let boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric() && c != '_');
+                boundary(text[..start].chars().next_back()) && boundary(text[end..].chars().next())
+            });
+            weight * if whole_word { 1.5 } else { 1.0 }
+        };
+        let age_days = (now - self.time).max(0) as f64 / 86400.0;
+        score(subject, 10.0) + score(body, 3.0) + 5.0 * 0.5_f64.powf(age_days / 90.0)
+    }
+}
+
 #[derive(Tabled, Clone, PartialOrd, PartialEq, Ord, Eq)]
 pub struct BranchInfo {
-    pub repo: Rc<str>,
-    pub branch: Rc<str>,
+    pub repo: Arc<str>,
+    pub branch: Arc<str>,
+    pub remote: Arc<str>,
 }
 
-#[derive(Clone)]
-pub struct BranchInfoList {
-    pub repo: Rc<str>,
-    pub collection: Rc<[BranchInfo]>
+#[derive(Tabled, Clone)]
+pub struct CurrentBranchInfo {
+    pub repo: Arc<str>,
+    pub branch: Arc<str>,
+    /// Commits on the branch that are not on its upstream, None without an upstream
+    #[tabled(display_with = "display_ahead")]
+    pub ahead: Option<usize>,
+    /// Commits on the upstream that are not on the branch, None without an upstream
+    #[tabled(display_with = "display_behind")]
+    pub behind: Option<usize>,
+    /// Operation in progress (merge, rebase, ...) that needs attention, empty when clean
+    pub state: Arc<str>,
+}
+
+fn display_ahead(count: &Option<usize>) -> String {
+    display_count('↑', count)
+}
+
+fn display_behind(count: &Option<usize>) -> String {
+    display_count('↓', count)
 }
-impl BranchInfoList {
-    pub fn branch_names(&self) -> Vec<String> {
-        self.collection.iter().map(|bi| bi.branch.clone().to_string()).collect()
+
+/// `↑2` for a count, blank for none and `-` when there is no upstream to count against
+fn display_count(arrow: char, count: &Option<usize>) -> String {
+    match count {
+        None => "-".to_string(),
+        Some(0) => String::new(),
+        Some(count) => format!("{}{}", arrow, count),
     }
 }
 
-struct GitRepo {
-    config: ConfigFile,
-    repo_name: Rc<str>
+#[derive(Tabled, Clone)]
+pub struct ConflictInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Conflicted Path")]
+    pub path: Arc<str>,
 }
 
-#[derive(Debug)]
-pub enum RepoError {
-    OpenFailure(String),
+#[derive(Tabled, Clone)]
+pub struct DivergenceInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
+    #[tabled(rename = "Upstream")]
+    pub upstream: Arc<str>,
+    #[tabled(rename = "Ahead")]
+    pub ahead: Arc<str>,
+    #[tabled(rename = "Behind")]
+    pub behind: Arc<str>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+    /// Sort key: diverged first, then behind, ahead, up to date and no upstream
+    #[tabled(skip)]
+    pub priority: (u8, Reverse<usize>),
 }
-impl fmt::Display for RepoError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            RepoError::OpenFailure(repo) => write!(f, "Could not open git repo at {}", repo),
-        }
+
+#[derive(Tabled, Clone)]
+pub struct BranchSwitch {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "From")]
+    pub from: Arc<str>,
+    #[tabled(rename = "To")]
+    pub to: Arc<str>,
+    #[tabled(rename = "Action")]
+    pub action: Arc<str>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct OwnerInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Committer")]
+    pub author: Arc<str>,
+    #[tabled(rename = "Commits")]
+    pub commits: usize,
+}
+
+#[derive(Tabled, Clone)]
+pub struct LintViolation {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Commit")]
+    pub commit: Arc<str>,
+    #[tabled(rename = "Rule")]
+    pub rule: Arc<str>,
+    #[tabled(rename = "Detail")]
+    pub detail: Arc<str>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct HookInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Hook")]
+    pub hook: Arc<str>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct ConfigKeyInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Value")]
+    pub value: Arc<str>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct ConfigValueInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Current")]
+    pub current: Arc<str>,
+    #[tabled(rename = "New")]
+    pub new: Arc<str>,
+    #[tabled(rename = "Action")]
+    pub action: Arc<str>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct IdentityInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Name")]
+    pub name: Arc<str>,
+    #[tabled(rename = "Email")]
+    pub email: Arc<str>,
+    #[tabled(rename = "Set In")]
+    pub level: Arc<str>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+/// Object stores a repo borrows through alternates and watched repos borrowing from it
+#[derive(Tabled, Clone)]
+pub struct AlternatesInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Borrows From", display_with = "join_names")]
+    pub borrows_from: Vec<String>,
+    #[tabled(rename = "Shared With", display_with = "join_names")]
+    pub shared_with: Vec<String>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct UpstreamSetupInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
+    #[tabled(rename = "Upstream")]
+    pub upstream: Arc<str>,
+    #[tabled(rename = "Action")]
+    pub action: Arc<str>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct FetchInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Remote")]
+    pub remote: Arc<str>,
+    #[tabled(rename = "New Refs", display_with = "join_names")]
+    pub new_refs: Vec<String>,
+    #[tabled(rename = "Updated")]
+    pub updated: usize,
+    #[tabled(rename = "Pruned", display_with = "join_names")]
+    pub pruned: Vec<String>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+/// Result of fast-forwarding a repo's current branch, for `repo pull`
+#[derive(Tabled, Clone)]
+pub struct PullInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
+    #[tabled(rename = "Upstream")]
+    pub upstream: Arc<str>,
+    /// Commits the branch moved forward by
+    #[tabled(rename = "Commits")]
+    pub commits: usize,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+/// Shared files whose copy in a repo differs from the source, for `sync files`
+#[derive(Tabled, Clone)]
+pub struct SyncInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Out of Sync", display_with = "join_names")]
+    pub out_of_sync: Vec<String>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+/// Inventory details of a repo for `repo info` and `stats age`
+#[derive(Tabled, Clone)]
+pub struct RepoInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "First Commit")]
+    pub first_commit: Arc<str>,
+    #[tabled(rename = "Age")]
+    pub age: Arc<str>,
+    #[tabled(rename = "Commits")]
+    pub commits: usize,
+    #[tabled(rename = "Birth Author")]
+    pub birth_author: Arc<str>,
+    /// Unix time of the first commit, for ordering by age
+    #[tabled(skip)]
+    pub born: Option<i64>,
+}
+
+/// What `repo add` found for one of the names it was given. The checks are None when
+/// they were skipped with --fast.
+#[derive(Tabled, Clone)]
+pub struct AddCandidate {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Directory", display_with = "display_check")]
+    pub exists: Option<bool>,
+    #[tabled(rename = "Git Repo", display_with = "display_check")]
+    pub git_repo: Option<bool>,
+    #[tabled(rename = "Bare", display_with = "display_check")]
+    pub bare: Option<bool>,
+    #[tabled(rename = "Watched", display_with = "display_check")]
+    pub watched: Option<bool>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+fn display_check(check: &Option<bool>) -> String {
+    match check {
+        None => "-".to_string(),
+        Some(true) => "yes".to_string(),
+        Some(false) => "no".to_string(),
     }
 }
 
-#[derive(Debug)]
-pub enum GrepoError {
-    Repo(RepoError),
-    Branch(BranchError),
-    Commit(CommitError),
+/// A repo without recent commits on any branch, for `repo stale`
+#[derive(Tabled, Clone)]
+pub struct StaleInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Last Commit")]
+    pub last_commit: Arc<str>,
+    #[tabled(rename = "Age")]
+    pub age: Arc<str>,
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
 }
-impl fmt::Display for GrepoError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            GrepoError::Repo(ref error) => write!(f, "Repo failed: {}", error),
-            GrepoError::Branch(ref error) => write!(f, "Branch error: {}", error),
-            GrepoError::Commit(ref error) => write!(f, "Commit error: {}", error),
-        }
+
+/// A tag in a watched repo, for `tag list` and `search tag`
+#[derive(Tabled, Clone)]
+pub struct TagInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Tag")]
+    pub tag: Arc<str>,
+    /// Commit the tag points to, through any annotated tag objects
+    #[tabled(rename = "Commit")]
+    pub commit: Arc<str>,
+    /// Empty for lightweight tags
+    #[tabled(rename = "Tagger")]
+    pub tagger: Arc<str>,
+    /// When the tag was made, or the commit date for lightweight tags
+    #[tabled(rename = "Date")]
+    pub date: Arc<str>,
+}
+
+/// A stash entry in a watched repo, for `stash list`
+#[derive(Tabled, Clone)]
+pub struct StashInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    /// N in stash@{N}
+    #[tabled(rename = "Index")]
+    pub index: usize,
+    /// Branch the stash was made on, from its message
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
+    #[tabled(rename = "Message")]
+    pub message: Arc<str>,
+    #[tabled(rename = "Date")]
+    pub date: Arc<str>,
+}
+
+/// Working tree state of a repo for `repo status`
+#[derive(Tabled, Clone)]
+pub struct RepoStatusInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Staged")]
+    pub staged: usize,
+    #[tabled(rename = "Modified")]
+    pub modified: usize,
+    #[tabled(rename = "Untracked")]
+    pub untracked: usize,
+    #[tabled(rename = "In Progress")]
+    pub operation: Arc<str>,
+    #[tabled(rename = "State")]
+    pub state: Arc<str>,
+}
+
+#[derive(Tabled, Clone)]
+pub struct ApplyInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+/// Files a `sed` run changes in a repo, with a unified diff of the changes for the preview
+#[derive(Tabled, Clone)]
+pub struct SedInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Files")]
+    pub files: usize,
+    #[tabled(rename = "Replacements")]
+    pub replacements: usize,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+    #[tabled(skip)]
+    pub diff: String,
+}
+
+/// What a `sed` run does with the replacements it finds
+pub enum SedMode<'a> {
+    Preview,
+    Write,
+    /// Write the files and commit them, on a new branch when one is given
+    Commit { message: &'a str, branch: Option<&'a str> },
+}
+
+/// Size of a branch's changes since it left the default branch
+#[derive(Tabled, Clone)]
+pub struct DiffstatInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Against")]
+    pub base: Arc<str>,
+    #[tabled(rename = "Files")]
+    pub files: usize,
+    #[tabled(rename = "Insertions")]
+    pub insertions: usize,
+    #[tabled(rename = "Deletions")]
+    pub deletions: usize,
+}
+
+/// A local branch that a protected_branches pattern covers
+#[derive(Tabled, Clone)]
+pub struct ProtectedBranchInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
+    #[tabled(rename = "Pattern")]
+    pub pattern: Arc<str>,
+}
+
+/// A local branch whose configured upstream no longer exists
+#[derive(Tabled, Clone)]
+pub struct GoneBranchInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
+    #[tabled(rename = "Upstream")]
+    pub upstream: Arc<str>,
+    #[tabled(rename = "Action")]
+    pub action: Arc<str>,
+}
+
+/// A local branch with commits that are not on any remote-tracking branch
+pub struct UnpushedBranch {
+    pub branch: Arc<str>,
+    pub commits: usize,
+}
+
+
+/// Who last touched a branch and who most likely owns it
+#[derive(Tabled, Clone)]
+pub struct BranchOwner {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Branch")]
+    pub branch: Arc<str>,
+    #[tabled(rename = "Remote")]
+    pub remote: Arc<str>,
+    #[tabled(rename = "Tip Author")]
+    pub tip_author: Arc<str>,
+    #[tabled(skip)]
+    pub tip_email: Arc<str>,
+    #[tabled(rename = "Owner")]
+    pub owner: Arc<str>,
+    /// branch.<name>.description, local branches only
+    #[tabled(rename = "Description")]
+    pub description: Arc<str>,
+}
+
+impl BranchOwner {
+    /// Whether the tip author's name or email contains `author`, which must be lowercase
+    pub fn tip_authored_by(&self, author: &str) -> bool {
+        self.tip_author.to_lowercase().contains(author) || self.tip_email.to_lowercase().contains(author)
     }
 }
 
-#[derive(Debug)]
-pub enum BranchError {
-    NameError(String, String),
+pub struct BranchOwnerList {
+    pub repo: Arc<str>,
+    pub owners: Vec<BranchOwner>,
 }
-impl fmt::Display for BranchError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            BranchError::NameError(error, repo) => write!(f, "Could not open branch in repo {}: {}", repo, error),
-        }
+
+impl BranchOwnerList {
+    /// The repo's branches on one row, remote-tracking ones as remote/name
+    pub fn compact(&self) -> RepoBranches {
+        let branches = self.owners.iter()
+            .map(|owner| if owner.remote.is_empty() { owner.branch.to_string() } else { format!("{}/{}", owner.remote, owner.branch) })
+            .collect::<Vec<_>>();
+        RepoBranches { repo: self.repo.clone(), count: branches.len(), branches }
+    }
+}
+
+/// One row per repo for `branch list --compact`
+#[derive(Tabled, Clone)]
+pub struct RepoBranches {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Count")]
+    pub count: usize,
+    #[tabled(rename = "Branches", display_with = "join_names")]
+    pub branches: Vec<String>,
+}
+
+#[derive(Tabled)]
+pub struct CommitGraphInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Commit Graph")]
+    pub status: Arc<str>,
+}
+
+#[derive(Tabled)]
+pub struct MaintenanceInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Before")]
+    pub before: Arc<str>,
+    #[tabled(rename = "After")]
+    pub after: Arc<str>,
+    #[tabled(rename = "Status")]
+    pub status: Arc<str>,
+}
+
+/// One author's activity in one repo over a digest window
+#[derive(Tabled, Clone)]
+pub struct DigestEntry {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Author")]
+    pub author: Arc<str>,
+    #[tabled(rename = "Commits")]
+    pub commits: usize,
+    #[tabled(rename = "Merged", display_with = "join_names")]
+    pub merged: Vec<String>,
+    #[tabled(rename = "New Branches", display_with = "join_names")]
+    pub created: Vec<String>,
+}
+
+/// A line of a file at some ref matching a content search
+#[derive(Tabled, Clone)]
+pub struct ContentMatch {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Path")]
+    pub path: Arc<str>,
+    #[tabled(rename = "Line")]
+    pub line: usize,
+    #[tabled(rename = "Text")]
+    pub text: Arc<str>,
+    /// The matching line with the lines around it asked for by `ContentQuery::context`
+    #[tabled(skip)]
+    pub context: Vec<String>,
+    /// Line number of the first context line
+    #[tabled(skip)]
+    pub context_start: usize,
+}
+
+/// A commit whose diff changed how often the `search diff` string occurs
+#[derive(Tabled, Clone)]
+pub struct DiffMatch {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Commit")]
+    pub commit: Arc<str>,
+    #[tabled(rename = "Author")]
+    pub author: Arc<str>,
+    #[tabled(rename = "Date")]
+    pub date: Arc<str>,
+    #[tabled(rename = "Message")]
+    pub message: Arc<str>,
+    /// Paths whose count changed, with the change, e.g. "src/lib.rs (+1)"
+    #[tabled(rename = "Paths", display_with = "join_names")]
+    pub paths: Vec<String>,
+}
+
+/// Where a `search hash` commit is in one repo
+#[derive(Tabled, Clone)]
+pub struct HashMatch {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Commit")]
+    pub commit: Arc<str>,
+    #[tabled(rename = "Message")]
+    pub message: Arc<str>,
+    /// Branches whose tip is the commit or a descendant of it, remote-tracking ones as remote/name
+    #[tabled(rename = "Contained In", display_with = "join_names")]
+    pub branches: Vec<String>,
+}
+
+/// Branches created and deleted in a repo over a window, estimated from its reflogs
+#[derive(Tabled, Clone)]
+pub struct BranchChurnInfo {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Created")]
+    pub created: usize,
+    #[tabled(rename = "Deleted")]
+    pub deleted: usize,
+    #[tabled(rename = "Net")]
+    pub net: i64,
+    #[tabled(rename = "Deleted Branches", display_with = "join_names")]
+    pub deleted_names: Vec<String>,
+}
+
+/// When a commit was authored, in the author's own timezone, for `stats hours`
+#[derive(Clone)]
+pub struct CommitTime {
+    pub repo: Arc<str>,
+    pub author: Arc<str>,
+    pub seconds: i64,
+    pub offset_minutes: i32,
+}
+
+fn join_names(names: &[String]) -> String {
+    names.join(", ")
+}
+
+/// Where `search commit --export-patches` wrote a repo's patches
+pub struct PatchExport {
+    pub repo: Arc<str>,
+    pub dir: PathBuf,
+    pub patches: usize,
+}
+
+/// A watched repo that could not be processed by a multi-repo command
+#[derive(Tabled, Clone)]
+pub struct RepoFailure {
+    #[tabled(rename = "Repo")]
+    pub repo: Arc<str>,
+    #[tabled(rename = "Reason")]
+    pub reason: Arc<str>,
+}
+
+/// Per-repo results of a multi-repo command along with the repos that failed
+pub struct RepoResults<T> {
+    pub results: Vec<T>,
+    pub failures: Vec<RepoFailure>,
+    /// Repos whose result counted as a match, recorded for --scope-from-last
+    pub matched: Vec<String>,
+}
+
+/// Whether a repo's result counts as a match when recording the scope for --scope-from-last
+pub trait ScopeMatch {
+    fn is_match(&self) -> bool {
+        true
+    }
+}
+impl<T> ScopeMatch for Vec<T> {
+    fn is_match(&self) -> bool {
+        !self.is_empty()
+    }
+}
+impl<T> ScopeMatch for Option<T> {
+    fn is_match(&self) -> bool {
+        self.is_some()
+    }
+}
+impl ScopeMatch for BranchOwnerList {}
+impl ScopeMatch for CurrentBranchInfo {}
+impl ScopeMatch for CommitGraphInfo {}
+impl ScopeMatch for MaintenanceInfo {}
+impl ScopeMatch for ConfigKeyInfo {}
+impl ScopeMatch for ConfigValueInfo {}
+impl ScopeMatch for IdentityInfo {}
+impl ScopeMatch for UpstreamSetupInfo {}
+impl ScopeMatch for FetchInfo {}
+impl ScopeMatch for PullInfo {}
+impl ScopeMatch for BranchSwitch {}
+impl ScopeMatch for AlternatesInfo {}
+impl ScopeMatch for ObjectStores {}
+impl ScopeMatch for RepoGraph {}
+impl ScopeMatch for ApplyInfo {}
+impl ScopeMatch for ContentMatch {}
+impl ScopeMatch for DiffMatch {}
+impl ScopeMatch for HashMatch {}
+impl ScopeMatch for BranchChurnInfo {}
+impl ScopeMatch for RepoInfo {}
+impl ScopeMatch for StaleInfo {}
+impl ScopeMatch for TagInfo {}
+impl ScopeMatch for StashInfo {}
+impl ScopeMatch for PatchExport {}
+impl ScopeMatch for AddCandidate {}
+impl ScopeMatch for SyncInfo {
+    fn is_match(&self) -> bool {
+        !self.out_of_sync.is_empty()
     }
 }
+impl ScopeMatch for RepoStatusInfo {
+    fn is_match(&self) -> bool {
+        &*self.state != "clean"
+    }
+}
+impl ScopeMatch for SedInfo {
+    fn is_match(&self) -> bool {
+        self.files > 0
+    }
+}
+
+/// A repo's own object directory and the ones it borrows from
+struct ObjectStores {
+    repo: Arc<str>,
+    objects_dir: PathBuf,
+    alternates: Vec<PathBuf>,
+}
+
+struct GitRepo {
+    config: ConfigFile,
+    repo_name: Arc<str>,
+    /// Watched repo to open, differs from `repo_name` for virtual repos
+    location: Arc<str>,
+    /// Sub-project path of a virtual repo
+    path_prefix: Option<Arc<str>>,
+}
+
+#[derive(Debug, Error)]
+pub enum RepoError {
+    #[error("Could not open git repo at {path} (check the base directory and repo name): {}", .source.message())]
+    OpenFailure { path: String, source: git2::Error },
+    #[error("Could not read HEAD of repo {repo}: {}", .source.message())]
+    HeadFailure { repo: String, source: git2::Error },
+    #[error("Could not read git config of repo {repo}: {}", .source.message())]
+    ConfigUnreadable { repo: String, source: git2::Error },
+    #[error("Could not set {key} in repo {repo}: {}", .source.message())]
+    ConfigWriteFailure { repo: String, key: String, source: git2::Error },
+    #[error("Could not fetch {remote} in repo {repo}: {}", .source.message())]
+    FetchFailure { repo: String, remote: String, source: git2::Error },
+    #[error("Could not write {path} in repo {repo}: {source}")]
+    FileWriteFailure { repo: String, path: String, source: std::io::Error },
+    #[error("Invalid path glob {pathspec}: {}", .source.message())]
+    PathspecInvalid { pathspec: String, source: git2::Error },
+    #[error("Could not preview the changes to {path} in repo {repo}: {}", .source.message())]
+    PreviewFailure { repo: String, path: String, source: git2::Error },
+    #[error("Could not read the index of repo {repo}: {}", .source.message())]
+    IndexUnreadable { repo: String, source: git2::Error },
+    #[error("Could not read working tree status of repo {repo}: {}", .source.message())]
+    StatusUnreadable { repo: String, source: git2::Error },
+    #[error("Could not read the reflog of repo {repo}: {}", .source.message())]
+    ReflogUnreadable { repo: String, source: git2::Error },
+    #[error("Could not list tags in repo {repo}: {}", .source.message())]
+    TagListFailure { repo: String, source: git2::Error },
+    #[error("Could not list stashes in repo {repo}: {}", .source.message())]
+    StashListFailure { repo: String, source: git2::Error },
+}
+
+#[derive(Debug, Error)]
+pub enum GrepoError {
+    #[error("Repo failed: {0}")]
+    Repo(#[from] RepoError),
+    #[error("Branch error: {0}")]
+    Branch(#[from] BranchError),
+    #[error("Commit error: {0}")]
+    Commit(#[from] CommitError),
+    #[error("Git command error: {0}")]
+    Cli(#[from] CliError),
+    #[error("Skipped: {0}")]
+    Guard(#[from] GuardError),
+    #[error("Hook error: {0}")]
+    Hook(#[from] HookError),
+}
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
+pub enum BranchError {
+    #[error("Could not list branches in repo {repo}: {}", .source.message())]
+    ListFailure { repo: String, source: git2::Error },
+    #[error("Could not read branch name in repo {repo}: {}", .source.message())]
+    NameError { repo: String, source: git2::Error },
+    #[error("Could not resolve branch {branch} in repo {repo} to a commit: {}", .source.message())]
+    PeelFailure { repo: String, branch: String, source: git2::Error },
+    #[error("Branch {branch} does not exist in repo {repo}")]
+    NotFound { repo: String, branch: String },
+    #[error("Could not check out branch {branch} in repo {repo}: {}", .source.message())]
+    CheckoutFailure { repo: String, branch: String, source: git2::Error },
+    #[error("Could not set upstream of branch {branch} in repo {repo}: {}", .source.message())]
+    UpstreamWriteFailure { repo: String, branch: String, source: git2::Error },
+    #[error("Could not read upstream of branch {branch} in repo {repo}: {}", .source.message())]
+    UpstreamUnreadable { repo: String, branch: String, source: git2::Error },
+    #[error("Branch {branch} in repo {repo} has diverged from {upstream} and cannot be fast-forwarded")]
+    NotFastForward { repo: String, branch: String, upstream: String },
+    #[error("Could not fast-forward branch {branch} in repo {repo}: {}", .source.message())]
+    FastForwardFailure { repo: String, branch: String, source: git2::Error },
+    #[error("Could not create branch {branch} in repo {repo}: {}", .source.message())]
+    CreateFailure { repo: String, branch: String, source: git2::Error },
+    #[error("Could not delete branch {branch} in repo {repo}: {}", .source.message())]
+    DeleteFailure { repo: String, branch: String, source: git2::Error },
+    #[error("Filter script failed on branch {branch} in repo {repo}: {message}")]
+    ScriptFailure { repo: String, branch: String, message: String },
+}
+
+#[derive(Debug, Error)]
 pub enum CommitError {
-    RevWalkFailure(String)
+    #[error("Commit search failed on branch {branch} in repo {repo}: {}", .source.message())]
+    RevWalkFailure { repo: String, branch: String, source: git2::Error },
+    #[error("Could not compare branch {branch} with its upstream in repo {repo}: {}", .source.message())]
+    AheadBehind { repo: String, branch: String, source: git2::Error },
+    #[error("Could not diff commit {commit} in repo {repo}: {}", .source.message())]
+    DiffFailure { repo: String, commit: String, source: git2::Error },
+    #[error("Could not create a patch for commit {commit} in repo {repo}: {}", .source.message())]
+    PatchFailure { repo: String, commit: String, source: git2::Error },
+    #[error("Could not write patch {path}: {source}")]
+    PatchWriteFailure { path: String, source: std::io::Error },
+    #[error("Could not read the files at {reference} in repo {repo}: {}", .source.message())]
+    TreeWalkFailure { repo: String, reference: String, source: git2::Error },
+    #[error("Could not read {path} in repo {repo}: {}", .source.message())]
+    BlobUnreadable { repo: String, path: String, source: git2::Error },
+    #[error("Could not read patch: {}", .source.message())]
+    PatchUnparseable { source: git2::Error },
+    #[error("Could not write the changes to repo {repo}: {}", .source.message())]
+    WriteFailure { repo: String, source: git2::Error },
+    #[error("Filter script failed on commit {commit} in repo {repo}: {message}")]
+    ScriptFailure { repo: String, commit: String, message: String },
+    #[error("Could not look up commit {hash} in repo {repo}: {}", .source.message())]
+    HashLookupFailure { repo: String, hash: String, source: git2::Error },
+}
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("'git {command}' requires the git binary, which was not found on PATH")]
+    Unavailable { command: String },
+    #[error("Could not run 'git {command}' in {path}: {source}")]
+    SpawnFailure { command: String, path: String, source: std::io::Error },
+    #[error("'git {command}' failed in {path}: {stderr}")]
+    CommandFailure { command: String, path: String, stderr: String },
+}
+
+#[derive(Debug, Error)]
+pub enum GuardError {
+    #[error("repo {repo} has a {operation} in progress (finish or abort it first, or pass --force)")]
+    InProgress { repo: String, operation: String },
+    #[error("repo {repo} has uncommitted changes to {count} tracked file(s) (commit or stash them first, or pass --force)")]
+    Dirty { repo: String, count: usize },
+    #[error("Could not read working tree status of repo {repo}: {}", .source.message())]
+    StatusFailure { repo: String, source: git2::Error },
+}
+
+/// Archive formats supported by `git archive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
 }
-impl fmt::Display for CommitError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl ArchiveFormat {
+    fn extension(&self) -> &'static str {
         match self {
-            CommitError::RevWalkFailure(error) => write!(f, "Commit search failed: {}", error),
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Zip => "zip",
         }
     }
 }
+
+/// Which implementation carries out a git operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Libgit2,
+    Cli,
+}
+
 impl GitRepo {
     /// Create new GitRepo
     fn new(config: ConfigFile, repo_name: String) -> GitRepo {
+        let virtual_repo = config.virtual_repos.iter().find(|v| v.name == repo_name).cloned();
+        let (location, path_prefix) = match virtual_repo {
+            Some(v) => (Arc::from(v.repo), Some(Arc::from(v.path.trim_matches('/')))),
+            None => (Arc::from(repo_name.as_str()), None),
+        };
         Self {
             config,
-            repo_name: Rc::from(repo_name),
+            repo_name: Arc::from(repo_name),
+            location,
+            path_prefix,
         }
     }
     /// Open a Git repository and return object
     fn open(&mut self) -> Result<Repository, GrepoError> {
-        let repo_path_str = format!("{}/{}", self.config.base_path, self.repo_name.clone());
-        let repo_path = PathBuf::from(repo_path_str.clone());
-        match Repository::open(repo_path) {
-            Ok(repo) => Ok(repo),
-            Err(_) => Err(GrepoError::Repo(RepoError::OpenFailure(repo_path_str))),
-        }
+        // absolute names (ad-hoc --path repos) replace the base path when joined
+        let repo_path = Path::new(&*self.config.base_path).join(&*self.location);
+        let repo_path_str = repo_path.display().to_string();
+        Repository::open(repo_path)
+            .map_err(|source| RepoError::OpenFailure { path: repo_path_str, source }.into())
     }
     /// Get all local branches
-    fn all_branches(&mut self) -> BranchInfoList {
+    fn all_branches(&mut self, scope: BranchScope) -> Result<Vec<BranchInfo>, GrepoError> {
+        let repo = self.open()?;
+        let mut collection = Vec::new();
+        for scoped in scoped_branches(&repo, &self.repo_name, scope)? {
+            collection.push(BranchInfo {
+                repo: self.repo_name.clone(),
+                branch: scoped.name.into(),
+                remote: scoped.remote.into(),
+            });
+        }
 
-        BranchInfoList { 
-            repo: self.repo_name.clone(),
-            collection: self.open()
-                .expect("Failed to open git repo")
-                .branches(Some(git2::BranchType::Local))
-                .unwrap()
-                .map(|b| {
-                    let (branch, _) = b.expect("Expected branch error");
-                    let branch_name = branch.name().unwrap().unwrap().to_owned();
-
-                    BranchInfo {
-                        repo: self.repo_name.clone(),
-                        branch: branch_name.into(),
-                    }
+        Ok(collection)
+    }
+    /// Local branches with their tip author and inferred owner: whoever authored most of
+    /// the branch's commits that are not on HEAD, or the tip author when there are none
+    fn branch_owners(&mut self, scope: BranchScope) -> Result<BranchOwnerList, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let git_config = repo.config().map_err(|source| RepoError::ConfigUnreadable { repo: repo_name.clone(), source })?;
+        let mut owners = Vec::new();
+        for ScopedBranch { remote, name: branch_name, branch } in scoped_branches(&repo, &repo_name, scope)? {
+            let description = if remote.is_empty() {
+                git_config.get_string(&format!("branch.{}.description", branch_name)).unwrap_or_default()
+            } else {
+                String::new()
+            };
+            let tip = branch.get().peel_to_commit()
+                .map_err(|source| BranchError::PeelFailure { repo: repo_name.clone(), branch: branch_name.clone(), source })?;
+            let tip_author = tip.author().name().unwrap_or("unknown").to_string();
+            let tip_email = tip.author().email().unwrap_or("").to_string();
 
-                })
-                .collect()
+            let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: branch_name.clone(), source };
+            let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+            revwalk.push(tip.id()).map_err(revwalk_error)?;
+            revwalk.hide_head().map_err(revwalk_error)?;
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for commit in revwalk.filter_map(|oid| oid.ok()).filter_map(|oid| repo.find_commit(oid).ok()) {
+                *counts.entry(commit.author().name().unwrap_or("unknown").to_string()).or_default() += 1;
+            }
+            let owner = counts
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                .map(|(author, _)| author)
+                .unwrap_or_else(|| tip_author.clone());
+
+            owners.push(BranchOwner {
+                repo: self.repo_name.clone(),
+                branch: branch_name.into(),
+                remote: remote.into(),
+                tip_author: tip_author.into(),
+                tip_email: tip_email.into(),
+                owner: owner.into(),
+                description: description.trim().into(),
+            });
+        }
+        owners.sort_by(|a, b| a.remote.cmp(&b.remote).then_with(|| a.branch.cmp(&b.branch)));
+        Ok(BranchOwnerList { repo: self.repo_name.clone(), owners })
+    }
+    /// Pick the backend for an operation that both libgit2 and the git binary support
+    fn backend(&self) -> Backend {
+        if self.config.prefer_cli && git_cli_available() {
+            Backend::Cli
+        } else {
+            Backend::Libgit2
+        }
+    }
+    /// Run a git binary command in this repo, for operations libgit2 lacks
+    fn git_cli(&mut self, args: &[&str]) -> Result<String, GrepoError> {
+        let repo = self.open()?;
+        if !git_cli_available() {
+            return Err(CliError::Unavailable { command: args.join(" ") }.into());
         }
+        run_git(repo.path(), args)
     }
     /// Get current checked out branch for repo
-    fn current_branch_name(&mut self) -> String {
-        let repo = self.open().expect("Failed to open git repo");
+    fn current_branch_name(&mut self) -> Result<String, GrepoError> {
+        if self.backend() == Backend::Cli {
+            if let Ok(name) = self.git_cli(&["symbolic-ref", "--short", "-q", "HEAD"]) {
+                return Ok(name.trim().to_string());
+            }
+        }
+        let repo = self.open()?;
         let head = match repo.head() {
             Ok(head) => Some(head),
             Err(ref e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => {
                 None
             }
-            Err(e) => panic!("Error occurred: {}", e) //return Err(e),
+            Err(source) => return Err(RepoError::HeadFailure { repo: self.repo_name.to_string(), source }.into()),
         };
         let head = head.as_ref().and_then(|h| h.shorthand());
-        head.unwrap_or("** Not currently on any branch **").to_string()
+        Ok(head.unwrap_or("** Not currently on any branch **").to_string())
     }
-}
-
-
-pub fn get_repo_branch_names(cfg: ConfigFile) -> Vec<BranchInfoList> {
-    cfg.repos
-        .clone()
-        .into_iter()
-        .map(|repo| {
-            GitRepo::new(cfg.clone(), repo).all_branches()
-        })
-        .collect()
-}
-
-pub fn search_repos(cfg: ConfigFile, name: String) -> HashMap<String, Vec<BranchInfo>> {
-    cfg.clone().repos.into_iter().filter_map(|repo| {
-        let branches = GitRepo::new(cfg.clone(), repo.clone()).all_branches();
-        let filtered_branches: Vec<BranchInfo> = branches.collection.iter()
-            .filter_map(|binfo| {
-                if binfo.branch.contains(&name) {
-                    Some(binfo.clone())
-                } else {
-                    None
-                }
-            })
-            .collect();
-        if !filtered_branches.is_empty() {
-            Some((repo, filtered_branches))
+    /// Commits ahead of and behind the current branch's upstream, None when HEAD is
+    /// detached or the branch has no upstream
+    fn upstream_counts(&mut self) -> Result<Option<(usize, usize)>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let head = match repo.head() {
+            Ok(head) if head.is_branch() => head,
+            _ => return Ok(None),
+        };
+        let branch_name = head.shorthand().unwrap_or_default().to_string();
+        let local = head.target();
+        let upstream = match git2::Branch::wrap(head).upstream() {
+            Ok(upstream) => upstream.get().target(),
+            Err(ref e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(source) => return Err(BranchError::UpstreamUnreadable { repo: repo_name, branch: branch_name, source }.into()),
+        };
+        let (Some(local), Some(upstream)) = (local, upstream) else { return Ok(None) };
+        let counts = repo.graph_ahead_behind(local, upstream)
+            .map_err(|source| CommitError::AheadBehind { repo: repo_name, branch: branch_name, source })?;
+        Ok(Some(counts))
+    }
+    /// Describe an in-progress operation (e.g. "rebase in progress"), empty when clean
+    fn state_label(&mut self) -> Result<String, GrepoError> {
+        let repo = self.open()?;
+        Ok(in_progress_operation(repo.state())
+            .map(|operation| format!("{} in progress", operation))
+            .unwrap_or_default())
+    }
+    /// Check out an existing local branch, refusing to overwrite local changes
+    fn checkout_branch(&mut self, name: &str) -> Result<(), GrepoError> {
+        let repo = self.open()?;
+        let checkout_error = |source| BranchError::CheckoutFailure { repo: self.repo_name.to_string(), branch: name.to_string(), source };
+        let branch = repo.find_branch(name, git2::BranchType::Local)
+            .map_err(|_| BranchError::NotFound { repo: self.repo_name.to_string(), branch: name.to_string() })?;
+        let reference = branch.into_reference();
+        let commit = reference.peel_to_commit().map_err(checkout_error)?;
+        repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe())).map_err(checkout_error)?;
+        repo.set_head(reference.name().unwrap_or_default()).map_err(checkout_error)?;
+        Ok(())
+    }
+    /// Switch to a local branch, creating it from a same-named origin branch or, with
+    /// `create`, from the default branch when the repo has neither
+    fn checkout(&mut self, name: &str, create: bool) -> Result<BranchSwitch, GrepoError> {
+        let from = self.current_branch_name()?;
+        let repo_label = self.repo_name.clone();
+        let switch = |action: &str| BranchSwitch {
+            repo: repo_label.clone(),
+            from: Arc::from(from.as_str()),
+            to: Arc::from(name),
+            action: Arc::from(action),
+        };
+        if from == name {
+            return Ok(switch("already on it"));
+        }
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let create_error = |source| BranchError::CreateFailure { repo: repo_name.clone(), branch: name.to_string(), source };
+        let action = if repo.find_branch(name, git2::BranchType::Local).is_ok() {
+            "switched".to_string()
+        } else if let Ok(remote) = repo.find_branch(&format!("origin/{}", name), git2::BranchType::Remote) {
+            let commit = remote.get().peel_to_commit().map_err(create_error)?;
+            let mut branch = repo.branch(name, &commit, false).map_err(create_error)?;
+            branch.set_upstream(Some(&format!("origin/{}", name)))
+                .map_err(|source| BranchError::UpstreamWriteFailure { repo: repo_name.clone(), branch: name.to_string(), source })?;
+            format!("switched, tracking origin/{}", name)
+        } else if create {
+            let Some((default, oid)) = default_branch(&repo) else {
+                return Ok(switch("no default branch to create from"));
+            };
+            let commit = repo.find_commit(oid).map_err(create_error)?;
+            repo.branch(name, &commit, false).map_err(create_error)?;
+            format!("created from {}", default)
         } else {
-            None
+            return Ok(switch("not found"));
+        };
+        drop(repo);
+        self.checkout_branch(name)?;
+        Ok(switch(&action))
+    }
+    /// Plan switching this repo from branch `from` to `to`; repos not on `from` are left out
+    fn plan_switch(&mut self, from: &str, to: &str) -> Result<Option<BranchSwitch>, GrepoError> {
+        if self.current_branch_name()? != from {
+            return Ok(None);
         }
-    }).collect::<HashMap<String, Vec<BranchInfo>>>()
-}
-
-pub fn get_current_branch_name(cfg: ConfigFile) -> Vec<BranchInfo> {
-    cfg.repos
-        .clone()
-        .into_iter()
-        .map(|repo| BranchInfo { 
-            repo: repo.clone().into(), 
-            branch: Rc::from(GitRepo::new(cfg.clone(), repo).current_branch_name())
-        } )
-        .collect::<Vec<BranchInfo>>()
-}
-
-pub fn get_valid_repo(cfg: ConfigFile, repo_name: String) -> bool {
-    match GitRepo::new(cfg, repo_name).open() {
-        Ok(_) => { true }
-        Err(_) => { false }
+        let repo = self.open()?;
+        if repo.find_branch(to, git2::BranchType::Local).is_err() {
+            return Err(BranchError::NotFound { repo: self.repo_name.to_string(), branch: to.to_string() }.into());
+        }
+        self.guard_mutation()?;
+        Ok(Some(BranchSwitch {
+            repo: self.repo_name.clone(),
+            from: Arc::from(from),
+            to: Arc::from(to),
+            action: Arc::from("switch"),
+        }))
     }
-}
+    /// Write an archive of the tree at `reference` into `output_dir` using `git archive`,
+    /// with every path prefixed by the repo name. `git archive` already leaves out
+    /// export-ignore paths; `skip_vendored` also leaves out linguist-vendored ones.
+    fn archive(&mut self, reference: &str, format: ArchiveFormat, output_dir: &Path, skip_vendored: bool) -> Result<PathBuf, GrepoError> {
+        let safe_ref = reference.replace(['/', '\\', ':'], "-");
+        let output = output_dir.join(format!("{}-{}.{}", self.repo_name, safe_ref, format.extension()));
+        let output_arg = format!("--output={}", output.display());
+        let prefix_arg = format!("--prefix={}/", self.repo_name);
+        let format_arg = format!("--format={}", format.extension());
+        let mut args = vec!["archive", format_arg.as_str(), prefix_arg.as_str(), output_arg.as_str(), reference];
+        if skip_vendored {
+            args.extend(["--", ".", ":(exclude,attr:linguist-vendored)"]);
+        }
+        self.git_cli(&args)?;
+        Ok(output)
+    }
+    /// Count authors of commits on HEAD since `since` that touched paths matching `path_glob`
+    fn recent_owners(&mut self, path_glob: &str, since: i64, top: usize) -> Result<Vec<OwnerInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
 
-pub fn search_commits(cfg: ConfigFile, search_string: String, include_author: bool) -> Result<Vec<RepoBranchCommit>, GrepoError> {
-    let repo_names = cfg.repos.clone();
-    let mut found_commits = Vec::new();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for commit in head_commits_since(&repo, &repo_name, since)? {
+            let oid = commit.id();
+            let diff_error = |source| CommitError::DiffFailure { repo: repo_name.clone(), commit: oid.to_string(), source };
+            let tree = commit.tree().map_err(diff_error)?;
+            let parent_tree = commit.parent(0).ok().and_then(|parent| parent.tree().ok());
+            let diff = repo
+                .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(DiffOptions::new().pathspec(path_glob)))
+                .map_err(diff_error)?;
+            if diff.deltas().len() > 0 {
+                *counts.entry(commit.author().to_string()).or_default() += 1;
+            }
+        }
 
-    for repo_name in repo_names {
-        let mut watchobj = GitRepo::new(cfg.clone(), repo_name.clone());
-        let repo = match watchobj.open() {
-            Ok(r) => { r },
-            Err(_) => { continue },
+        let mut owners = counts.into_iter().collect::<Vec<_>>();
+        owners.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(owners
+            .into_iter()
+            .take(top)
+            .map(|(author, commits)| OwnerInfo {
+                repo: self.repo_name.clone(),
+                author: Arc::from(author),
+                commits,
+            })
+            .collect())
+    }
+    /// Lines matching the pattern in the files of the tree at the query's ref, below the path
+    /// of a virtual repo. Binary files are skipped. Repos without the ref yield nothing.
+    fn search_content(&mut self, query: &ContentQuery) -> Result<Vec<ContentMatch>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let reference = query.reference.as_str();
+        let Ok(tree) = repo.revparse_single(reference).and_then(|object| object.peel_to_tree()) else {
+            return Ok(Vec::new());
         };
-        
-        for branches in repo.branches(Some(git2::BranchType::Local)).unwrap() {
-            let branch = match branches {
-                Ok((b,_)) => { b },
-                Err(_) => { continue },
-            };
-
-            let branch_name = match branch.name() {
-                Ok(n) => { 
-                    match n {
-                        Some(name) => { name.to_string() },
-                        None => { continue },
-                    }},
-                Err(e) => { return Err(GrepoError::Branch(BranchError::NameError(e.to_string(), repo_name)))},
+        let vendored_dirs = if query.include_vendored { &[][..] } else { self.config.vendored_dirs(&repo_name) };
+        let mut found = Vec::new();
+        let mut read_error = None;
+        let walked = tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+            if interrupted() {
+                return git2::TreeWalkResult::Abort;
+            }
+            let name = entry.name().unwrap_or_default();
+            let path = format!("{}{}", root, name);
+            if entry.kind() == Some(ObjectType::Tree) && vendored_dirs.iter().any(|dir| {
+                let dir = dir.trim_end_matches('/');
+                if dir.contains('/') { dir == path } else { dir == name }
+            }) {
+                return git2::TreeWalkResult::Skip;
+            }
+            if let Some(prefix) = self.path_prefix.as_deref() {
+                // keep descending into the directories leading to the prefix
+                if !Path::new(&path).starts_with(prefix) && !Path::new(prefix).starts_with(&path) {
+                    return git2::TreeWalkResult::Skip;
+                }
+            }
+            if entry.kind() != Some(ObjectType::Blob) || self.path_prefix.as_deref().is_some_and(|prefix| !Path::new(&path).starts_with(prefix)) {
+                return git2::TreeWalkResult::Ok;
+            }
+            let blob = match repo.find_blob(entry.id()) {
+                Ok(blob) => blob,
+                Err(source) => {
+                    read_error = Some(CommitError::BlobUnreadable { repo: repo_name.clone(), path, source });
+                    return git2::TreeWalkResult::Abort;
+                }
             };
+            if blob.is_binary() || (query.skip_vendored && is_vendored(&repo, &path)) {
+                return git2::TreeWalkResult::Ok;
+            }
+            let text = String::from_utf8_lossy(blob.content());
+            let lines = text.lines().collect::<Vec<_>>();
+            for (index, line) in lines.iter().enumerate().filter(|(_, line)| query.pattern.is_match(line)) {
+                let context = if query.context == 0 {
+                    Vec::new()
+                } else {
+                    let first = index.saturating_sub(query.context);
+                    lines[first..(index + query.context + 1).min(lines.len())].iter().map(|line| line.to_string()).collect()
+                };
+                found.push(ContentMatch {
+                    repo: self.repo_name.clone(),
+                    path: Arc::from(path.as_str()),
+                    line: index + 1,
+                    text: Arc::from(line.trim()),
+                    context,
+                    context_start: index.saturating_sub(query.context) + 1,
+                });
+            }
+            git2::TreeWalkResult::Ok
+        });
+        // an interrupted walk was aborted on purpose, keep what it found
+        if interrupted() {
+            return Ok(found);
+        }
+        walked.map_err(|source| CommitError::TreeWalkFailure { repo: repo_name.clone(), reference: reference.to_string(), source })?;
+        match read_error {
+            Some(e) => Err(e.into()),
+            None => Ok(found),
+        }
+    }
+    /// Estimate branch creations and deletions since `since`. A local branch counts as
+    /// created when its reflog starts in the window. Deleted branches lose their reflog, so
+    /// they are found as branches HEAD was checked out from in the window that no longer
+    /// exist; branches that were never checked out are missed.
+    fn branch_churn(&mut self, since: i64) -> Result<BranchChurnInfo, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let reflog_error = |source| RepoError::ReflogUnreadable { repo: repo_name.clone(), source };
 
-            let commit_id = branch.into_reference().peel(ObjectType::Commit).expect("peeling branch failed!").id();
-            let mut revwalk = repo.revwalk().map_err(|e| GrepoError::Commit(CommitError::RevWalkFailure(e.to_string())))?;
-            revwalk.push(commit_id).unwrap();
+        let mut created = 0;
+        for scoped in scoped_branches(&repo, &repo_name, BranchScope::Local)? {
+            let Some(refname) = scoped.branch.get().name() else { continue };
+            let reflog = repo.reflog(refname).map_err(reflog_error)?;
+            // entries are newest first, the last one is the branch's creation
+            if reflog.iter().next_back().is_some_and(|entry| entry.committer().when().seconds() >= since) {
+                created += 1;
+            }
+        }
 
-            let commits: Vec<Commit> = revwalk
-                .filter_map(|oid| oid.ok())
-                .filter_map(|oid| {
-                    repo.find_commit(oid).ok()
-                })
-                .collect();
+        let mut gone = Vec::new();
+        for entry in repo.reflog("HEAD").map_err(reflog_error)?.iter() {
+            if entry.committer().when().seconds() < since {
+                break;
+            }
+            let Some(moves) = entry.message().and_then(|message| message.strip_prefix("checkout: moving from ")) else { continue };
+            for name in moves.split(" to ") {
+                let is_commit_id = name.len() >= 7 && name.chars().all(|c| c.is_ascii_hexdigit());
+                if is_commit_id || gone.iter().any(|gone: &String| gone == name)
+                    || repo.find_branch(name, git2::BranchType::Local).is_ok()
+                    || repo.find_branch(name, git2::BranchType::Remote).is_ok()
+                    || repo.revparse_single(name).is_ok()
+                {
+                    continue;
+                }
+                gone.push(name.to_string());
+            }
+        }
+        Ok(BranchChurnInfo {
+            repo: self.repo_name.clone(),
+            created,
+            deleted: gone.len(),
+            net: created as i64 - gone.len() as i64,
+            deleted_names: gone,
+        })
+    }
+    /// Author times of the commits on HEAD since `since`, only those touching the path of
+    /// a virtual repo
+    fn commit_times(&mut self, since: i64) -> Result<Vec<CommitTime>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let commits = head_commits_since(&repo, &repo_name, since)?;
+        let times = commits
+            .iter()
+            .filter(|commit| self.path_prefix.as_deref().is_none_or(|prefix| touches_path(&repo, commit, prefix)))
+            .map(|commit| {
+                let author = commit.author();
+                CommitTime {
+                    repo: self.repo_name.clone(),
+                    author: Arc::from(author.name().unwrap_or("unknown")),
+                    seconds: author.when().seconds(),
+                    offset_minutes: author.when().offset_minutes(),
+                }
+            })
+            .collect();
+        Ok(times)
+    }
+    /// Per-author commit counts, merges into HEAD and new local branches since `since`.
+    /// A branch counts as new when its oldest commit not on HEAD is inside the window.
+    fn digest(&mut self, since: i64) -> Result<Vec<DigestEntry>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let mut entries: HashMap<String, DigestEntry> = HashMap::new();
+        for commit in head_commits_since(&repo, &repo_name, since)? {
+            let author = commit.author();
+            let author = author.name().unwrap_or("unknown");
+            if commit.parent_count() > 1 {
+                let merged = merged_branch_name(commit.summary().unwrap_or(""))
+                    .unwrap_or_else(|| commit.id().to_string()[..7].to_string());
+                digest_entry(&mut entries, &self.repo_name, author).merged.push(merged);
+            } else {
+                digest_entry(&mut entries, &self.repo_name, author).commits += 1;
+            }
+        }
 
+        let head_name = repo.head().ok().and_then(|head| head.shorthand().map(String::from));
+        let branches = repo.branches(Some(git2::BranchType::Local))
+            .map_err(|source| BranchError::ListFailure { repo: repo_name.clone(), source })?;
+        for (branch, _) in branches.filter_map(|b| b.ok()) {
+            let Some(name) = branch.name().ok().flatten().map(String::from) else { continue };
+            if head_name.as_deref() == Some(name.as_str()) {
+                continue;
+            }
+            let Some(tip) = branch.get().target() else { continue };
+            let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: name.clone(), source };
+            let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+            revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE).map_err(revwalk_error)?;
+            revwalk.push(tip).map_err(revwalk_error)?;
+            if repo.head().is_ok() {
+                revwalk.hide_head().map_err(revwalk_error)?;
+            }
+            let first = revwalk.filter_map(|oid| oid.ok()).find_map(|oid| repo.find_commit(oid).ok());
+            if let Some(first) = first.filter(|c| c.time().seconds() >= since) {
+                digest_entry(&mut entries, &self.repo_name, first.author().name().unwrap_or("unknown")).created.push(name);
+            }
+        }
 
-            found_commits.extend(commits.into_iter().filter( |commit| {
-                    let message = commit.message().unwrap_or("");
-                    (include_author && commit.author().to_string().contains(&search_string))
-                        || message.contains(&search_string)
-                })
-                .map(|commit| {
-                    RepoBranchCommit {
-                        repo: Rc::from(repo_name.to_owned()),
-                        branch: Rc::from(branch_name.to_owned()),
-                        message: Rc::from(commit.message().unwrap_or("").trim()),
-                        author: Rc::from(commit.author().to_string()),
-                        commit: Rc::from(commit.id().to_string()),
-                    }
-                })
-                .collect::<Vec<RepoBranchCommit>>());
+        let mut entries = entries.into_values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.author.cmp(&b.author));
+        Ok(entries)
+    }
+    /// Check non-merge commits on HEAD since `since` against the commit lint rules
+    fn lint_commits(&mut self, since: i64) -> Result<Vec<LintViolation>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let mut violations = Vec::new();
+        for commit in head_commits_since(&repo, &repo_name, since)? {
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            let short_id = Arc::from(&commit.id().to_string()[..7]);
+            for violation in lint::lint_message(&self.config.commit_lint, commit.message().unwrap_or("")) {
+                violations.push(LintViolation {
+                    repo: self.repo_name.clone(),
+                    commit: Arc::clone(&short_id),
+                    rule: Arc::from(violation.rule),
+                    detail: Arc::from(violation.detail),
+                });
+            }
         }
+        Ok(violations)
     }
-    Ok(found_commits)
+    /// Effective value of a git config key for this repo, if set at any level
+    fn config_value(&mut self, key: &str) -> Result<Option<String>, GrepoError> {
+        let repo = self.open()?;
+        let config = repo.config()
+            .map_err(|source| RepoError::ConfigUnreadable { repo: self.repo_name.to_string(), source })?;
+        match config.get_string(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(ref e) if e.code() == ErrorCode::NotFound => Ok(None),
+            Err(source) => Err(RepoError::ConfigUnreadable { repo: self.repo_name.to_string(), source }.into()),
+        }
+    }
+    /// Set branch.<name>.description of a local branch, removing it when `text` is empty
+    fn describe_branch(&mut self, branch: &str, text: &str) -> Result<(), GrepoError> {
+        let repo = self.open()?;
+        if repo.find_branch(branch, git2::BranchType::Local).is_err() {
+            return Err(BranchError::NotFound { repo: self.repo_name.to_string(), branch: branch.to_string() }.into());
+        }
+        let key = format!("branch.{}.description", branch);
+        let write_error = |source| RepoError::ConfigWriteFailure { repo: self.repo_name.to_string(), key: key.clone(), source };
+        let mut config = repo.config().and_then(|config| config.open_level(git2::ConfigLevel::Local)).map_err(write_error)?;
+        if !text.is_empty() {
+            return Ok(config.set_str(&key, text).map_err(write_error)?);
+        }
+        match config.remove(&key) {
+            Err(ref e) if e.code() == ErrorCode::NotFound => Ok(()),
+            result => Ok(result.map_err(write_error)?),
+        }
+    }
+    /// Configured user.name/user.email, flagging emails outside the allowed domains
+    fn identity(&mut self, allowed_domains: &[String]) -> Result<IdentityInfo, GrepoError> {
+        let repo = self.open()?;
+        let config = repo.config()
+            .map_err(|source| RepoError::ConfigUnreadable { repo: self.repo_name.to_string(), source })?;
+        let name = config.get_string("user.name").ok();
+        let email = config.get_entry("user.email").ok()
+            .and_then(|entry| entry.value().map(|value| (value.to_string(), entry.level())));
+
+        let status = match (&name, &email) {
+            (_, None) => "user.email not set".to_string(),
+            (None, _) => "user.name not set".to_string(),
+            (_, Some((email, _))) if !allowed_domains.is_empty() => {
+                let domain = email.rsplit_once('@').map(|(_, domain)| domain.to_lowercase()).unwrap_or_default();
+                if allowed_domains.iter().any(|allowed| allowed.to_lowercase() == domain) {
+                    "ok".to_string()
+                } else {
+                    format!("email domain '{}' not allowed", domain)
+                }
+            }
+            _ => "ok".to_string(),
+        };
+        Ok(IdentityInfo {
+            repo: self.repo_name.clone(),
+            name: Arc::from(name.unwrap_or_else(|| "-".to_string())),
+            email: Arc::from(email.as_ref().map(|(email, _)| email.as_str()).unwrap_or("-")),
+            level: Arc::from(email.map(|(_, level)| config_level_name(level)).unwrap_or("-")),
+            status: Arc::from(status),
+        })
+    }
+    /// Set a git config key in the repo's local config
+    fn set_config_value(&mut self, key: &str, value: &str) -> Result<(), GrepoError> {
+        let repo = self.open()?;
+        let write_error = |source| RepoError::ConfigWriteFailure { repo: self.repo_name.to_string(), key: key.to_string(), source };
+        repo.config()
+            .and_then(|config| config.open_level(ConfigLevel::Local))
+            .and_then(|mut local| local.set_str(key, value))
+            .map_err(write_error)?;
+        Ok(())
+    }
+    /// Compare the current value of a key with the value about to be set
+    fn plan_config_value(&mut self, key: &str, value: &str) -> Result<ConfigValueInfo, GrepoError> {
+        let current = self.config_value(key)?;
+        let unchanged = current.as_deref() == Some(value);
+        Ok(ConfigValueInfo {
+            repo: self.repo_name.clone(),
+            current: Arc::from(current.as_deref().map(|v| format!("- {}", v)).unwrap_or_else(|| "- (unset)".to_string())),
+            new: Arc::from(format!("+ {}", value)),
+            action: Arc::from(if unchanged { "unchanged" } else { "change" }),
+        })
+    }
+    /// Hooks directory of the repo, honoring core.hooksPath
+    fn hooks_dir(&mut self) -> Result<PathBuf, GrepoError> {
+        let repo = self.open()?;
+        let base = repo.workdir().unwrap_or(repo.path()).to_path_buf();
+        let hooks_path = repo.config().ok().and_then(|config| config.get_path("core.hooksPath").ok());
+        Ok(match hooks_path {
+            Some(path) => base.join(path),
+            None => repo.path().join("hooks"),
+        })
+    }
+    /// Install each shared hook into this repo
+    fn install_hooks(&mut self, source_hooks: &[PathBuf], symlink: bool) -> Result<Vec<HookInfo>, GrepoError> {
+        let hooks_dir = self.hooks_dir()?;
+        source_hooks
+            .iter()
+            .map(|hook| {
+                Ok(HookInfo {
+                    repo: self.repo_name.clone(),
+                    hook: Arc::from(hook.file_name().unwrap_or_default().to_string_lossy().as_ref()),
+                    status: Arc::from(hooks::install_hook(hook, &hooks_dir, symlink)?),
+                })
+            })
+            .collect()
+    }
+    /// Compare installed hooks in this repo with the shared hooks
+    fn hooks_status(&mut self, source_hooks: &[PathBuf]) -> Result<Vec<HookInfo>, GrepoError> {
+        let hooks_dir = self.hooks_dir()?;
+        Ok(source_hooks
+            .iter()
+            .map(|hook| {
+                let name = hook.file_name().unwrap_or_default();
+                HookInfo {
+                    repo: self.repo_name.clone(),
+                    hook: Arc::from(name.to_string_lossy().as_ref()),
+                    status: Arc::from(hooks::hook_state(hook, &hooks_dir.join(name)).label()),
+                }
+            })
+            .collect())
+    }
+    /// Local branches with commits not reachable from any remote-tracking branch
+    fn unpushed_branches(&mut self) -> Result<Vec<UnpushedBranch>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let list_error = |source| BranchError::ListFailure { repo: repo_name.clone(), source };
+        let mut unpushed = Vec::new();
+        for b in repo.branches(Some(git2::BranchType::Local)).map_err(list_error)? {
+            let (branch, _) = b.map_err(list_error)?;
+            let (Ok(Some(branch_name)), Some(tip)) = (branch.name(), branch.get().target()) else {
+                continue;
+            };
+            let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: branch_name.to_string(), source };
+            let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+            revwalk.push(tip).map_err(revwalk_error)?;
+            revwalk.hide_glob("refs/remotes/*").map_err(revwalk_error)?;
+            let commits = revwalk.count();
+            if commits > 0 {
+                unpushed.push(UnpushedBranch { branch: Arc::from(branch_name), commits });
+            }
+        }
+        Ok(unpushed)
+    }
+    /// Track `<remote>/<branch>` for the current branch when it has no upstream and
+    /// a same-named remote branch exists
+    fn set_missing_upstream(&mut self, remote: &str) -> Result<UpstreamSetupInfo, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let info = |branch: &str, upstream: &str, action: &str| UpstreamSetupInfo {
+            repo: self.repo_name.clone(),
+            branch: Arc::from(branch),
+            upstream: Arc::from(upstream),
+            action: Arc::from(action),
+        };
+        let head = match repo.head() {
+            Ok(head) if head.is_branch() => head,
+            _ => return Ok(info("-", "-", "not on a branch")),
+        };
+        let branch_name = head.shorthand().unwrap_or_default().to_string();
+        let mut branch = git2::Branch::wrap(head);
+        match branch.upstream() {
+            Ok(upstream) => {
+                let upstream_name = upstream.name().ok().flatten().unwrap_or_default().to_string();
+                return Ok(info(&branch_name, &upstream_name, "already tracking"));
+            }
+            Err(ref e) if e.code() == ErrorCode::NotFound => {}
+            Err(source) => return Err(BranchError::UpstreamUnreadable { repo: repo_name, branch: branch_name, source }.into()),
+        }
+        let upstream_name = format!("{}/{}", remote, branch_name);
+        if repo.find_branch(&upstream_name, git2::BranchType::Remote).is_err() {
+            return Ok(info(&branch_name, "-", &format!("no {} on remote", upstream_name)));
+        }
+        branch.set_upstream(Some(&upstream_name))
+            .map_err(|source| BranchError::UpstreamWriteFailure { repo: repo_name, branch: branch_name.clone(), source })?;
+        Ok(info(&branch_name, &upstream_name, "set"))
+    }
+    /// Date and author of the first commit on HEAD and the number of commits, counting
+    /// only commits touching the path of a virtual repo
+    fn info(&mut self) -> Result<RepoInfo, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let mut info = RepoInfo {
+            repo: self.repo_name.clone(),
+            first_commit: Arc::from("-"),
+            age: Arc::from("-"),
+            commits: 0,
+            birth_author: Arc::from("-"),
+            born: None,
+        };
+        if repo.head().is_err() {
+            return Ok(info);
+        }
+        let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: "HEAD".to_string(), source };
+        let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE).map_err(revwalk_error)?;
+        revwalk.push_head().map_err(revwalk_error)?;
+
+        let date_format = self.config.date_format();
+        for oid in revwalk {
+            let commit = oid.and_then(|oid| repo.find_commit(oid)).map_err(revwalk_error)?;
+            if self.path_prefix.as_deref().is_some_and(|prefix| !touches_path(&repo, &commit, prefix)) {
+                continue;
+            }
+            if info.commits == 0 {
+                let time = commit.time();
+                info.first_commit = Arc::from(date_format.format(time.seconds(), time.offset_minutes()));
+                info.age = Arc::from(dates::DateFormat::Relative.format(time.seconds(), time.offset_minutes()));
+                info.birth_author = Arc::from(commit.author().to_string());
+                info.born = Some(time.seconds());
+            }
+            info.commits += 1;
+        }
+        Ok(info)
+    }
+    /// The newest branch tip, local or remote-tracking, if it is older than `since`.
+    /// A repo without branches counts as stale.
+    fn stale(&mut self, since: i64) -> Result<Option<StaleInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let mut newest: Option<(git2::Time, String)> = None;
+        for scoped in scoped_branches(&repo, &repo_name, BranchScope::All)? {
+            let tip = scoped.branch.get().peel_to_commit()
+                .map_err(|source| BranchError::PeelFailure { repo: repo_name.clone(), branch: scoped.name.clone(), source })?;
+            if newest.as_ref().is_some_and(|(time, _)| time.seconds() >= tip.time().seconds()) {
+                continue;
+            }
+            let label = if scoped.remote.is_empty() { scoped.name } else { format!("{}/{}", scoped.remote, scoped.name) };
+            newest = Some((tip.time(), label));
+        }
+        let info = match newest {
+            Some((time, _)) if time.seconds() >= since => return Ok(None),
+            Some((time, branch)) => StaleInfo {
+                repo: self.repo_name.clone(),
+                last_commit: Arc::from(self.config.date_format().format(time.seconds(), time.offset_minutes())),
+                age: Arc::from(dates::DateFormat::Relative.format(time.seconds(), time.offset_minutes())),
+                branch: Arc::from(branch),
+            },
+            None => StaleInfo { repo: self.repo_name.clone(), last_commit: Arc::from("-"), age: Arc::from("-"), branch: Arc::from("-") },
+        };
+        Ok(Some(info))
+    }
+    /// Tags whose name matches the pattern (all tags without one), newest first. Tags
+    /// that do not point to a commit are left out.
+    fn tags(&mut self, pattern: Option<&Matcher>) -> Result<Vec<TagInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let names = repo.tag_names(None).map_err(|source| RepoError::TagListFailure { repo: repo_name.clone(), source })?;
+        let date_format = self.config.date_format();
+        let mut tags = Vec::new();
+        for name in names.iter().flatten().filter(|name| pattern.is_none_or(|pattern| pattern.is_match(name))) {
+            let Ok(reference) = repo.find_reference(&format!("refs/tags/{}", name)) else { continue };
+            let Ok(commit) = reference.peel_to_commit() else { continue };
+            let tag = reference.target().and_then(|target| repo.find_tag(target).ok());
+            let tagger = tag.as_ref().and_then(|tag| tag.tagger());
+            let time = tagger.as_ref().map_or(commit.time(), |tagger| tagger.when());
+            tags.push((time.seconds(), TagInfo {
+                repo: self.repo_name.clone(),
+                tag: Arc::from(name),
+                commit: Arc::from(commit.id().to_string()),
+                tagger: Arc::from(tagger.map(|tagger| tagger.to_string()).unwrap_or_default()),
+                date: Arc::from(date_format.format(time.seconds(), time.offset_minutes())),
+            }));
+        }
+        tags.sort_by(|(a, _), (b, _)| b.cmp(a));
+        Ok(tags.into_iter().map(|(_, tag)| tag).collect())
+    }
+    /// The repo's stash entries, newest (stash@{0}) first
+    fn stashes(&mut self) -> Result<Vec<StashInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let mut repo = self.open()?;
+        let mut entries = Vec::new();
+        repo.stash_foreach(|index, message, oid| {
+            entries.push((index, message.to_string(), *oid));
+            true
+        }).map_err(|source| RepoError::StashListFailure { repo: repo_name, source })?;
+        let date_format = self.config.date_format();
+        Ok(entries.into_iter().map(|(index, message, oid)| {
+            // "WIP on main: 1a2b3c4 subject" or "On main: message"
+            let (branch, message) = message
+                .strip_prefix("WIP on ")
+                .or_else(|| message.strip_prefix("On "))
+                .and_then(|rest| rest.split_once(": "))
+                .map_or(("-", message.as_str()), |(branch, message)| (branch, message));
+            let date = repo.find_commit(oid)
+                .map_or("-".to_string(), |commit| date_format.format(commit.time().seconds(), commit.time().offset_minutes()));
+            StashInfo {
+                repo: self.repo_name.clone(),
+                index,
+                branch: Arc::from(branch),
+                message: Arc::from(message),
+                date: Arc::from(date),
+            }
+        }).collect())
+    }
+    /// Count staged, modified and untracked files, only below the path of a virtual repo.
+    /// A file with both staged and unstaged changes counts in both columns.
+    fn status(&mut self) -> Result<RepoStatusInfo, GrepoError> {
+        let repo = self.open()?;
+        let operation = Arc::from(in_progress_operation(repo.state()).unwrap_or("-"));
+        let info = |staged, modified, untracked, state: &str| RepoStatusInfo {
+            repo: self.repo_name.clone(),
+            staged,
+            modified,
+            untracked,
+            operation: Arc::clone(&operation),
+            state: Arc::from(state),
+        };
+        if repo.is_bare() {
+            return Ok(info(0, 0, 0, "bare"));
+        }
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true).include_ignored(false);
+        if let Some(prefix) = &self.path_prefix {
+            options.pathspec(&**prefix);
+        }
+        let statuses = repo.statuses(Some(&mut options))
+            .map_err(|source| RepoError::StatusUnreadable { repo: self.repo_name.to_string(), source })?;
+
+        let staged_flags = git2::Status::INDEX_NEW | git2::Status::INDEX_MODIFIED | git2::Status::INDEX_DELETED
+            | git2::Status::INDEX_RENAMED | git2::Status::INDEX_TYPECHANGE;
+        let modified_flags = git2::Status::WT_MODIFIED | git2::Status::WT_DELETED | git2::Status::WT_RENAMED
+            | git2::Status::WT_TYPECHANGE | git2::Status::CONFLICTED;
+        let count = |flags: git2::Status| statuses.iter().filter(|entry| entry.status().intersects(flags)).count();
+        let (staged, modified, untracked) = (count(staged_flags), count(modified_flags), count(git2::Status::WT_NEW));
+        let state = if staged + modified + untracked == 0 { "clean" } else { "dirty" };
+        Ok(info(staged, modified, untracked, state))
+    }
+    /// Fetch from `remote`, optionally pruning stale remote-tracking refs and tags,
+    /// and report the refs that were created, moved or pruned
+    fn fetch(&mut self, remote_name: &str, prune: bool, prune_tags: bool) -> Result<FetchInfo, GrepoError> {
+        if self.backend() == Backend::Cli {
+            return self.fetch_cli(remote_name, prune, prune_tags);
+        }
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let fetch_error = |source| RepoError::FetchFailure { repo: repo_name.clone(), remote: remote_name.to_string(), source };
+        let mut remote = repo.find_remote(remote_name).map_err(fetch_error)?;
+
+        let mut refspecs = remote.fetch_refspecs().map_err(fetch_error)?
+            .iter()
+            .flatten()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        // git's --prune-tags: fetch tags through an explicit refspec so pruning covers them
+        if prune_tags {
+            refspecs.push("+refs/tags/*:refs/tags/*".to_string());
+        }
+        let mut globs = vec![format!("refs/remotes/{}/*", remote_name)];
+        if prune_tags {
+            globs.push("refs/tags/*".to_string());
+        }
+        let watched_refs = |repo: &Repository| -> Vec<String> {
+            let mut names = Vec::new();
+            for glob in &globs {
+                if let Ok(refs) = repo.references_glob(glob) {
+                    names.extend(refs.flatten().filter_map(|r| r.name().map(String::from)));
+                }
+            }
+            names
+        };
+        let before = watched_refs(&repo);
+
+        let git_config = repo.config().map_err(|source| RepoError::ConfigUnreadable { repo: repo_name.clone(), source })?;
+        let mut new_refs = Vec::new();
+        let mut updated = 0;
+        let mut callbacks = git2::RemoteCallbacks::new();
+        callbacks.credentials(remote_credentials(&git_config));
+        callbacks.update_tips(|name, old, _| {
+            if old.is_zero() {
+                new_refs.push(short_ref_name(name).to_string());
+            } else {
+                updated += 1;
+            }
+            true
+        });
+        let mut options = git2::FetchOptions::new();
+        options.remote_callbacks(callbacks);
+        if prune || prune_tags {
+            options.prune(git2::FetchPrune::On);
+        }
+        remote.fetch(&refspecs, Some(&mut options), None).map_err(fetch_error)?;
+        // release the callbacks borrowing new_refs and updated
+        drop(options);
+
+        let after = watched_refs(&repo);
+        let pruned = before
+            .into_iter()
+            .filter(|name| !after.contains(name))
+            .map(|name| short_ref_name(&name).to_string())
+            .collect::<Vec<_>>();
+        let status = if new_refs.is_empty() && updated == 0 && pruned.is_empty() { "up to date" } else { "fetched" };
+        Ok(FetchInfo {
+            repo: self.repo_name.clone(),
+            remote: Arc::from(remote_name),
+            new_refs,
+            updated,
+            pruned,
+            status: Arc::from(status),
+        })
+    }
+    /// `fetch` through the git binary, which handles negotiation, partial clones and
+    /// credentials the way the user's git does. The refs are compared before and after.
+    fn fetch_cli(&mut self, remote_name: &str, prune: bool, prune_tags: bool) -> Result<FetchInfo, GrepoError> {
+        let snapshot = |repo: &Repository| -> HashMap<String, git2::Oid> {
+            let mut refs = HashMap::new();
+            for glob in [format!("refs/remotes/{}/*", remote_name), "refs/tags/*".to_string()] {
+                if let Ok(found) = repo.references_glob(&glob) {
+                    refs.extend(found.flatten().filter_map(|r| Some((r.name()?.to_string(), r.target()?))));
+                }
+            }
+            refs
+        };
+        let before = snapshot(&self.open()?);
+        let mut args = vec!["fetch", "--quiet"];
+        if prune || prune_tags {
+            args.push("--prune");
+        }
+        if prune_tags {
+            args.push("--prune-tags");
+        }
+        args.push(remote_name);
+        self.git_cli(&args)?;
+        let after = snapshot(&self.open()?);
+
+        let mut new_refs = after.keys().filter(|name| !before.contains_key(*name)).map(|name| short_ref_name(name).to_string()).collect::<Vec<_>>();
+        new_refs.sort();
+        let updated = after.iter().filter(|(name, id)| before.get(*name).is_some_and(|old| old != *id)).count();
+        let mut pruned = before.keys().filter(|name| !after.contains_key(*name)).map(|name| short_ref_name(name).to_string()).collect::<Vec<_>>();
+        pruned.sort();
+        let status = if new_refs.is_empty() && updated == 0 && pruned.is_empty() { "up to date" } else { "fetched" };
+        Ok(FetchInfo {
+            repo: self.repo_name.clone(),
+            remote: Arc::from(remote_name),
+            new_refs,
+            updated,
+            pruned,
+            status: Arc::from(status),
+        })
+    }
+    /// Fetch the current branch's upstream remote and fast-forward the branch to it. A branch
+    /// that has diverged is left alone, and with `ff_only` reported as a failure.
+    fn pull(&mut self, ff_only: bool) -> Result<PullInfo, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo_label = self.repo_name.clone();
+        let info = |branch: &str, upstream: &str, commits: usize, status: &str| PullInfo {
+            repo: repo_label.clone(),
+            branch: Arc::from(branch),
+            upstream: Arc::from(upstream),
+            commits,
+            status: Arc::from(status),
+        };
+        let (branch_name, remote) = {
+            let repo = self.open()?;
+            let head = match repo.head() {
+                Ok(head) if head.is_branch() => head,
+                _ => return Ok(info("-", "-", 0, "not on a branch")),
+            };
+            let branch_name = head.shorthand().unwrap_or_default().to_string();
+            let remote = match repo.branch_upstream_remote(head.name().unwrap_or_default()) {
+                Ok(remote) => remote.as_str().unwrap_or_default().to_string(),
+                Err(ref e) if e.code() == ErrorCode::NotFound => return Ok(info(&branch_name, "-", 0, "no upstream")),
+                Err(source) => return Err(BranchError::UpstreamUnreadable { repo: repo_name, branch: branch_name, source }.into()),
+            };
+            (branch_name, remote)
+        };
+        self.fetch(&remote, false, false)?;
+
+        let repo = self.open()?;
+        let ff_error = |source| BranchError::FastForwardFailure { repo: repo_name.clone(), branch: branch_name.clone(), source };
+        let branch = repo.find_branch(&branch_name, git2::BranchType::Local).map_err(ff_error)?;
+        let upstream = branch.upstream()
+            .map_err(|source| BranchError::UpstreamUnreadable { repo: repo_name.clone(), branch: branch_name.clone(), source })?;
+        let upstream_name = upstream.name().ok().flatten().unwrap_or_default().to_string();
+        let local = branch.get().peel_to_commit().map_err(ff_error)?;
+        let target = upstream.get().peel_to_commit().map_err(ff_error)?;
+        if local.id() == target.id() {
+            return Ok(info(&branch_name, &upstream_name, 0, "up to date"));
+        }
+        let (ahead, behind) = repo.graph_ahead_behind(local.id(), target.id())
+            .map_err(|source| CommitError::AheadBehind { repo: repo_name.clone(), branch: branch_name.clone(), source })?;
+        if behind == 0 {
+            return Ok(info(&branch_name, &upstream_name, 0, &format!("ahead by {}", ahead)));
+        }
+        if ahead > 0 {
+            if ff_only {
+                return Err(BranchError::NotFastForward { repo: repo_name, branch: branch_name, upstream: upstream_name }.into());
+            }
+            return Ok(info(&branch_name, &upstream_name, 0, "diverged, not fast-forwarded"));
+        }
+        repo.checkout_tree(target.as_object(), Some(CheckoutBuilder::new().safe())).map_err(ff_error)?;
+        let mut reference = branch.into_reference();
+        reference.set_target(target.id(), &format!("grepo pull: fast-forward to {}", upstream_name)).map_err(ff_error)?;
+        Ok(info(&branch_name, &upstream_name, behind, "updated"))
+    }
+    /// Local branches whose configured upstream ref is gone, e.g. after a fetch with prune.
+    /// With `delete`, removes them unless checked out, or not merged into HEAD without --force.
+    fn gone_branches(&mut self, delete: bool) -> Result<Vec<GoneBranchInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let list_error = |source| BranchError::ListFailure { repo: repo_name.clone(), source };
+        let head = repo.head().ok();
+        let head_name = head.as_ref().filter(|h| h.is_branch()).and_then(|h| h.shorthand().map(String::from));
+        let head_oid = head.as_ref().and_then(|h| h.target());
+        let mut gone = Vec::new();
+        for b in repo.branches(Some(git2::BranchType::Local)).map_err(list_error)? {
+            let (mut branch, _) = b.map_err(list_error)?;
+            let branch_name = match branch.name() {
+                Ok(Some(name)) => name.to_string(),
+                Ok(None) => continue,
+                Err(source) => return Err(BranchError::NameError { repo: repo_name, source }.into()),
+            };
+            let upstream = match repo.branch_upstream_name(&format!("refs/heads/{}", branch_name)) {
+                Ok(name) => name.as_str().unwrap_or_default().to_string(),
+                Err(ref e) if e.code() == ErrorCode::NotFound => continue,
+                Err(source) => return Err(BranchError::UpstreamUnreadable { repo: repo_name, branch: branch_name, source }.into()),
+            };
+            if repo.find_reference(&upstream).is_ok() {
+                continue;
+            }
+            let merged = match (branch.get().target(), head_oid) {
+                (Some(tip), Some(head)) => tip == head || repo.graph_descendant_of(head, tip).unwrap_or(false),
+                _ => false,
+            };
+            let action = if head_name.as_deref() == Some(branch_name.as_str()) {
+                "checked out, kept"
+            } else if self.config.is_protected(&branch_name) {
+                "protected, kept"
+            } else if !merged && !self.config.runtime.force {
+                if delete { "unmerged, kept (use --force)" } else { "gone, unmerged" }
+            } else if delete {
+                branch.delete()
+                    .map_err(|source| BranchError::DeleteFailure { repo: repo_name.clone(), branch: branch_name.clone(), source })?;
+                "deleted"
+            } else {
+                "gone"
+            };
+            gone.push(GoneBranchInfo {
+                repo: self.repo_name.clone(),
+                branch: Arc::from(branch_name),
+                upstream: Arc::from(upstream.strip_prefix("refs/remotes/").unwrap_or(&upstream)),
+                action: Arc::from(action),
+            });
+        }
+        Ok(gone)
+    }
+    /// Local branches that branch deletion refuses to touch, with the pattern protecting each
+    fn protected_branches(&mut self) -> Result<Vec<ProtectedBranchInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let list_error = |source| BranchError::ListFailure { repo: repo_name.clone(), source };
+        let mut protected = Vec::new();
+        for b in repo.branches(Some(git2::BranchType::Local)).map_err(list_error)? {
+            let (branch, _) = b.map_err(list_error)?;
+            let branch_name = match branch.name() {
+                Ok(Some(name)) => name.to_string(),
+                Ok(None) => continue,
+                Err(source) => return Err(BranchError::NameError { repo: repo_name, source }.into()),
+            };
+            if let Some(pattern) = self.config.protecting_pattern(&branch_name) {
+                protected.push(ProtectedBranchInfo {
+                    repo: self.repo_name.clone(),
+                    branch: Arc::from(branch_name),
+                    pattern: Arc::from(pattern),
+                });
+            }
+        }
+        Ok(protected)
+    }
+    /// Ahead/behind counts of every local branch against its upstream
+    fn branch_divergence(&mut self) -> Result<Vec<DivergenceInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let list_error = |source| BranchError::ListFailure { repo: repo_name.clone(), source };
+        let mut divergence = Vec::new();
+        for b in repo.branches(Some(git2::BranchType::Local)).map_err(list_error)? {
+            let (branch, _) = b.map_err(list_error)?;
+            let branch_name = match branch.name() {
+                Ok(Some(name)) => name.to_string(),
+                Ok(None) => continue,
+                Err(source) => return Err(BranchError::NameError { repo: repo_name, source }.into()),
+            };
+            let upstream = match branch.upstream() {
+                Ok(upstream) => upstream,
+                Err(ref e) if e.code() == ErrorCode::NotFound => {
+                    divergence.push(DivergenceInfo {
+                        repo: self.repo_name.clone(),
+                        branch: Arc::from(branch_name),
+                        upstream: Arc::from("-"),
+                        ahead: Arc::from("-"),
+                        behind: Arc::from("-"),
+                        status: Arc::from("no upstream"),
+                        priority: (4, Reverse(0)),
+                    });
+                    continue;
+                }
+                Err(source) => return Err(BranchError::UpstreamUnreadable { repo: repo_name, branch: branch_name, source }.into()),
+            };
+            let upstream_name = upstream.name().ok().flatten().unwrap_or("** Unknown **").to_string();
+            let (ahead, behind) = match (branch.get().target(), upstream.get().target()) {
+                (Some(local), Some(remote)) => repo.graph_ahead_behind(local, remote)
+                    .map_err(|source| CommitError::AheadBehind { repo: repo_name.clone(), branch: branch_name.clone(), source })?,
+                _ => (0, 0),
+            };
+            let (status, rank) = match (ahead, behind) {
+                (0, 0) => ("up to date", 3),
+                (_, 0) => ("ahead", 2),
+                (0, _) => ("behind", 1),
+                _ => ("diverged", 0),
+            };
+            divergence.push(DivergenceInfo {
+                repo: self.repo_name.clone(),
+                branch: Arc::from(branch_name),
+                upstream: Arc::from(upstream_name),
+                ahead: Arc::from(ahead.to_string()),
+                behind: Arc::from(behind.to_string()),
+                status: Arc::from(status),
+                priority: (rank, Reverse(ahead + behind)),
+            });
+        }
+        Ok(divergence)
+    }
+    /// Paths with unresolved conflicts in the index
+    fn conflicted_paths(&mut self) -> Result<Vec<ConflictInfo>, GrepoError> {
+        let repo = self.open()?;
+        let index_error = |source| RepoError::IndexUnreadable { repo: self.repo_name.to_string(), source };
+        let index = repo.index().map_err(index_error)?;
+        if !index.has_conflicts() {
+            return Ok(Vec::new());
+        }
+        let mut conflicts = Vec::new();
+        for conflict in index.conflicts().map_err(index_error)? {
+            let conflict = conflict.map_err(index_error)?;
+            if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                conflicts.push(ConflictInfo {
+                    repo: self.repo_name.clone(),
+                    path: Arc::from(String::from_utf8_lossy(&entry.path).as_ref()),
+                });
+            }
+        }
+        Ok(conflicts)
+    }
+    /// Refuse to continue when the repo has an operation in progress or uncommitted
+    /// changes to tracked files, unless running with --force
+    fn guard_mutation(&mut self) -> Result<(), GrepoError> {
+        if self.config.runtime.force {
+            return Ok(());
+        }
+        let repo = self.open()?;
+        if let Some(operation) = in_progress_operation(repo.state()) {
+            return Err(GuardError::InProgress { repo: self.repo_name.to_string(), operation: operation.to_string() }.into());
+        }
+        if repo.is_bare() {
+            return Ok(());
+        }
+        let mut options = StatusOptions::new();
+        options.include_untracked(false).include_ignored(false);
+        let statuses = repo.statuses(Some(&mut options))
+            .map_err(|source| GuardError::StatusFailure { repo: self.repo_name.to_string(), source })?;
+        if !statuses.is_empty() {
+            return Err(GuardError::Dirty { repo: self.repo_name.to_string(), count: statuses.len() }.into());
+        }
+        Ok(())
+    }
+    /// Check for a commit-graph file (single or split chain) in the object directory.
+    /// libgit2 loads it automatically during revwalks when present.
+    fn has_commit_graph(&mut self) -> Result<bool, GrepoError> {
+        let repo = self.open()?;
+        let info_dir = repo.path().join("objects").join("info");
+        Ok(info_dir.join("commit-graph").is_file() || info_dir.join("commit-graphs").is_dir())
+    }
+    /// Generate a commit-graph for all reachable commits using the git binary,
+    /// since libgit2 does not expose a commit-graph writer
+    fn write_commit_graph(&mut self) -> Result<(), GrepoError> {
+        self.git_cli(&["commit-graph", "write", "--reachable"])?;
+        Ok(())
+    }
+    /// Pack refs, prune unreachable objects older than `prune_days` and repack, returning the
+    /// object directory size before and after. Pruning is skipped when `shared` is set, since
+    /// other repos borrow this object store and may need objects that are unreachable here.
+    fn run_maintenance(&mut self, prune_days: u32, shared: bool) -> Result<(u64, u64), GrepoError> {
+        let repo = self.open()?;
+        let objects_dir = repo.path().join("objects");
+        let before = dir_size(&objects_dir);
+        let expire = format!("--expire={}.days.ago", prune_days);
+        self.git_cli(&["pack-refs", "--all"])?;
+        if !shared {
+            self.git_cli(&["prune", expire.as_str()])?;
+        }
+        self.git_cli(&["repack", "-d", "-l"])?;
+        Ok((before, dir_size(&objects_dir)))
+    }
+    /// Canonical object directory and the object directories listed in its alternates file
+    fn object_stores(&mut self) -> Result<ObjectStores, GrepoError> {
+        let repo = self.open()?;
+        let objects_dir = repo.path().join("objects");
+        let objects_dir = objects_dir.canonicalize().unwrap_or(objects_dir);
+        let alternates = std::fs::read_to_string(objects_dir.join("info").join("alternates"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            // relative entries are relative to the objects directory
+            .map(|line| objects_dir.join(line))
+            .map(|path| path.canonicalize().unwrap_or(path))
+            .collect();
+        Ok(ObjectStores { repo: self.repo_name.clone(), objects_dir, alternates })
+    }
+    /// The `limit` most recent commits on HEAD and local branches, children first, labelled
+    /// with short id, branch/tag decorations and summary
+    fn graph_commits(&mut self, limit: usize) -> Result<Vec<GraphCommit>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: "HEAD".to_string(), source };
+        let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME).map_err(revwalk_error)?;
+        if repo.head().is_ok() {
+            revwalk.push_head().map_err(revwalk_error)?;
+        }
+        revwalk.push_glob("refs/heads/*").map_err(revwalk_error)?;
+
+        let mut decorations: HashMap<git2::Oid, Vec<String>> = HashMap::new();
+        if let Ok(references) = repo.references() {
+            for reference in references.flatten() {
+                let (Some(name), Ok(commit)) = (reference.shorthand(), reference.peel_to_commit()) else { continue };
+                if reference.is_branch() || reference.is_remote() || reference.is_tag() {
+                    decorations.entry(commit.id()).or_default().push(name.to_string());
+                }
+            }
+        }
+
+        Ok(revwalk
+            .filter_map(|oid| oid.ok())
+            .filter_map(|oid| repo.find_commit(oid).ok())
+            .take(limit)
+            .map(|commit| {
+                let short_id = commit.id().to_string()[..7].to_string();
+                let decoration = decorations.get(&commit.id())
+                    .map(|names| format!(" ({})", names.join(", ")))
+                    .unwrap_or_default();
+                GraphCommit {
+                    id: commit.id().to_string(),
+                    parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+                    label: format!("{}{} {}", short_id, decoration, commit.summary().unwrap_or("")),
+                }
+            })
+            .collect())
+    }
+    /// Local branches with their upstreams and whether HEAD already contains them
+    fn branch_graph(&mut self) -> Result<RepoGraph, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let head = repo.head().ok();
+        let head_oid = head.as_ref().and_then(|h| h.target());
+        let list_error = |source| BranchError::ListFailure { repo: repo_name.clone(), source };
+        let mut branches = Vec::new();
+        for b in repo.branches(Some(git2::BranchType::Local)).map_err(list_error)? {
+            let (branch, _) = b.map_err(list_error)?;
+            let Some(name) = branch.name().ok().flatten().map(String::from) else { continue };
+            let upstream = branch.upstream().ok().and_then(|u| u.name().ok().flatten().map(String::from));
+            let merged = match (branch.get().target(), head_oid) {
+                (Some(tip), Some(head)) => tip == head || repo.graph_descendant_of(head, tip).unwrap_or(false),
+                _ => false,
+            };
+            branches.push(BranchNode { name, upstream, merged });
+        }
+        // a virtual repo shares its root with the repo containing it, that is not a fork
+        let root = match head_oid.filter(|_| self.path_prefix.is_none()) {
+            Some(oid) => {
+                let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: "HEAD".to_string(), source };
+                let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+                revwalk.push(oid).map_err(revwalk_error)?;
+                revwalk.simplify_first_parent().map_err(revwalk_error)?;
+                revwalk.filter_map(|oid| oid.ok()).last().map(|oid| oid.to_string())
+            }
+            None => None,
+        };
+        Ok(RepoGraph {
+            repo: repo_name,
+            head: head.filter(|h| h.is_branch()).and_then(|h| h.shorthand().map(String::from)),
+            branches,
+            root,
+        })
+    }
+    /// Files changed, insertions and deletions on `branch` since its merge base with the
+    /// default branch, or None when the repo has no such branch
+    fn diffstat(&mut self, branch_name: &str) -> Result<Option<DiffstatInfo>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let Ok(branch) = repo.find_branch(branch_name, git2::BranchType::Local) else { return Ok(None) };
+        let peel_error = |source| BranchError::PeelFailure { repo: repo_name.clone(), branch: branch_name.to_string(), source };
+        let tip = branch.get().peel_to_commit().map_err(peel_error)?;
+        let Some((base_name, base_oid)) = default_branch(&repo) else { return Ok(None) };
+
+        let diff_error = |source| CommitError::DiffFailure { repo: repo_name.clone(), commit: tip.id().to_string(), source };
+        let merge_base = repo.merge_base(base_oid, tip.id()).map_err(diff_error)?;
+        let base_tree = repo.find_commit(merge_base).and_then(|c| c.tree()).map_err(diff_error)?;
+        let tree = tip.tree().map_err(diff_error)?;
+        let mut options = DiffOptions::new();
+        if let Some(prefix) = &self.path_prefix {
+            options.pathspec(&**prefix);
+        }
+        let stats = repo.diff_tree_to_tree(Some(&base_tree), Some(&tree), Some(&mut options))
+            .and_then(|diff| diff.stats())
+            .map_err(diff_error)?;
+        Ok(Some(DiffstatInfo {
+            repo: self.repo_name.clone(),
+            base: Arc::from(base_name),
+            files: stats.files_changed(),
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        }))
+    }
+    /// Apply a patch to the working tree, or with `commit` also commit it on HEAD using the
+    /// patch's author and message. The commit is HEAD's tree with the patch applied, so
+    /// changes that were already staged stay staged and out of it. A patch that does not
+    /// apply cleanly leaves the repo untouched.
+    fn apply_patch(&mut self, patch: &[u8], commit: bool, message: Option<&str>) -> Result<ApplyInfo, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let info = |status: String| ApplyInfo { repo: self.repo_name.clone(), status: Arc::from(status) };
+        let diff = git2::Diff::from_buffer(patch).map_err(|source| CommitError::PatchUnparseable { source })?;
+        if let Err(e) = repo.apply(&diff, git2::ApplyLocation::WorkDir, Some(git2::ApplyOptions::new().check(true))) {
+            return Ok(info(format!("does not apply: {}", e.message())));
+        }
+        if !commit {
+            repo.apply(&diff, git2::ApplyLocation::WorkDir, None).map_err(|source| CommitError::WriteFailure { repo: repo_name.clone(), source })?;
+            return Ok(info("applied to working tree".to_string()));
+        }
+
+        let header = patch::parse_header(&String::from_utf8_lossy(patch));
+        let Some(message) = message.map(String::from).or(header.message) else {
+            return Ok(info("skipped: patch has no subject, pass --message".to_string()));
+        };
+        let commit_error = |source| CommitError::WriteFailure { repo: repo_name.clone(), source };
+        let committer = repo.signature().map_err(commit_error)?;
+        let author = match (&header.author_name, &header.author_email) {
+            (Some(name), Some(email)) => git2::Signature::now(name, email).map_err(commit_error)?,
+            _ => committer.clone(),
+        };
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let head_tree = match &parent {
+            Some(parent) => parent.tree().map_err(commit_error)?,
+            None => repo.treebuilder(None).and_then(|builder| builder.write()).and_then(|oid| repo.find_tree(oid)).map_err(commit_error)?,
+        };
+        let mut patched = match repo.apply_to_tree(&head_tree, &diff, None) {
+            Ok(patched) => patched,
+            Err(e) => return Ok(info(format!("does not apply to HEAD: {}", e.message()))),
+        };
+        let tree = patched.write_tree_to(&repo).and_then(|oid| repo.find_tree(oid)).map_err(commit_error)?;
+        let oid = repo.commit(Some("HEAD"), &author, &committer, &message, &tree, &parent.iter().collect::<Vec<_>>())
+            .map_err(commit_error)?;
+        repo.apply(&diff, git2::ApplyLocation::WorkDir, None).map_err(commit_error)?;
+        // stage the committed version of the patched paths, leaving the other entries alone
+        let mut index = repo.index().map_err(commit_error)?;
+        for delta in diff.deltas() {
+            if let Some(old_path) = delta.old_file().path() {
+                if patched.get_path(old_path, 0).is_none() {
+                    index.remove_path(old_path).map_err(commit_error)?;
+                }
+            }
+            if let Some(entry) = delta.new_file().path().and_then(|path| patched.get_path(path, 0)) {
+                index.add(&entry).map_err(commit_error)?;
+            }
+        }
+        index.write().map_err(commit_error)?;
+        Ok(info(format!("committed {}", &oid.to_string()[..7])))
+    }
+    /// Replace `pattern` in the tracked files matching the `path_glob` pathspec, or only
+    /// preview the diff. Binary and non-UTF-8 files are left alone.
+    fn sed(&mut self, pattern: &regex::Regex, replacement: &str, path_glob: Option<&str>, mode: &SedMode) -> Result<SedInfo, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let Some(workdir) = repo.workdir().map(Path::to_path_buf) else {
+            let status = Arc::from("bare repo, no working tree");
+            return Ok(SedInfo { repo: self.repo_name.clone(), files: 0, replacements: 0, status, diff: String::new() });
+        };
+        let index = repo.index().map_err(|source| RepoError::IndexUnreadable { repo: repo_name.clone(), source })?;
+        let pathspec = git2::Pathspec::new(path_glob)
+            .map_err(|source| RepoError::PathspecInvalid { pathspec: path_glob.unwrap_or_default().to_string(), source })?;
+
+        let mut edits = Vec::new();
+        let mut replacements = 0;
+        let mut diff = String::new();
+        for entry in index.iter() {
+            let path = String::from_utf8_lossy(&entry.path).to_string();
+            if self.path_prefix.as_deref().is_some_and(|prefix| !Path::new(&path).starts_with(prefix))
+                || !pathspec.matches_path(Path::new(&path), git2::PathspecFlags::DEFAULT)
+            {
+                continue;
+            }
+            let Ok(bytes) = std::fs::read(workdir.join(&path)) else { continue };
+            let Ok(before) = String::from_utf8(bytes) else { continue };
+            if before.contains('\0') || !pattern.is_match(&before) {
+                continue;
+            }
+            let after = pattern.replace_all(&before, replacement).into_owned();
+            if after == before {
+                continue;
+            }
+            replacements += pattern.find_iter(&before).count();
+            let preview_error = |source| RepoError::PreviewFailure { repo: repo_name.clone(), path: path.clone(), source };
+            let file_diff = git2::Patch::from_buffers(before.as_bytes(), Some(Path::new(&path)), after.as_bytes(), Some(Path::new(&path)), None)
+                .and_then(|mut patch| patch.to_buf())
+                .map_err(preview_error)?;
+            diff.push_str(&String::from_utf8_lossy(&file_diff));
+            edits.push((path, after));
+        }
+
+        let info = |status: String| SedInfo {
+            repo: self.repo_name.clone(),
+            files: edits.len(),
+            replacements,
+            status: Arc::from(status),
+            diff: diff.clone(),
+        };
+        if edits.is_empty() {
+            return Ok(info("no matches".to_string()));
+        }
+        if let SedMode::Preview = mode {
+            return Ok(info("preview".to_string()));
+        }
+
+        if let SedMode::Commit { branch: Some(branch), .. } = mode {
+            switch_to_new_branch(&repo, &repo_name, branch)?;
+        }
+        write_files(&workdir, &repo_name, &edits)?;
+        let SedMode::Commit { message, branch } = mode else {
+            return Ok(info("written".to_string()));
+        };
+        let oid = commit_paths(&repo, &repo_name, edits.iter().map(|(path, _)| path.as_str()), message)?;
+        let on_branch = branch.map(|branch| format!(" on {}", branch)).unwrap_or_default();
+        Ok(info(format!("committed {}{}", &oid.to_string()[..7], on_branch)))
+    }
+    /// Compare the shared files with their copies in the repo, below the path of a virtual
+    /// repo, and with `commit` write the differing ones and commit them on a new branch
+    fn sync_files(&mut self, files: &[sync::SyncFile], commit: Option<(&str, &str)>) -> Result<SyncInfo, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let info = |out_of_sync: Vec<String>, status: &str| SyncInfo { repo: self.repo_name.clone(), out_of_sync, status: Arc::from(status) };
+        let Some(workdir) = repo.workdir().map(Path::to_path_buf) else {
+            return Ok(info(Vec::new(), "bare repo, no working tree"));
+        };
+
+        let stale = files
+            .iter()
+            .map(|file| match &self.path_prefix {
+                Some(prefix) => (Path::new(&**prefix).join(&file.dest).display().to_string(), &file.contents),
+                None => (file.dest.clone(), &file.contents),
+            })
+            .filter(|(dest, contents)| std::fs::read(workdir.join(dest)).map_or(true, |current| &current != *contents))
+            .collect::<Vec<_>>();
+        let out_of_sync = stale.iter().map(|(dest, _)| dest.clone()).collect::<Vec<_>>();
+        if stale.is_empty() {
+            return Ok(info(out_of_sync, "in sync"));
+        }
+        let Some((branch, message)) = commit else {
+            return Ok(info(out_of_sync, "out of sync"));
+        };
+
+        switch_to_new_branch(&repo, &repo_name, branch)?;
+        write_files(&workdir, &repo_name, &stale)?;
+        let oid = commit_paths(&repo, &repo_name, out_of_sync.iter().map(String::as_str), message)?;
+        Ok(info(out_of_sync, &format!("committed {} on {}", &oid.to_string()[..7], branch)))
+    }
+    /// Write commits as format-patch style files into `dir`, oldest first, returning the paths
+    fn write_patches(&mut self, commit_ids: &[&str], dir: &Path) -> Result<Vec<PathBuf>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let patch_error = |commit: &str, source| CommitError::PatchFailure { repo: repo_name.clone(), commit: commit.to_string(), source };
+        let mut commits = Vec::new();
+        for id in commit_ids {
+            let commit = git2::Oid::from_str(id).and_then(|oid| repo.find_commit(oid)).map_err(|source| patch_error(id, source))?;
+            commits.push(commit);
+        }
+        commits.sort_by_key(|commit| commit.time().seconds());
+
+        let write_error = |path: &Path, source| CommitError::PatchWriteFailure { path: path.display().to_string(), source };
+        std::fs::create_dir_all(dir).map_err(|source| write_error(dir, source))?;
+        let mut written = Vec::new();
+        for (i, commit) in commits.iter().enumerate() {
+            let mut options = git2::EmailCreateOptions::new();
+            if let Some(prefix) = &self.path_prefix {
+                options.diff_options().pathspec(&**prefix);
+            }
+            let email = git2::Email::from_commit(commit, &mut options)
+                .map_err(|source| patch_error(&commit.id().to_string(), source))?;
+            let path = dir.join(format!("{:04}-{}.patch", i + 1, patch_slug(commit.summary().unwrap_or(""))));
+            std::fs::write(&path, email.as_slice()).map_err(|source| write_error(&path, source))?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+    /// Search commit messages (and optionally authors) on every local branch
+    fn search_commits(&mut self, query: &CommitQuery) -> Result<Vec<RepoBranchCommit>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let mut found_commits: Vec<RepoBranchCommit> = Vec::new();
+        // without --per-branch, the row of each commit and the branches it was found on
+        let mut rows: HashMap<git2::Oid, (usize, Vec<(String, String)>)> = HashMap::new();
+        let date_format = self.config.date_format();
+        let head_only = self.config.groups_of(&repo_name).any(|group| group.head_only);
+
+        'branches: for ScopedBranch { remote, name: branch_name, branch } in scoped_branches(&repo, &repo_name, query.branches)? {
+            if head_only && remote.is_empty() && !branch.is_head() {
+                continue;
+            }
+            if query.branch_pattern.as_ref().is_some_and(|pattern| {
+                !pattern.is_match(&branch_name) && !pattern.is_match(&format!("{}/{}", remote, branch_name))
+            }) {
+                continue;
+            }
+            let commit_id = branch.into_reference().peel(ObjectType::Commit)
+                .map_err(|source| BranchError::PeelFailure { repo: repo_name.clone(), branch: branch_name.clone(), source })?
+                .id();
+            let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: branch_name.clone(), source };
+            let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+            revwalk.push(commit_id).map_err(revwalk_error)?;
+            // newest first, so the walk can stop at the first commit older than --since
+            revwalk.set_sorting(Sort::TIME).map_err(revwalk_error)?;
+
+            let commits = revwalk
+                .filter_map(|oid| oid.ok())
+                .filter_map(|oid| {
+                    repo.find_commit(oid).ok()
+                })
+                .take_while(|commit| query.since.is_none_or(|since| commit.time().seconds() >= since))
+                .filter(|commit| query.until.is_none_or(|until| commit.time().seconds() <= until));
+
+            for commit in commits {
+                if interrupted() {
+                    break 'branches;
+                }
+                let signature = commit.author();
+                let author = signature.to_string();
+                let fields = query::CommitFields {
+                    repo: &repo_name,
+                    branch: &branch_name,
+                    author: &author,
+                    email: signature.email().unwrap_or(""),
+                    message: commit.message().unwrap_or(""),
+                    time: commit.time().seconds(),
+                };
+                let kept = query.matches(&commit)
+                    && query.filter.as_ref().is_none_or(|filter| filter.matches(&fields))
+                    && self.path_prefix.as_deref().is_none_or(|prefix| touches_path(&repo, &commit, prefix));
+                if !kept {
+                    continue;
+                }
+                if let Some(script) = &query.script {
+                    let matched = script.matches_commit(&fields).map_err(|message| CommitError::ScriptFailure {
+                        repo: repo_name.clone(),
+                        commit: commit.id().to_string(),
+                        message,
+                    })?;
+                    if !matched {
+                        continue;
+                    }
+                }
+                if !query.per_branch {
+                    if let Some((_, branches)) = rows.get_mut(&commit.id()) {
+                        branches.push((remote.clone(), branch_name.clone()));
+                        continue;
+                    }
+                    rows.insert(commit.id(), (found_commits.len(), vec![(remote.clone(), branch_name.clone())]));
+                }
+                found_commits.push(RepoBranchCommit {
+                    repo: Arc::from(repo_name.to_owned()),
+                    branch: Arc::from(branch_name.to_owned()),
+                    remote: Arc::from(remote.as_str()),
+                    message: Arc::from(commit.message().unwrap_or("").trim()),
+                    author: Arc::from(author),
+                    date: Arc::from(date_format.format(commit.time().seconds(), commit.time().offset_minutes())),
+                    commit: Arc::from(commit.id().to_string()),
+                    time: commit.time().seconds(),
+                });
+                if query.limit.is_some_and(|limit| found_commits.len() >= limit) {
+                    break 'branches;
+                }
+            }
+        }
+        for (index, branches) in rows.into_values().filter(|(_, branches)| branches.len() > 1) {
+            let row = &mut found_commits[index];
+            let labels = branches.iter()
+                .map(|(remote, name)| if remote.is_empty() { name.clone() } else { format!("{}/{}", remote, name) })
+                .collect::<Vec<_>>();
+            let mut remotes = branches.into_iter().map(|(remote, _)| remote).filter(|remote| !remote.is_empty()).collect::<Vec<_>>();
+            remotes.sort();
+            remotes.dedup();
+            row.branch = Arc::from(labels.join(", "));
+            row.remote = Arc::from(remotes.join(", "));
+        }
+        Ok(found_commits)
+    }
+    /// Commits on the searched branches that change how often the needle occurs in a
+    /// file, compared with their first parent. Merge commits are skipped like `git log -S`
+    /// does, and binary files are not counted.
+    fn search_diffs(&mut self, query: &DiffQuery) -> Result<Vec<DiffMatch>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.clone(), branch: "*".to_string(), source };
+        let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+        for scoped in scoped_branches(&repo, &repo_name, query.branches)? {
+            if let Some(tip) = scoped.branch.get().target() {
+                revwalk.push(tip).map_err(revwalk_error)?;
+            }
+        }
+        // newest first, so the walk can stop at the first commit older than --since
+        revwalk.set_sorting(Sort::TIME).map_err(revwalk_error)?;
+
+        let needle = query.needle.as_bytes();
+        let date_format = self.config.date_format();
+        let mut found = Vec::new();
+        let commits = revwalk
+            .filter_map(|oid| oid.ok())
+            .filter_map(|oid| repo.find_commit(oid).ok())
+            .take_while(|commit| query.since.is_none_or(|since| commit.time().seconds() >= since))
+            .filter(|commit| query.until.is_none_or(|until| commit.time().seconds() <= until))
+            .filter(|commit| commit.parent_count() <= 1);
+        for commit in commits {
+            if interrupted() {
+                break;
+            }
+            let diff_error = |source| CommitError::DiffFailure { repo: repo_name.clone(), commit: commit.id().to_string(), source };
+            let tree = commit.tree().map_err(diff_error)?;
+            let parent_tree = commit.parent(0).ok().and_then(|parent| parent.tree().ok());
+            let mut options = DiffOptions::new();
+            if let Some(prefix) = self.path_prefix.as_deref() {
+                options.pathspec(prefix);
+            }
+            let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut options)).map_err(diff_error)?;
+            let mut paths = Vec::new();
+            for delta in diff.deltas() {
+                let count = |id: git2::Oid| -> Option<usize> {
+                    if id.is_zero() {
+                        return Some(0);
+                    }
+                    let blob = repo.find_blob(id).ok()?;
+                    (!blob.is_binary()).then(|| count_occurrences(blob.content(), needle))
+                };
+                let (Some(before), Some(after)) = (count(delta.old_file().id()), count(delta.new_file().id())) else { continue };
+                if before == after {
+                    continue;
+                }
+                let path = delta.new_file().path().or(delta.old_file().path()).map(|path| path.display().to_string()).unwrap_or_default();
+                let change = if after > before { format!("+{}", after - before) } else { format!("-{}", before - after) };
+                paths.push(format!("{} ({})", path, change));
+            }
+            if paths.is_empty() {
+                continue;
+            }
+            found.push(DiffMatch {
+                repo: self.repo_name.clone(),
+                commit: Arc::from(commit.id().to_string()),
+                author: Arc::from(commit.author().to_string()),
+                date: Arc::from(date_format.format(commit.time().seconds(), commit.time().offset_minutes())),
+                message: Arc::from(commit.summary().unwrap_or("").trim()),
+                paths,
+            });
+            if query.limit.is_some_and(|limit| found.len() >= limit) {
+                break;
+            }
+        }
+        Ok(found)
+    }
+
+    /// The commit `hash` (full or abbreviated) names in this repo and the branches that
+    /// contain it, or None if the repo does not have it
+    fn search_hash(&mut self, hash: &str, scope: BranchScope) -> Result<Option<HashMatch>, GrepoError> {
+        let repo_name = self.repo_name.to_string();
+        let repo = self.open()?;
+        // Looked up as an object id only, so a branch or tag that happens to be named like a
+        // hash is not taken for it
+        let found = git2::Oid::from_str(hash).and_then(|id| match hash.len() {
+            40 => repo.find_commit(id),
+            len => repo.odb()?.exists_prefix(id, len).and_then(|id| repo.find_commit(id)),
+        });
+        let commit = match found {
+            Ok(commit) => commit,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(source) => return Err(CommitError::HashLookupFailure { repo: repo_name, hash: hash.to_string(), source }.into()),
+        };
+        let mut branches = Vec::new();
+        for scoped in scoped_branches(&repo, &repo_name, scope)? {
+            let Some(tip) = scoped.branch.get().target() else { continue };
+            let contains = tip == commit.id() || repo.graph_descendant_of(tip, commit.id()).map_err(|source| CommitError::RevWalkFailure {
+                repo: repo_name.clone(),
+                branch: scoped.name.clone(),
+                source,
+            })?;
+            if contains {
+                branches.push(if scoped.remote.is_empty() { scoped.name } else { format!("{}/{}", scoped.remote, scoped.name) });
+            }
+        }
+        Ok(Some(HashMatch {
+            repo: self.repo_name.clone(),
+            commit: Arc::from(commit.id().to_string()),
+            message: Arc::from(commit.summary().unwrap_or("").trim()),
+            branches,
+        }))
+    }
+}
+
+/// Non-overlapping occurrences of `needle` in `haystack`
+fn count_occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+    if needle.is_empty() {
+        return 0;
+    }
+    let mut count = 0;
+    let mut rest = haystack;
+    while let Some(position) = rest.windows(needle.len()).position(|window| window == needle) {
+        count += 1;
+        rest = &rest[position + needle.len()..];
+    }
+    count
+}
+
+/// Which commits a commit search keeps
+#[derive(Debug, Clone, Default)]
+pub struct CommitQuery {
+    pub pattern: Matcher,
+    /// Also match the pattern against the author name and email
+    pub include_author: bool,
+    /// Only keep commits authored with this email (case-insensitive)
+    pub author_email: Option<String>,
+    /// Only keep commits whose author "Name <email>" contains this (case-insensitive)
+    pub author: Option<String>,
+    /// Only keep commits whose committer "Name <email>" contains this (case-insensitive)
+    pub committer: Option<String>,
+    /// Which branches to search
+    pub branches: BranchScope,
+    /// Only walk branches whose name, or remote/name, matches
+    pub branch_pattern: Option<regex::Regex>,
+    /// Only commits committed at or after this unix timestamp
+    pub since: Option<i64>,
+    /// Only commits committed at or before this unix timestamp
+    pub until: Option<i64>,
+    /// `grepo query` expression commits must also match
+    pub filter: Option<query::Expr>,
+    /// `--script` filter commits must also pass
+    pub script: Option<Arc<script::Filter>>,
+    /// Stop searching a repo once it has this many matches
+    pub limit: Option<usize>,
+    /// Keep a row per branch a commit is on, instead of one row listing the branches
+    pub per_branch: bool,
+}
+
+/// Which commits a pickaxe search (`git log -S`) keeps
+#[derive(Debug, Clone, Default)]
+pub struct DiffQuery {
+    /// Text whose number of occurrences in a file the commit must change
+    pub needle: String,
+    /// Which branches to walk
+    pub branches: BranchScope,
+    /// Only commits committed at or after this unix timestamp
+    pub since: Option<i64>,
+    /// Only commits committed at or before this unix timestamp
+    pub until: Option<i64>,
+    /// Stop searching a repo once it has this many matches
+    pub limit: Option<usize>,
+}
+
+/// Which file lines a content search keeps
+#[derive(Debug, Clone)]
+pub struct ContentQuery {
+    pub pattern: Matcher,
+    /// Branch, tag or commit whose files to search
+    pub reference: String,
+    /// Leave out files .gitattributes marks linguist-vendored or export-ignore
+    pub skip_vendored: bool,
+    /// Also search the repo's vendored_dirs
+    pub include_vendored: bool,
+    /// Lines of context to keep on each side of a match, for --preview
+    pub context: usize,
+}
+
+impl CommitQuery {
+    fn matches(&self, commit: &Commit) -> bool {
+        let author = commit.author();
+        if let Some(email) = &self.author_email {
+            if !author.email().is_some_and(|e| e.eq_ignore_ascii_case(email)) {
+                return false;
+            }
+        }
+        let contains = |signature: &git2::Signature, wanted: &str| signature.to_string().to_lowercase().contains(&wanted.to_lowercase());
+        if self.author.as_deref().is_some_and(|wanted| !contains(&author, wanted))
+            || self.committer.as_deref().is_some_and(|wanted| !contains(&commit.committer(), wanted))
+        {
+            return false;
+        }
+        let author = author.to_string();
+        let searched = [Some(commit.message().unwrap_or("")), self.include_author.then_some(author.as_str())];
+        self.pattern.matches_any(searched.into_iter().flatten())
+    }
+}
+
+/// Which branches a listing or search covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BranchScope {
+    #[default]
+    Local,
+    Remote,
+    All,
+}
+
+impl BranchScope {
+    pub fn from_flags(remote: bool, all: bool) -> Self {
+        match (remote, all) {
+            (_, true) => BranchScope::All,
+            (true, false) => BranchScope::Remote,
+            (false, false) => BranchScope::Local,
+        }
+    }
+    fn branch_type(self) -> Option<git2::BranchType> {
+        match self {
+            BranchScope::Local => Some(git2::BranchType::Local),
+            BranchScope::Remote => Some(git2::BranchType::Remote),
+            BranchScope::All => None,
+        }
+    }
+}
+
+/// A local or remote-tracking branch, named without its remote
+struct ScopedBranch<'r> {
+    /// Remote of a remote-tracking branch, empty for local branches
+    remote: String,
+    name: String,
+    branch: git2::Branch<'r>,
+}
+
+/// The branches `scope` covers, skipping symbolic remote refs such as origin/HEAD and
+/// branches whose name is not valid UTF-8
+fn scoped_branches<'r>(repo: &'r Repository, repo_name: &str, scope: BranchScope) -> Result<Vec<ScopedBranch<'r>>, GrepoError> {
+    let list_error = |source| BranchError::ListFailure { repo: repo_name.to_string(), source };
+    let mut branches = Vec::new();
+    for b in repo.branches(scope.branch_type()).map_err(list_error)? {
+        let (branch, branch_type) = b.map_err(list_error)?;
+        let name = match branch.name() {
+            Ok(Some(name)) => name.to_string(),
+            Ok(None) => continue,
+            Err(source) => return Err(BranchError::NameError { repo: repo_name.to_string(), source }.into()),
+        };
+        if branch_type == git2::BranchType::Local {
+            branches.push(ScopedBranch { remote: String::new(), name, branch });
+            continue;
+        }
+        if branch.get().kind() == Some(git2::ReferenceType::Symbolic) {
+            continue;
+        }
+        let remote = branch.get().name()
+            .and_then(|refname| repo.branch_remote_name(refname).ok())
+            .and_then(|remote| remote.as_str().map(String::from))
+            .unwrap_or_default();
+        let name = name.strip_prefix(&format!("{}/", remote)).unwrap_or(&name).to_string();
+        branches.push(ScopedBranch { remote, name, branch });
+    }
+    Ok(branches)
+}
+
+/// Credentials for fetching: the SSH agent for SSH remotes, the configured credential
+/// helper for HTTPS ones, otherwise the platform default. libgit2 asks again after each
+/// rejected attempt, so the attempts are capped to report the failure instead of looping.
+fn remote_credentials(config: &git2::Config) -> impl FnMut(&str, Option<&str>, git2::CredentialType) -> Result<git2::Cred, git2::Error> + '_ {
+    let mut attempts = 0;
+    move |url, username, allowed| {
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str("authentication failed (tried the SSH agent, credential helper and defaults)"));
+        }
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            return git2::Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            return git2::Cred::credential_helper(config, url, username);
+        }
+        if allowed.contains(git2::CredentialType::USERNAME) {
+            return git2::Cred::username(username.unwrap_or("git"));
+        }
+        git2::Cred::default()
+    }
+}
+
+/// "refs/remotes/origin/main" -> "origin/main", "refs/tags/v1" -> "tags/v1"
+fn short_ref_name(name: &str) -> &str {
+    name.strip_prefix("refs/remotes/").or_else(|| name.strip_prefix("refs/")).unwrap_or(name)
+}
+
+/// Create a branch at HEAD and switch to it. Only for repos whose working tree matches
+/// HEAD, where moving HEAD is the whole checkout.
+fn switch_to_new_branch(repo: &Repository, repo_name: &str, branch: &str) -> Result<(), GrepoError> {
+    let head = repo.head().and_then(|head| head.peel_to_commit())
+        .map_err(|source| RepoError::HeadFailure { repo: repo_name.to_string(), source })?;
+    repo.branch(branch, &head, false)
+        .map_err(|source| BranchError::CreateFailure { repo: repo_name.to_string(), branch: branch.to_string(), source })?;
+    repo.set_head(&format!("refs/heads/{}", branch))
+        .map_err(|source| BranchError::CheckoutFailure { repo: repo_name.to_string(), branch: branch.to_string(), source })?;
+    Ok(())
+}
+
+/// Write files to paths relative to the working tree, creating missing directories
+fn write_files(workdir: &Path, repo_name: &str, files: &[(String, impl AsRef<[u8]>)]) -> Result<(), GrepoError> {
+    for (path, contents) in files {
+        let target = workdir.join(path);
+        target.parent().map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&target, contents))
+            .map_err(|source| RepoError::FileWriteFailure { repo: repo_name.to_string(), path: path.clone(), source })?;
+    }
+    Ok(())
+}
+
+/// Whether the checked-out .gitattributes mark a path as vendored or export-ignore.
+/// Attributes that cannot be read count as unset.
+fn is_vendored(repo: &Repository, path: &str) -> bool {
+    ["linguist-vendored", "export-ignore"].iter().any(|name| {
+        repo.get_attr(Path::new(path), name, git2::AttrCheckFlags::INDEX_THEN_FILE)
+            .is_ok_and(|value| git2::AttrValue::from_string(value) == git2::AttrValue::True)
+    })
+}
+
+/// Stage the paths and commit them on HEAD as the repo's configured user. The commit is
+/// HEAD's tree with only these paths changed, so whatever else was staged stays staged
+/// and out of the commit.
+fn commit_paths<'p>(repo: &Repository, repo_name: &str, paths: impl Iterator<Item = &'p str>, message: &str) -> Result<git2::Oid, GrepoError> {
+    let write_error = |source| CommitError::WriteFailure { repo: repo_name.to_string(), source };
+    let parent = repo.head().and_then(|head| head.peel_to_commit()).map_err(write_error)?;
+    let head_tree = parent.tree().map_err(write_error)?;
+    let mut index = repo.index().map_err(write_error)?;
+    let mut update = git2::build::TreeUpdateBuilder::new();
+    for path in paths {
+        index.add_path(Path::new(path)).map_err(write_error)?;
+        let entry = index.get_path(Path::new(path), 0)
+            .ok_or_else(|| write_error(git2::Error::from_str(&format!("{} is not in the index after staging it", path))))?;
+        let mode = match entry.mode {
+            0o100755 => git2::FileMode::BlobExecutable,
+            0o120000 => git2::FileMode::Link,
+            _ => git2::FileMode::Blob,
+        };
+        update.upsert(path, entry.id, mode);
+    }
+    let tree = update.create_updated(repo, &head_tree).and_then(|oid| repo.find_tree(oid)).map_err(write_error)?;
+    let signature = repo.signature().map_err(write_error)?;
+    let oid = repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &[&parent]).map_err(write_error)?;
+    index.write().map_err(write_error)?;
+    Ok(oid)
+}
+
+/// Set on Ctrl-C: workers stop taking repos and long walks stop early, so what was found
+/// so far can still be printed
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Run an operation against every watched repo, collecting failures instead of
+/// aborting unless running in strict mode. Once interrupted no further repos are started.
+fn for_each_repo<T: ScopeMatch + Send>(
+    cfg: &ConfigFile,
+    operation: impl Fn(&mut GitRepo) -> Result<T, GrepoError> + Sync,
+) -> Result<RepoResults<T>, GrepoError> {
+    let names = watched_repo_names(cfg);
+    let jobs = cfg.runtime.job_count();
+
+    // workers take the next repo off a shared counter; results are put back in watch-list order.
+    // A strict run stops handing out repos once one has failed.
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let outcomes = Mutex::new(Vec::with_capacity(names.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(names.len()) {
+            scope.spawn(|| loop {
+                if failed.load(Ordering::Relaxed) || interrupted() {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(repo) = names.get(index) else { break };
+                let result = operation(&mut GitRepo::new(cfg.clone(), repo.clone()));
+                if result.is_err() && cfg.runtime.strict {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                outcomes.lock().unwrap_or_else(PoisonError::into_inner).push((index, result));
+            });
+        }
+    });
+    let mut outcomes = outcomes.into_inner().unwrap_or_else(PoisonError::into_inner);
+    outcomes.sort_by_key(|(index, _)| *index);
+
+    let mut out = RepoResults { results: Vec::new(), failures: Vec::new(), matched: Vec::new() };
+    for (index, outcome) in outcomes {
+        let repo = &names[index];
+        match outcome {
+            Ok(result) => {
+                if result.is_match() {
+                    out.matched.push(repo.clone());
+                }
+                out.results.push(result)
+            }
+            Err(e) if cfg.runtime.strict => return Err(e),
+            Err(e) => out.failures.push(RepoFailure {
+                repo: Arc::from(repo.as_str()),
+                reason: Arc::from(e.to_string()),
+            }),
+        }
+    }
+    Ok(out)
+}
+
+/// Watched repos followed by the virtual repos defined in the config, or only the
+/// repos a scope flag selected for this invocation, narrowed to the --group if any
+pub fn watched_repo_names(cfg: &ConfigFile) -> Vec<String> {
+    let mut names = cfg.repos.clone();
+    if !cfg.runtime.scoped {
+        names.extend(cfg.virtual_repos.iter().map(|v| v.name.clone()).filter(|name| !cfg.repos.contains(name)));
+    }
+    if let Some(group) = cfg.runtime.group.as_ref().and_then(|group| cfg.groups.get(group)) {
+        names.retain(|name| group.repos.contains(name));
+    }
+    names
+}
+
+/// Like `for_each_repo`, but skips repos that are not safe to modify
+fn for_each_mutable_repo<T: ScopeMatch + Send>(
+    cfg: &ConfigFile,
+    operation: impl Fn(&mut GitRepo) -> Result<T, GrepoError> + Sync,
+) -> Result<RepoResults<T>, GrepoError> {
+    for_each_repo(cfg, |watchobj| {
+        watchobj.guard_mutation()?;
+        operation(watchobj)
+    })
+}
+
+/// Commits reachable from HEAD, newest first, stopping at the first one older than `since`.
+/// Repos without a HEAD yield nothing.
+fn head_commits_since<'r>(repo: &'r Repository, repo_name: &str, since: i64) -> Result<Vec<Commit<'r>>, GrepoError> {
+    if repo.head().is_err() {
+        return Ok(Vec::new());
+    }
+    let revwalk_error = |source| CommitError::RevWalkFailure { repo: repo_name.to_string(), branch: "HEAD".to_string(), source };
+    let mut revwalk = repo.revwalk().map_err(revwalk_error)?;
+    revwalk.set_sorting(Sort::TIME).map_err(revwalk_error)?;
+    revwalk.push_head().map_err(revwalk_error)?;
+    Ok(revwalk
+        .filter_map(|oid| oid.ok())
+        .filter_map(|oid| repo.find_commit(oid).ok())
+        .take_while(|commit| commit.time().seconds() >= since)
+        .collect())
+}
+
+fn digest_entry<'a>(entries: &'a mut HashMap<String, DigestEntry>, repo: &Arc<str>, author: &str) -> &'a mut DigestEntry {
+    entries.entry(author.to_string()).or_insert_with(|| DigestEntry {
+        repo: repo.clone(),
+        author: Arc::from(author),
+        commits: 0,
+        merged: Vec::new(),
+        created: Vec::new(),
+    })
+}
+
+/// Branch name from a merge commit summary such as "Merge branch 'x'" or
+/// "Merge pull request #12 from org/x"
+fn merged_branch_name(summary: &str) -> Option<String> {
+    if let Some(rest) = summary.strip_prefix("Merge branch '") {
+        return rest.split('\'').next().map(String::from);
+    }
+    if let Some(rest) = summary.strip_prefix("Merge pull request ") {
+        return rest.split_once(" from ").map(|(_, source)| source.split_whitespace().next().unwrap_or(source).to_string());
+    }
+    if let Some(rest) = summary.strip_prefix("Merge remote-tracking branch '") {
+        return rest.split('\'').next().map(String::from);
+    }
+    None
+}
+
+/// File name part for a patch, like format-patch: "Fix the parser!" -> "Fix-the-parser"
+fn patch_slug(summary: &str) -> String {
+    let mut slug = String::new();
+    for c in summary.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 52 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches(['-', '.']);
+    if slug.is_empty() { "patch".to_string() } else { slug.to_string() }
+}
+
+/// The repo's default branch: what origin/HEAD points at, else a local main or master,
+/// else the current HEAD
+fn default_branch(repo: &Repository) -> Option<(String, git2::Oid)> {
+    if let Ok(origin_head) = repo.find_reference("refs/remotes/origin/HEAD") {
+        if let (Some(target), Ok(resolved)) = (origin_head.symbolic_target().map(String::from), origin_head.resolve()) {
+            let name = target.strip_prefix("refs/remotes/").unwrap_or(&target).to_string();
+            if let Some(oid) = resolved.target() {
+                return Some((name, oid));
+            }
+        }
+    }
+    for name in ["main", "master"] {
+        if let Ok(branch) = repo.find_branch(name, git2::BranchType::Local) {
+            if let Some(oid) = branch.get().target() {
+                return Some((name.to_string(), oid));
+            }
+        }
+    }
+    let head = repo.head().ok()?;
+    Some((head.shorthand().unwrap_or("HEAD").to_string(), head.target()?))
+}
+
+/// Whether a commit changed anything under `path` compared to its first parent
+fn touches_path(repo: &Repository, commit: &Commit, path: &str) -> bool {
+    let Ok(tree) = commit.tree() else { return false };
+    let parent_tree = commit.parent(0).ok().and_then(|parent| parent.tree().ok());
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(DiffOptions::new().pathspec(path)))
+        .map(|diff| diff.deltas().len() > 0)
+        .unwrap_or(false)
+}
+
+/// Short name of the config file a value was read from
+fn config_level_name(level: ConfigLevel) -> &'static str {
+    match level {
+        ConfigLevel::ProgramData | ConfigLevel::System => "system",
+        ConfigLevel::XDG | ConfigLevel::Global => "global",
+        ConfigLevel::Local => "local",
+        ConfigLevel::App => "app",
+        ConfigLevel::Highest => "highest",
+    }
+}
+
+/// Name of the operation a repo is in the middle of, if any
+fn in_progress_operation(state: RepositoryState) -> Option<&'static str> {
+    match state {
+        RepositoryState::Clean => None,
+        RepositoryState::Merge => Some("merge"),
+        RepositoryState::Revert | RepositoryState::RevertSequence => Some("revert"),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some("cherry-pick"),
+        RepositoryState::Bisect => Some("bisect"),
+        RepositoryState::Rebase | RepositoryState::RebaseInteractive | RepositoryState::RebaseMerge => Some("rebase"),
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => Some("am"),
+    }
+}
+
+/// Total size in bytes of all files below a directory
+fn dir_size(path: &Path) -> u64 {
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| match entry.metadata() {
+                    Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+                    Ok(meta) => meta.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Human readable byte size
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// Whether the git binary can be run (checked once per process)
+fn git_cli_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        Command::new("git")
+            .arg("--version")
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    })
+}
+
+/// Run a git command against the given git directory and return its stdout
+fn run_git(git_dir: &Path, args: &[&str]) -> Result<String, GrepoError> {
+    let command = args.join(" ");
+    let path = git_dir.display().to_string();
+    let output = Command::new("git")
+        // repos run in parallel, so a credential prompt could not be answered
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .arg("--git-dir")
+        .arg(git_dir)
+        .args(args)
+        .output()
+        .map_err(|source| CliError::SpawnFailure { command: command.clone(), path: path.clone(), source })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let stderr = if stderr.is_empty() { format!("exited with {}", output.status) } else { stderr };
+        return Err(CliError::CommandFailure { command, path, stderr }.into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+
+pub fn branch_owners(cfg: ConfigFile, scope: BranchScope) -> Result<RepoResults<BranchOwnerList>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.branch_owners(scope))
+}
+
+/// Branches whose tip commit was authored by someone whose name or email contains `author`
+pub fn branches_by_author(cfg: ConfigFile, author: &str) -> Result<RepoResults<Vec<BranchOwner>>, GrepoError> {
+    let author = author.to_lowercase();
+    for_each_repo(&cfg, |watchobj| {
+        Ok(watchobj.branch_owners(BranchScope::Local)?
+            .owners
+            .into_iter()
+            .filter(|owner| owner.tip_authored_by(&author))
+            .collect())
+    })
+}
+
+pub fn search_repos(cfg: ConfigFile, pattern: &Matcher, scope: BranchScope, script: Option<&script::Filter>) -> Result<RepoResults<Vec<BranchInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| {
+        let mut found = Vec::new();
+        for binfo in watchobj.all_branches(scope)?.into_iter().filter(|binfo| pattern.is_match(&binfo.branch)) {
+            if let Some(script) = script {
+                let matched = script.matches_branch(&binfo.repo, &binfo.branch, &binfo.remote).map_err(|message| BranchError::ScriptFailure {
+                    repo: binfo.repo.to_string(),
+                    branch: binfo.branch.to_string(),
+                    message,
+                })?;
+                if !matched {
+                    continue;
+                }
+            }
+            found.push(binfo);
+        }
+        Ok(found)
+    })
+}
+
+pub fn get_current_branch_name(cfg: ConfigFile) -> Result<RepoResults<CurrentBranchInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| {
+        let counts = watchobj.upstream_counts()?;
+        Ok(CurrentBranchInfo {
+            repo: watchobj.repo_name.clone(),
+            branch: Arc::from(watchobj.current_branch_name()?),
+            ahead: counts.map(|(ahead, _)| ahead),
+            behind: counts.map(|(_, behind)| behind),
+            state: Arc::from(watchobj.state_label()?),
+        })
+    })
+}
+
+/// Check the directory of each name `repo add` was given, --jobs at a time. Only the
+/// directory checks are filled in, in the order given.
+pub fn inspect_candidates(cfg: &ConfigFile, names: &[String]) -> Vec<AddCandidate> {
+    let next = AtomicUsize::new(0);
+    let candidates = Mutex::new(Vec::with_capacity(names.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..cfg.runtime.job_count().min(names.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(name) = names.get(index) else { break };
+                let repo = GitRepo::new(cfg.clone(), name.clone()).open().ok();
+                let candidate = AddCandidate {
+                    repo: Arc::from(name.as_str()),
+                    // absolute names replace the base path when joined
+                    exists: Some(Path::new(&*cfg.base_path).join(name).is_dir()),
+                    git_repo: Some(repo.is_some()),
+                    bare: repo.map(|repo| repo.is_bare()),
+                    watched: None,
+                    status: Arc::from(""),
+                };
+                candidates.lock().unwrap_or_else(PoisonError::into_inner).push((index, candidate));
+            });
+        }
+    });
+    let mut candidates = candidates.into_inner().unwrap_or_else(PoisonError::into_inner);
+    candidates.sort_by_key(|(index, _)| *index);
+    candidates.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+pub fn get_valid_repo(cfg: ConfigFile, repo_name: String) -> bool {
+    match GitRepo::new(cfg, repo_name).open() {
+        Ok(_) => { true }
+        Err(_) => { false }
+    }
+}
+
+pub fn search_commits(cfg: ConfigFile, query: &CommitQuery) -> Result<RepoResults<Vec<RepoBranchCommit>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.search_commits(query))
+}
+
+/// Watched repos that are shallow clones. Repos that fail to open are left to the
+/// command that follows to report.
+pub fn shallow_repos(mut cfg: ConfigFile) -> Vec<String> {
+    cfg.runtime.strict = false;
+    for_each_repo(&cfg, |watchobj| Ok(watchobj.open()?.is_shallow().then(|| watchobj.repo_name.to_string())))
+        .map(|found| found.results.into_iter().flatten().collect())
+        .unwrap_or_default()
+}
+
+/// Fetch `depth` more commits of history into every shallow watched repo
+pub fn deepen_shallow_repos(cfg: ConfigFile, depth: u32) -> Result<RepoResults<Option<Arc<str>>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| {
+        if !watchobj.open()?.is_shallow() {
+            return Ok(None);
+        }
+        watchobj.git_cli(&["fetch", &format!("--deepen={}", depth)])?;
+        Ok(Some(watchobj.repo_name.clone()))
+    })
+}
+
+pub fn apply_patch(cfg: ConfigFile, patch: &[u8], commit: bool, message: Option<&str>) -> Result<RepoResults<ApplyInfo>, GrepoError> {
+    git2::Diff::from_buffer(patch).map_err(|source| CommitError::PatchUnparseable { source })?;
+    for_each_mutable_repo(&cfg, |watchobj| watchobj.apply_patch(patch, commit, message))
+}
+
+pub fn sed(cfg: ConfigFile, pattern: &regex::Regex, replacement: &str, path_glob: Option<&str>, mode: SedMode) -> Result<RepoResults<SedInfo>, GrepoError> {
+    for_each_mutable_repo(&cfg, |watchobj| watchobj.sed(pattern, replacement, path_glob, &mode))
+}
+
+/// Write the found commits as patch files under `dir/<repo>/`, each commit once even when
+/// it was found on several branches. A repo whose patches cannot be written is reported as
+/// a failure while the other repos are still exported.
+pub fn export_patches(mut cfg: ConfigFile, found: &[&RepoBranchCommit], dir: &Path) -> Result<RepoResults<PatchExport>, GrepoError> {
+    let mut per_repo: Vec<(&str, Vec<&str>)> = Vec::new();
+    for commit in found {
+        match per_repo.iter_mut().find(|(repo, _)| *repo == &*commit.repo) {
+            Some((_, ids)) if ids.contains(&&*commit.commit) => {}
+            Some((_, ids)) => ids.push(&commit.commit),
+            None => per_repo.push((&commit.repo, vec![&commit.commit])),
+        }
+    }
+    cfg.repos = per_repo.iter().map(|(repo, _)| repo.to_string()).collect();
+    cfg.runtime.scoped = true;
+    for_each_repo(&cfg, |watchobj| {
+        let ids = per_repo.iter().find(|(repo, _)| *repo == &*watchobj.repo_name).map_or(&[][..], |(_, ids)| ids.as_slice());
+        // virtual repo names may contain '/', keep them as nested directories
+        let target = dir.join(watchobj.repo_name.trim_start_matches('/'));
+        let patches = watchobj.write_patches(ids, &target)?;
+        Ok(PatchExport { repo: watchobj.repo_name.clone(), dir: target, patches: patches.len() })
+    })
+}
+
+/// user.email from the global/system git config, the identity `--mine` filters on
+pub fn global_user_email() -> Option<String> {
+    git2::Config::open_default().ok()?.get_string("user.email").ok()
+}
+
+pub fn commit_graph_status(cfg: ConfigFile, write: bool) -> Result<RepoResults<CommitGraphInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| {
+        let status = if write {
+            watchobj.write_commit_graph()?;
+            "written"
+        } else if watchobj.has_commit_graph()? {
+            "present"
+        } else {
+            "missing"
+        };
+        Ok(CommitGraphInfo {
+            repo: watchobj.repo_name.clone(),
+            status: Arc::from(status),
+        })
+    })
+}
+
+pub fn run_maintenance(cfg: ConfigFile, prune_days: u32) -> Result<RepoResults<MaintenanceInfo>, GrepoError> {
+    let mut lookup_cfg = cfg.clone();
+    lookup_cfg.runtime.strict = false;
+    let sharing = audit_alternates(lookup_cfg).map(|found| found.results).unwrap_or_default();
+    // housekeeping never touches the worktree or index, so uncommitted changes do not matter
+    for_each_repo(&cfg, |watchobj| {
+        let shared_with = sharing.iter()
+            .find(|info| info.repo == watchobj.repo_name)
+            .map(|info| info.shared_with.clone())
+            .unwrap_or_default();
+        let (before, after) = watchobj.run_maintenance(prune_days, !shared_with.is_empty())?;
+        let mut status = if after > before {
+            format!("grew by {}", format_size(after - before))
+        } else {
+            format!("saved {}", format_size(before - after))
+        };
+        if !shared_with.is_empty() {
+            status.push_str(&format!(", prune skipped (shared with {})", shared_with.join(", ")));
+        }
+        Ok(MaintenanceInfo {
+            repo: watchobj.repo_name.clone(),
+            before: Arc::from(format_size(before)),
+            after: Arc::from(format_size(after)),
+            status: Arc::from(status),
+        })
+    })
+}
+
+/// Which watched repos borrow objects from other object stores, and which lend theirs
+pub fn audit_alternates(cfg: ConfigFile) -> Result<RepoResults<AlternatesInfo>, GrepoError> {
+    let stores = for_each_repo(&cfg, |watchobj| watchobj.object_stores())?;
+    // name alternates after the watched repo owning them where possible
+    let display_store = |path: &PathBuf| {
+        stores.results.iter()
+            .find(|store| &store.objects_dir == path)
+            .map(|store| store.repo.to_string())
+            .unwrap_or_else(|| path.display().to_string())
+    };
+    let results = stores.results.iter()
+        .map(|store| AlternatesInfo {
+            repo: store.repo.clone(),
+            borrows_from: store.alternates.iter().map(display_store).collect(),
+            shared_with: stores.results.iter()
+                .filter(|other| other.alternates.contains(&store.objects_dir))
+                .map(|other| other.repo.to_string())
+                .collect(),
+        })
+        .collect();
+    Ok(RepoResults { results, failures: stores.failures, matched: stores.matched })
+}
+
+pub fn find_conflicts(cfg: ConfigFile) -> Result<RepoResults<Vec<ConflictInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.conflicted_paths())
+}
+
+pub fn branch_divergence(cfg: ConfigFile) -> Result<RepoResults<Vec<DivergenceInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.branch_divergence())
+}
+
+pub fn plan_branch_switch(cfg: ConfigFile, from: &str, to: &str) -> Result<RepoResults<BranchSwitch>, GrepoError> {
+    let planned = for_each_repo(&cfg, |watchobj| watchobj.plan_switch(from, to))?;
+    Ok(RepoResults {
+        results: planned.results.into_iter().flatten().collect(),
+        failures: planned.failures,
+        matched: planned.matched,
+    })
+}
+
+pub fn switch_branches(cfg: ConfigFile, from: &str, to: &str) -> Result<RepoResults<BranchSwitch>, GrepoError> {
+    let switched = for_each_repo(&cfg, |watchobj| {
+        let planned = watchobj.plan_switch(from, to)?;
+        if planned.is_some() {
+            watchobj.checkout_branch(to)?;
+        }
+        Ok(planned.map(|switch| BranchSwitch { action: Arc::from("switched"), ..switch }))
+    })?;
+    Ok(RepoResults {
+        results: switched.results.into_iter().flatten().collect(),
+        failures: switched.failures,
+        matched: switched.matched,
+    })
+}
+
+pub fn checkout_branches(cfg: ConfigFile, name: &str, create: bool) -> Result<RepoResults<BranchSwitch>, GrepoError> {
+    for_each_mutable_repo(&cfg, |watchobj| watchobj.checkout(name, create))
+}
+
+/// URL of a repo's origin remote, if it has one
+pub fn origin_url(cfg: ConfigFile, repo_name: String) -> Option<String> {
+    let repo = GitRepo::new(cfg, repo_name).open().ok()?;
+    let remote = repo.find_remote("origin").ok()?;
+    remote.url().map(String::from)
+}
+
+pub fn unpushed_work(cfg: ConfigFile, repo_name: String) -> Result<Vec<UnpushedBranch>, GrepoError> {
+    GitRepo::new(cfg, repo_name).unpushed_branches()
+}
+
+pub fn graph_commits(cfg: ConfigFile, repo_name: String, limit: usize) -> Result<Vec<GraphCommit>, GrepoError> {
+    GitRepo::new(cfg, repo_name).graph_commits(limit)
+}
+
+pub fn branch_graphs(cfg: ConfigFile) -> Result<RepoResults<RepoGraph>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.branch_graph())
+}
+
+pub fn describe_branch(cfg: ConfigFile, repo_name: String, branch: &str, text: &str) -> Result<(), GrepoError> {
+    GitRepo::new(cfg, repo_name).describe_branch(branch, text)
+}
+
+/// Description of a local branch in one repo, None when it has none
+pub fn branch_description(cfg: ConfigFile, repo_name: String, branch: &str) -> Result<Option<String>, GrepoError> {
+    GitRepo::new(cfg, repo_name).config_value(&format!("branch.{}.description", branch))
+}
+
+pub fn archive_repo(cfg: ConfigFile, repo_name: String, reference: &str, format: ArchiveFormat, output_dir: &str, skip_vendored: bool) -> Result<PathBuf, GrepoError> {
+    let output_dir = std::path::absolute(output_dir).unwrap_or_else(|_| PathBuf::from(output_dir));
+    GitRepo::new(cfg, repo_name).archive(reference, format, &output_dir, skip_vendored)
+}
+
+pub fn recent_owners(cfg: ConfigFile, path_glob: &str, since: i64, top: usize) -> Result<RepoResults<Vec<OwnerInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.recent_owners(path_glob, since, top))
+}
+
+pub fn search_diffs(cfg: ConfigFile, query: &DiffQuery) -> Result<RepoResults<Vec<DiffMatch>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.search_diffs(query))
+}
+
+/// Repos that have the commit `hash` names, with the branches containing it
+pub fn search_hash(cfg: ConfigFile, hash: &str, scope: BranchScope) -> Result<RepoResults<Option<HashMatch>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.search_hash(hash, scope))
+}
+
+pub fn search_content(cfg: ConfigFile, query: &ContentQuery) -> Result<RepoResults<Vec<ContentMatch>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.search_content(query))
+}
+
+pub fn branch_churn(cfg: ConfigFile, since: i64) -> Result<RepoResults<BranchChurnInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.branch_churn(since))
+}
+
+pub fn commit_times(cfg: ConfigFile, since: i64) -> Result<RepoResults<Vec<CommitTime>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.commit_times(since))
+}
+
+pub fn digest(cfg: ConfigFile, since: i64) -> Result<RepoResults<Vec<DigestEntry>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.digest(since))
+}
+
+pub fn lint_commits(cfg: ConfigFile, since: i64) -> Result<RepoResults<Vec<LintViolation>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.lint_commits(since))
+}
+
+pub fn install_hooks(cfg: ConfigFile, source: &str, symlink: bool) -> Result<RepoResults<Vec<HookInfo>>, GrepoError> {
+    let source_hooks = hooks::source_hooks(Path::new(source))?;
+    for_each_repo(&cfg, |watchobj| watchobj.install_hooks(&source_hooks, symlink))
+}
+
+pub fn hooks_status(cfg: ConfigFile, source: &str) -> Result<RepoResults<Vec<HookInfo>>, GrepoError> {
+    let source_hooks = hooks::source_hooks(Path::new(source))?;
+    for_each_repo(&cfg, |watchobj| watchobj.hooks_status(&source_hooks))
+}
+
+pub fn get_config_values(cfg: ConfigFile, key: &str) -> Result<RepoResults<ConfigKeyInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| {
+        Ok(ConfigKeyInfo {
+            repo: watchobj.repo_name.clone(),
+            value: Arc::from(watchobj.config_value(key)?.unwrap_or_else(|| "** Not set **".to_string())),
+        })
+    })
+}
+
+pub fn plan_config_values(cfg: ConfigFile, key: &str, value: &str) -> Result<RepoResults<ConfigValueInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.plan_config_value(key, value))
+}
+
+pub fn set_config_values(cfg: ConfigFile, key: &str, value: &str) -> Result<RepoResults<ConfigValueInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| {
+        let planned = watchobj.plan_config_value(key, value)?;
+        if &*planned.action == "unchanged" {
+            return Ok(planned);
+        }
+        watchobj.set_config_value(key, value)?;
+        Ok(ConfigValueInfo { action: Arc::from("set"), ..planned })
+    })
+}
+
+pub fn audit_identity(cfg: ConfigFile) -> Result<RepoResults<IdentityInfo>, GrepoError> {
+    let allowed_domains = cfg.allowed_email_domains.clone();
+    for_each_repo(&cfg, |watchobj| watchobj.identity(&allowed_domains))
+}
+
+pub fn sync_files(cfg: ConfigFile, files: &[sync::SyncFile], commit: Option<(&str, &str)>) -> Result<RepoResults<SyncInfo>, GrepoError> {
+    for_each_mutable_repo(&cfg, |watchobj| watchobj.sync_files(files, commit))
+}
+
+pub fn repo_infos(cfg: ConfigFile) -> Result<RepoResults<RepoInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.info())
+}
+
+pub fn stale_repos(cfg: ConfigFile, since: i64) -> Result<RepoResults<Option<StaleInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.stale(since))
+}
+
+pub fn list_tags(cfg: ConfigFile, pattern: Option<&Matcher>) -> Result<RepoResults<Vec<TagInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.tags(pattern))
+}
+
+pub fn list_stashes(cfg: ConfigFile) -> Result<RepoResults<Vec<StashInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.stashes())
+}
+
+pub fn repo_statuses(cfg: ConfigFile) -> Result<RepoResults<RepoStatusInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.status())
+}
+
+/// Fetch every repo, leaving out exclude_from_fetch groups unless a scope flag or
+/// --group picked the repos
+pub fn fetch_repos(mut cfg: ConfigFile, remote: &str, prune: bool, prune_tags: bool) -> Result<RepoResults<FetchInfo>, GrepoError> {
+    if !cfg.runtime.scoped && cfg.runtime.group.is_none() {
+        cfg.repos = watched_repo_names(&cfg)
+            .into_iter()
+            .filter(|name| !cfg.groups_of(name).any(|group| group.exclude_from_fetch))
+            .collect();
+        cfg.runtime.scoped = true;
+    }
+    for_each_repo(&cfg, |watchobj| watchobj.fetch(remote, prune, prune_tags))
+}
+
+pub fn pull_repos(cfg: ConfigFile, ff_only: bool) -> Result<RepoResults<PullInfo>, GrepoError> {
+    for_each_mutable_repo(&cfg, |watchobj| watchobj.pull(ff_only))
+}
+
+pub fn branch_diffstats(cfg: ConfigFile, branch: &str) -> Result<RepoResults<Option<DiffstatInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.diffstat(branch))
+}
+
+pub fn list_protected_branches(cfg: ConfigFile) -> Result<RepoResults<Vec<ProtectedBranchInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.protected_branches())
+}
+
+pub fn gone_branches(cfg: ConfigFile, delete: bool) -> Result<RepoResults<Vec<GoneBranchInfo>>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.gone_branches(delete))
+}
+
+pub fn set_missing_upstreams(cfg: ConfigFile, remote: &str) -> Result<RepoResults<UpstreamSetupInfo>, GrepoError> {
+    for_each_repo(&cfg, |watchobj| watchobj.set_missing_upstream(remote))
 }
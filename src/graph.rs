@@ -0,0 +1,238 @@
+/// A commit to draw in a graph
+pub struct GraphCommit {
+    pub id: String,
+    pub parents: Vec<String>,
+    /// Text shown after the node, e.g. "abc1234 (main) Fix parser"
+    pub label: String,
+}
+
+/// Render commits (children before parents) as an ASCII graph in the style of
+/// `git log --graph --oneline`. Parents outside the list keep their lane open.
+pub fn render_ascii(commits: &[GraphCommit]) -> Vec<String> {
+    // lanes[i] is the commit expected next in column i
+    let mut lanes: Vec<Option<&str>> = Vec::new();
+    let mut lines = Vec::new();
+    for commit in commits {
+        let column = match lanes.iter().position(|lane| *lane == Some(commit.id.as_str())) {
+            Some(column) => column,
+            None => free_lane(&mut lanes),
+        };
+        lines.push(format!("{} {}", lane_row(&lanes, Some(column)), commit.label));
+
+        // the first parent continues this column unless another lane already expects it
+        lanes[column] = None;
+        let mut joined = None;
+        match commit.parents.first() {
+            Some(parent) if lanes.contains(&Some(parent.as_str())) => {
+                joined = lanes.iter().position(|lane| *lane == Some(parent.as_str()));
+            }
+            Some(parent) => lanes[column] = Some(parent.as_str()),
+            None => {}
+        }
+        let mut forked = Vec::new();
+        for parent in commit.parents.iter().skip(1) {
+            if lanes.contains(&Some(parent.as_str())) {
+                continue;
+            }
+            // branch off to the right so the edge can be drawn from this column
+            let lane = match lanes.iter().skip(column + 1).position(Option::is_none) {
+                Some(offset) => column + 1 + offset,
+                None => {
+                    lanes.push(None);
+                    lanes.len() - 1
+                }
+            };
+            lanes[lane] = Some(parent.as_str());
+            forked.push(lane);
+        }
+
+        if let Some(target) = joined {
+            lines.push(join_row(&lanes, column, target));
+        }
+        if !forked.is_empty() {
+            lines.push(connector_row(&lanes, &forked, '\\'));
+        }
+        while lanes.last() == Some(&None) {
+            lanes.pop();
+        }
+    }
+    lines
+}
+
+/// First unused column, adding one when all are taken
+fn free_lane(lanes: &mut Vec<Option<&str>>) -> usize {
+    match lanes.iter().position(Option::is_none) {
+        Some(lane) => lane,
+        None => {
+            lanes.push(None);
+            lanes.len() - 1
+        }
+    }
+}
+
+fn lane_row(lanes: &[Option<&str>], node: Option<usize>) -> String {
+    lanes
+        .iter()
+        .enumerate()
+        .map(|(i, lane)| match (Some(i) == node, lane) {
+            (true, _) => "*",
+            (false, Some(_)) => "|",
+            (false, None) => " ",
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Row drawing `edge` between each of `columns` and the column to its left, for lanes
+/// that just merged in ('/') or branched off ('\\')
+fn connector_row(lanes: &[Option<&str>], columns: &[usize], edge: char) -> String {
+    let width = lanes.len().max(columns.iter().max().map_or(0, |c| c + 1));
+    let mut row = vec![' '; width * 2];
+    for (i, lane) in lanes.iter().enumerate() {
+        if lane.is_some() && !columns.contains(&i) {
+            row[i * 2] = '|';
+        }
+    }
+    for column in columns.iter().filter(|c| **c > 0) {
+        row[column * 2 - 1] = edge;
+    }
+    row.into_iter().collect::<String>().trim_end().to_string()
+}
+
+/// Row drawing the closed lane `from` running into lane `target`, which already expects
+/// its commit: '/' toward a lane to the left, '\\' toward one to the right, with '_' under
+/// the lanes it passes
+fn join_row(lanes: &[Option<&str>], from: usize, target: usize) -> String {
+    let mut row = vec![' '; lanes.len().max(from + 1) * 2];
+    for (i, lane) in lanes.iter().enumerate() {
+        if lane.is_some() {
+            row[i * 2] = '|';
+        }
+    }
+    let (edge, gap) = if target < from { ('/', from * 2 - 1) } else { ('\\', from * 2 + 1) };
+    row[gap] = edge;
+    let (left, right) = if target < from { (target * 2 + 1, gap) } else { (gap + 1, target * 2 - 1) };
+    for cell in row.iter_mut().take(right).skip(left) {
+        if *cell == ' ' {
+            *cell = '_';
+        }
+    }
+    row.into_iter().collect::<String>().trim_end().to_string()
+}
+
+/// A repo's local branches and how they relate, for DOT export
+pub struct RepoGraph {
+    pub repo: String,
+    /// Branch HEAD points at, which merges are measured against
+    pub head: Option<String>,
+    pub branches: Vec<BranchNode>,
+    /// Root commit of HEAD, shared roots mark forks of the same project
+    pub root: Option<String>,
+}
+
+pub struct BranchNode {
+    pub name: String,
+    /// Remote-tracking branch configured as upstream
+    pub upstream: Option<String>,
+    /// Tip is already contained in the HEAD branch
+    pub merged: bool,
+}
+
+/// Render repos as DOT clusters of branch nodes, with edges to tracked upstreams and
+/// from merged branches into HEAD, and optionally fork edges between repos that share a root
+pub fn render_dot(repos: &[RepoGraph], forks: bool) -> String {
+    let mut out = String::from("digraph grepo {\n  rankdir=LR;\n  node [shape=box];\n");
+    for (i, repo) in repos.iter().enumerate() {
+        out.push_str(&format!("  subgraph cluster_{} {{\n    label={};\n", i, quote(&repo.repo)));
+        for branch in &repo.branches {
+            let style = if repo.head.as_deref() == Some(branch.name.as_str()) { ", style=bold" } else { "" };
+            out.push_str(&format!("    {} [label={}{}];\n", node_id(&repo.repo, &branch.name), quote(&branch.name), style));
+            if let Some(upstream) = &branch.upstream {
+                out.push_str(&format!("    {} [label={}, style=dashed];\n", node_id(&repo.repo, upstream), quote(upstream)));
+            }
+        }
+        out.push_str("  }\n");
+        for branch in &repo.branches {
+            let from = node_id(&repo.repo, &branch.name);
+            if let Some(upstream) = &branch.upstream {
+                out.push_str(&format!("  {} -> {} [label=\"tracks\", style=dashed];\n", from, node_id(&repo.repo, upstream)));
+            }
+            match &repo.head {
+                Some(head) if branch.merged && *head != branch.name => {
+                    out.push_str(&format!("  {} -> {} [label=\"merged\"];\n", from, node_id(&repo.repo, head)));
+                }
+                _ => {}
+            }
+        }
+    }
+    if forks {
+        for (i, repo) in repos.iter().enumerate() {
+            for other in repos.iter().skip(i + 1) {
+                let (Some(root), Some(other_root)) = (&repo.root, &other.root) else { continue };
+                let (Some(head), Some(other_head)) = (&repo.head, &other.head) else { continue };
+                if root == other_root {
+                    out.push_str(&format!(
+                        "  {} -> {} [label=\"fork\", dir=none, style=dotted];\n",
+                        node_id(&repo.repo, head),
+                        node_id(&other.repo, other_head)
+                    ));
+                }
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn node_id(repo: &str, branch: &str) -> String {
+    quote(&format!("{}:{}", repo, branch))
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(id: &str, parents: &[&str]) -> GraphCommit {
+        GraphCommit { id: id.to_string(), parents: parents.iter().map(|p| p.to_string()).collect(), label: id.to_string() }
+    }
+
+    #[test]
+    fn linear_history_is_one_column() {
+        let lines = render_ascii(&[commit("c", &["b"]), commit("b", &["a"]), commit("a", &[])]);
+        assert_eq!(lines, ["* c", "* b", "* a"]);
+    }
+
+    #[test]
+    fn merge_forks_to_the_right_and_joins_back_left() {
+        let lines = render_ascii(&[commit("m", &["x", "y"]), commit("y", &["x"]), commit("x", &[])]);
+        assert_eq!(lines, ["* m", "|\\", "| * y", "|/", "* x"]);
+    }
+
+    #[test]
+    fn lane_joins_a_lane_two_columns_to_the_left() {
+        let lines = render_ascii(&[
+            commit("a", &["p"]),
+            commit("b", &["q"]),
+            commit("c", &["p"]),
+            commit("p", &["q"]),
+            commit("q", &[]),
+        ]);
+        assert_eq!(lines, ["* a", "| * b", "| | * c", "|_|/", "* | p", " \\|", "  * q"]);
+    }
+
+    #[test]
+    fn lane_joins_a_lane_to_the_right() {
+        let lines = render_ascii(&[commit("a", &["p"]), commit("b", &["q"]), commit("p", &["q"]), commit("q", &[])]);
+        assert_eq!(lines, ["* a", "| * b", "* | p", " \\|", "  * q"]);
+    }
+
+    #[test]
+    fn parents_outside_the_list_keep_their_lane() {
+        let lines = render_ascii(&[commit("b", &["a"]), commit("c", &["z"])]);
+        assert_eq!(lines, ["* b", "| * c"]);
+    }
+}
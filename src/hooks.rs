@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("Could not read hook source directory {path}: {source}")]
+    SourceUnreadable { path: String, source: std::io::Error },
+    #[error("Could not write hook {path}: {source}")]
+    WriteFailure { path: String, source: std::io::Error },
+}
+
+/// How an installed hook compares to the shared source
+pub enum HookState {
+    Installed,
+    Differs,
+    Missing,
+}
+impl HookState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HookState::Installed => "installed",
+            HookState::Differs => "differs",
+            HookState::Missing => "missing",
+        }
+    }
+}
+
+/// Hook files in the shared source directory, skipping samples and hidden files
+pub fn source_hooks(source: &Path) -> Result<Vec<PathBuf>, HookError> {
+    let unreadable = |source_error| HookError::SourceUnreadable { path: source.display().to_string(), source: source_error };
+    let mut hooks = fs::read_dir(source)
+        .map_err(unreadable)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            !name.starts_with('.') && !name.ends_with(".sample")
+        })
+        .map(|path| fs::canonicalize(&path).unwrap_or(path))
+        .collect::<Vec<PathBuf>>();
+    hooks.sort();
+    Ok(hooks)
+}
+
+/// Compare an installed hook against its source, following symlinks
+pub fn hook_state(source_hook: &Path, installed: &Path) -> HookState {
+    if !installed.exists() {
+        return HookState::Missing;
+    }
+    match (fs::read(source_hook), fs::read(installed)) {
+        (Ok(expected), Ok(actual)) if expected == actual => HookState::Installed,
+        _ => HookState::Differs,
+    }
+}
+
+/// Install a hook into `hooks_dir`, backing up a differing existing hook first.
+/// Returns a description of what was done.
+pub fn install_hook(source_hook: &Path, hooks_dir: &Path, symlink: bool) -> Result<String, HookError> {
+    let name = source_hook.file_name().unwrap_or_default();
+    let target = hooks_dir.join(name);
+    let write_error = |source| HookError::WriteFailure { path: target.display().to_string(), source };
+    let mut action = "installed".to_string();
+
+    match hook_state(source_hook, &target) {
+        HookState::Installed if !symlink || target.is_symlink() => return Ok("unchanged".to_string()),
+        // identical copy being replaced by a symlink, or a dangling symlink: nothing worth keeping
+        HookState::Installed => fs::remove_file(&target).map_err(write_error)?,
+        HookState::Missing if target.is_symlink() => fs::remove_file(&target).map_err(write_error)?,
+        HookState::Missing => {}
+        HookState::Differs => {
+            let backup = backup_path(&target);
+            fs::rename(&target, &backup).map_err(write_error)?;
+            action = format!("installed, previous hook saved as {}", backup.file_name().unwrap_or_default().to_string_lossy());
+        }
+    }
+
+    fs::create_dir_all(hooks_dir).map_err(write_error)?;
+    if symlink {
+        link(source_hook, &target).map_err(write_error)?;
+    } else {
+        fs::copy(source_hook, &target).map_err(write_error)?;
+    }
+    Ok(action)
+}
+
+/// `<hook>.bak`, or a timestamped name when a backup already exists
+fn backup_path(target: &Path) -> PathBuf {
+    let backup = target.with_extension("bak");
+    if !backup.exists() {
+        return backup;
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    target.with_extension(format!("bak.{}", now))
+}
+
+#[cfg(unix)]
+fn link(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(not(unix))]
+fn link(source: &Path, target: &Path) -> std::io::Result<()> {
+    fs::copy(source, target).map(|_| ())
+}
@@ -0,0 +1,95 @@
+//! Multi-repo git logic behind the `grepo` command line tool. [`Grepo`] runs searches
+//! and status checks over the watched repos of a config, the modules below expose the
+//! rest of what the CLI does.
+
+#[macro_use]
+extern crate serde_derive;
+
+pub mod cache;
+pub mod config;
+pub mod dates;
+pub mod digest;
+pub mod doctor;
+pub mod exec;
+pub mod fuzzy;
+pub mod git;
+pub mod graph;
+pub mod hooks;
+pub mod lint;
+pub mod matcher;
+pub mod output;
+pub mod patch;
+pub mod plugin;
+pub mod preview;
+pub mod query;
+pub mod script;
+pub mod stats;
+pub mod style;
+pub mod sync;
+
+pub use config::{ConfigError, ConfigFile, RepoGroup, RunOptions, VirtualRepo};
+pub use git::{
+    BranchInfo, BranchScope, CommitQuery, CurrentBranchInfo, FetchInfo, GrepoError, RepoBranchCommit,
+    RepoFailure, RepoResults, RepoStatusInfo,
+};
+pub use matcher::{MatchOptions, Matcher};
+
+/// The watched repos of one config. Every call runs against all of them and returns
+/// per-repo results alongside the repos that failed.
+#[derive(Debug, Clone)]
+pub struct Grepo {
+    cfg: ConfigFile,
+}
+
+impl Grepo {
+    /// Use the config file the `grepo` command reads
+    pub fn open() -> Result<Self, ConfigError> {
+        let mut cfg = config::load()?;
+        cfg.normalize_repos();
+        Ok(Self { cfg })
+    }
+
+    pub fn with_config(cfg: ConfigFile) -> Self {
+        Self { cfg }
+    }
+
+    pub fn config(&self) -> &ConfigFile {
+        &self.cfg
+    }
+
+    /// Run against only these watched repos (or virtual repos) from now on
+    pub fn with_repos(mut self, repos: Vec<String>) -> Self {
+        self.cfg.repos = repos;
+        self.cfg.runtime.scoped = true;
+        self
+    }
+
+    /// Repo names in watch-list order, virtual repos included
+    pub fn repo_names(&self) -> Vec<String> {
+        git::watched_repo_names(&self.cfg)
+    }
+
+    pub fn search_commits(&self, query: &CommitQuery) -> Result<RepoResults<Vec<RepoBranchCommit>>, GrepoError> {
+        git::search_commits(self.cfg.clone(), query)
+    }
+
+    pub fn search_branches(&self, pattern: &Matcher, scope: BranchScope, script: Option<&script::Filter>) -> Result<RepoResults<Vec<BranchInfo>>, GrepoError> {
+        git::search_repos(self.cfg.clone(), pattern, scope, script)
+    }
+
+    pub fn search_content(&self, query: &git::ContentQuery) -> Result<RepoResults<Vec<git::ContentMatch>>, GrepoError> {
+        git::search_content(self.cfg.clone(), query)
+    }
+
+    pub fn current_branches(&self) -> Result<RepoResults<CurrentBranchInfo>, GrepoError> {
+        git::get_current_branch_name(self.cfg.clone())
+    }
+
+    pub fn statuses(&self) -> Result<RepoResults<RepoStatusInfo>, GrepoError> {
+        git::repo_statuses(self.cfg.clone())
+    }
+
+    pub fn fetch(&self, remote: &str, prune: bool) -> Result<RepoResults<FetchInfo>, GrepoError> {
+        git::fetch_repos(self.cfg.clone(), remote, prune, false)
+    }
+}
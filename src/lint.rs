@@ -0,0 +1,179 @@
+/// Rules applied by `lint commits`, stored in the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommitLintRules {
+    /// Longest allowed subject line
+    pub max_subject_length: usize,
+    /// Longest allowed body line, lines without spaces (URLs, paths) are exempt
+    pub max_body_line_length: usize,
+    /// Flag subjects that start with a past tense or third person verb
+    pub imperative_mood: bool,
+    /// When not empty, every message must reference a ticket starting with one of these prefixes followed by a number
+    pub ticket_prefixes: Vec<String>,
+}
+
+impl Default for CommitLintRules {
+    fn default() -> Self {
+        Self {
+            max_subject_length: 72,
+            max_body_line_length: 72,
+            imperative_mood: true,
+            ticket_prefixes: Vec::new(),
+        }
+    }
+}
+
+/// A rule broken by a commit message
+pub struct Violation {
+    pub rule: &'static str,
+    pub detail: String,
+}
+
+/// Third person forms that don't end in "ed"/"ing" but are common non-imperative subjects
+const NON_IMPERATIVE: &[&str] = &[
+    "adds", "fixes", "updates", "removes", "changes", "makes", "moves", "renames", "uses", "improves",
+    "refactors", "implements", "bumps", "cleans", "reverts", "merges",
+];
+
+/// Imperative verbs that happen to end in "ed"/"ing", so the suffix check leaves them alone
+const IMPERATIVE_EXCEPTIONS: &[&str] = &[
+    "bring", "string", "ping", "ring", "sing", "spring", "swing", "wring", "embed", "speed", "seed", "feed",
+    "need", "proceed", "succeed", "exceed", "shed", "shred", "bleed", "breed", "heed", "weed",
+];
+
+/// Check a commit message against the rules
+pub fn lint_message(rules: &CommitLintRules, message: &str) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut lines = message.lines();
+    let subject = lines.next().unwrap_or("").trim_end();
+
+    if subject.trim().is_empty() {
+        violations.push(Violation { rule: "subject", detail: "empty subject line".to_string() });
+        return violations;
+    }
+    let subject_length = subject.chars().count();
+    if subject_length > rules.max_subject_length {
+        violations.push(Violation {
+            rule: "subject-length",
+            detail: format!("subject is {} characters, limit is {}", subject_length, rules.max_subject_length),
+        });
+    }
+
+    if rules.imperative_mood {
+        let first_word = subject
+            .split_whitespace()
+            .find(|word| !word.ends_with(':'))
+            .unwrap_or("")
+            .trim_matches(|c: char| !c.is_alphabetic())
+            .to_lowercase();
+        let suffixed = (first_word.ends_with("ed") || first_word.ends_with("ing")) && !IMPERATIVE_EXCEPTIONS.contains(&first_word.as_str());
+        if suffixed || NON_IMPERATIVE.contains(&first_word.as_str()) {
+            violations.push(Violation {
+                rule: "imperative-mood",
+                detail: format!("subject starts with '{}', use the imperative mood (e.g. 'Fix', 'Add')", first_word),
+            });
+        }
+    }
+
+    let body = lines.collect::<Vec<&str>>();
+    if body.first().is_some_and(|line| !line.trim().is_empty()) {
+        violations.push(Violation { rule: "body-separator", detail: "second line must be blank".to_string() });
+    }
+    for line in body.iter().filter(|line| line.contains(' ')) {
+        let length = line.chars().count();
+        if length > rules.max_body_line_length {
+            violations.push(Violation {
+                rule: "body-wrap",
+                detail: format!("body line is {} characters, wrap at {}", length, rules.max_body_line_length),
+            });
+            break;
+        }
+    }
+
+    if !rules.ticket_prefixes.is_empty() && !has_ticket_reference(&rules.ticket_prefixes, message) {
+        violations.push(Violation {
+            rule: "ticket",
+            detail: format!("no ticket reference ({}<number>)", rules.ticket_prefixes.join("|")),
+        });
+    }
+    violations
+}
+
+fn has_ticket_reference(prefixes: &[String], message: &str) -> bool {
+    prefixes.iter().any(|prefix| {
+        message
+            .match_indices(prefix.as_str())
+            .any(|(start, _)| message[start + prefix.len()..].starts_with(|c: char| c.is_ascii_digit()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(message: &str) -> Vec<&'static str> {
+        lint_message(&CommitLintRules::default(), message).into_iter().map(|violation| violation.rule).collect()
+    }
+
+    #[test]
+    fn clean_message_passes() {
+        assert!(rules("Fix login redirect\n\nThe session cookie was dropped on redirect.").is_empty());
+    }
+
+    #[test]
+    fn empty_subject() {
+        assert_eq!(rules(""), ["subject"]);
+        assert_eq!(rules("   \n\nbody"), ["subject"]);
+    }
+
+    #[test]
+    fn subject_length_counts_characters() {
+        assert_eq!(rules(&"a".repeat(73)), ["subject-length"]);
+        assert!(rules(&format!("Fix {}", "é".repeat(68))).is_empty());
+    }
+
+    #[test]
+    fn imperative_mood_flags_past_tense_and_third_person() {
+        assert_eq!(rules("Fixed login"), ["imperative-mood"]);
+        assert_eq!(rules("Adding tests"), ["imperative-mood"]);
+        assert_eq!(rules("Updates deps"), ["imperative-mood"]);
+        assert_eq!(rules("api: Removed the old route"), ["imperative-mood"]);
+        let detail = &lint_message(&CommitLintRules::default(), "Fixed login")[0].detail;
+        assert_eq!(detail, "subject starts with 'fixed', use the imperative mood (e.g. 'Fix', 'Add')");
+    }
+
+    #[test]
+    fn imperative_verbs_ending_in_ed_or_ing_pass() {
+        for subject in ["Bring back the cache", "String together the steps", "Embed the font", "Speed up search", "Add tests"] {
+            assert!(rules(subject).is_empty(), "{}", subject);
+        }
+    }
+
+    #[test]
+    fn imperative_mood_can_be_turned_off() {
+        let rules = CommitLintRules { imperative_mood: false, ..Default::default() };
+        assert!(lint_message(&rules, "Fixed login").is_empty());
+    }
+
+    #[test]
+    fn body_must_follow_a_blank_line() {
+        assert_eq!(rules("Fix login\nmore text"), ["body-separator"]);
+    }
+
+    #[test]
+    fn long_body_lines_are_flagged_once_except_without_spaces() {
+        let long_line = "word ".repeat(20);
+        assert_eq!(rules(&format!("Fix login\n\n{}\n{}", long_line, long_line)), ["body-wrap"]);
+        assert!(rules(&format!("Fix login\n\nhttps://example.com/{}", "a".repeat(80))).is_empty());
+    }
+
+    #[test]
+    fn ticket_reference_needs_a_prefix_and_a_number() {
+        let rules = CommitLintRules { ticket_prefixes: vec!["JIRA-".to_string(), "#".to_string()], ..Default::default() };
+        let broken = |message| lint_message(&rules, message).into_iter().map(|violation| violation.rule).collect::<Vec<_>>();
+        assert!(broken("Fix login JIRA-42").is_empty());
+        assert!(broken("Fix login\n\nCloses #7").is_empty());
+        assert_eq!(broken("Fix login JIRA-x"), ["ticket"]);
+        assert_eq!(lint_message(&rules, "Fix login")[0].detail, "no ticket reference (JIRA-|#<number>)");
+    }
+}
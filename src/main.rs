@@ -1,46 +1,37 @@
-mod git;
-extern crate confy;
+use grepo::{cache, config, dates, digest, doctor, exec, git, graph, output, plugin, preview, query, script, stats, style, sync};
+use grepo::{ConfigFile, MatchOptions, Matcher};
 
-#[macro_use]
-extern crate serde_derive;
-
-use clap::{Parser, Subcommand};
-use confy::ConfyError;
+use chrono::TimeZone;
+use clap::{CommandFactory, Parser, Subcommand};
 use dialoguer::Confirm;
-use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fs;
-use std::rc::Rc;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
 use tabled::{
     settings::{
-        object::Rows,
+        object::{Object, Rows},
         Disable, Panel, Style, Format,
     },
     tables::ExtendedTable,
-    Table, Tabled,
+    Table,
 };
 use tabled::settings::{Alignment, Modify, Padding};
 use tabled::settings::object::Columns;
+use tabled::settings::locator::ByColumnName;
+use tabled::settings::peaker::PriorityMax;
+use tabled::settings::Width;
 
+/// Whether repo directory names differ only by case on this platform's default filesystem
+const CASE_INSENSITIVE_PATHS: bool = cfg!(any(windows, target_os = "macos"));
+/// Exit code when at least one watched repo failed during a multi-repo command
+const EXIT_PARTIAL_FAILURE: i32 = 2;
+/// Exit code when the config file could not be read or written
+const EXIT_CONFIG_FAILURE: i32 = 3;
+/// Exit code after Ctrl-C, as shells report a SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
 
-const BASE_PATH: &str = "/repos";
-
-#[derive(Tabled, Debug, Clone, Serialize, Deserialize)]
-pub struct ConfigFile {
-    #[tabled(rename = "Base Path")]
-    base_path: Rc<str>,
-    #[tabled(rename = "Repos", display_with = "concatenate_values")]
-    repos: Vec<String>,
-}
-
-impl Default for ConfigFile {
-    fn default() -> Self {
-        Self {
-            base_path: Rc::from(BASE_PATH),
-            repos: Vec::new(),
-        }
-    }
-}
 
 #[derive(Parser, Debug)]
 #[clap(name = "grepo")]
@@ -50,6 +41,46 @@ impl Default for ConfigFile {
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+    /// Stop at the first repo that fails instead of reporting failures at the end
+    #[clap(long, global = true)]
+    strict: bool,
+    /// Allow commands that modify repos to run on dirty repos or ones with an operation in progress
+    #[clap(long, global = true)]
+    force: bool,
+    /// Include the repo at this directory for this invocation only (repeatable)
+    #[clap(long = "path", global = true, value_name = "DIR")]
+    paths: Vec<String>,
+    /// Run against the repo names/paths listed one per line in this file ('-' for stdin) instead of the watch list
+    #[clap(long, global = true, value_name = "FILE")]
+    repos_from: Option<String>,
+    /// Run against only the repos that matched the previous search or branch command
+    #[clap(long, global = true, conflicts_with = "repos_from")]
+    scope_from_last: bool,
+    /// Run against only the repos in this `repo tag` group
+    #[clap(long, global = true, value_name = "NAME")]
+    group: Option<String>,
+    /// Print results as JSON instead of a table
+    #[clap(long, global = true)]
+    json: bool,
+    /// Print long results directly instead of through $PAGER
+    #[clap(long, global = true)]
+    no_pager: bool,
+    /// Print without colors or bold text, also set by a non-empty NO_COLOR
+    #[clap(long, global = true)]
+    no_color: bool,
+    /// Answer yes to confirmation prompts, for running from scripts or cron
+    #[clap(short, long, global = true)]
+    yes: bool,
+    /// Edit the watch list without opening the repos: `repo add` takes names unchecked and
+    /// `repo remove` does not look for unpushed work
+    #[clap(long, global = true)]
+    fast: bool,
+    /// Date format for this invocation: iso, local, relative or a strftime pattern
+    #[clap(long, global = true, value_name = "FORMAT", value_parser = validate_date_format)]
+    date_format: Option<String>,
+    /// Number of repos to process concurrently, defaults to one per CPU
+    #[clap(short, long, global = true, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -57,8 +88,9 @@ enum RepoCmds {
     /// Add a new repo to watch
     #[clap(arg_required_else_help = true)]
     Add {
-        /// Name (or comma-delimited string) of repo(s)
-        names: String,
+        /// Names of repos, separately or comma-delimited
+        #[clap(required = true)]
+        names: Vec<String>,
         /// This flag will clear the current saved watched repos and add only those passed in
         #[clap(short, long)]
         reset_watched: bool,
@@ -66,41 +98,634 @@ enum RepoCmds {
     /// Remove a watched repo
     #[clap(arg_required_else_help = true)]
     Remove {
-        /// Name (or comma-delimited string) of repo(s) to remove from watch
-        names: String,
+        /// Names of repos to remove from watch, separately or comma-delimited
+        #[clap(required = true)]
+        names: Vec<String>,
+        /// Only remove entries spelled exactly like the name, instead of ignoring case and whitespace
+        #[clap(long)]
+        exact: bool,
+    },
+    /// Move a watched repo's directory and update the config entries pointing at it
+    #[clap(arg_required_else_help = true)]
+    Move {
+        /// Name of the watched repo
+        name: String,
+        /// New location, relative to the base directory unless absolute
+        new_path: String,
+        /// Only re-point the config entry, the repo was already moved
+        #[clap(long)]
+        no_move: bool,
+    },
+    /// Point a watch list entry at a repo directory that was renamed on disk
+    #[clap(arg_required_else_help = true)]
+    Rename {
+        /// Current watch list entry
+        old: String,
+        /// New directory name, relative to the base directory unless absolute
+        new: String,
     },
     /// List of watched repos
     List {},
+    /// Write a tar or zip archive of a repo's tree at a given ref
+    #[clap(arg_required_else_help = true)]
+    Archive {
+        /// Name of the watched repo
+        name: String,
+        /// Branch, tag or commit to archive
+        #[clap(long = "ref", default_value = "HEAD")]
+        reference: String,
+        /// Archive format
+        #[clap(long, value_enum, default_value_t = git::ArchiveFormat::Tar)]
+        format: git::ArchiveFormat,
+        /// Optional: output directory, defaults to the archive_dir setting
+        #[clap(short, long)]
+        output_dir: Option<String>,
+        /// Leave out paths .gitattributes marks linguist-vendored (export-ignore paths always are)
+        #[clap(long)]
+        no_vendored: bool,
+    },
+    /// Staged, modified and untracked file counts and any in-progress operation per watched repo
+    Status {},
+    /// First commit date, age, commit count and birth author of every watched repo
+    Info {},
+    /// Watched repos with no commits on any branch in the last months, candidates to stop watching
+    Stale {
+        /// Months without a commit before a repo counts as stale
+        #[clap(long, default_value_t = 6)]
+        months: u32,
+    },
+    /// Run a command in every watched repo's directory, at most --jobs at a time
+    #[clap(arg_required_else_help = true)]
+    Exec {
+        /// Command and its arguments after --, or one shell command line, e.g. -- git status -s
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Fetch every watched repo from a remote
+    Fetch {
+        /// Remote to fetch from
+        #[clap(long, default_value = "origin")]
+        remote: String,
+        /// Remove remote-tracking refs that no longer exist on the remote
+        #[clap(long)]
+        prune: bool,
+        /// Also remove local tags that no longer exist on the remote
+        #[clap(long)]
+        prune_tags: bool,
+    },
+    /// Fetch and fast-forward the current branch of every watched repo to its upstream
+    Pull {
+        /// Count branches that have diverged from their upstream as failures
+        #[clap(long)]
+        ff_only: bool,
+    },
+    /// Group repos under a name to pick them with --group
+    #[clap(subcommand)]
+    Tag(TagCmds),
 }
 
 #[derive(Subcommand, Debug)]
-enum BranchCmds {
-    /// View a list of all local branches in all watched repos
+enum TagCmds {
+    /// Add repos to a group, creating it if needed
+    #[clap(arg_required_else_help = true)]
+    Add {
+        /// Group name
+        #[clap(value_name = "GROUP")]
+        tag: String,
+        /// Name (or comma-delimited string) of repo(s)
+        names: String,
+    },
+    /// Take repos out of a group, deleting it once empty
+    #[clap(arg_required_else_help = true)]
+    Remove {
+        /// Group name
+        #[clap(value_name = "GROUP")]
+        tag: String,
+        /// Name (or comma-delimited string) of repo(s)
+        names: String,
+    },
+    /// Change the defaults the group's repos run with
+    #[clap(arg_required_else_help = true)]
+    Set {
+        /// Group name
+        #[clap(value_name = "GROUP")]
+        tag: String,
+        /// Leave the group's repos out of `repo fetch` unless --group picks them
+        #[clap(long, value_name = "BOOL")]
+        exclude_from_fetch: Option<bool>,
+        /// Only search the checked-out branch of the group's repos in commit searches
+        #[clap(long, value_name = "BOOL")]
+        head_only: Option<bool>,
+    },
+    /// List the groups and their repos
+    List {},
+}
+
+#[derive(Subcommand, Debug)]
+enum GitTagCmds {
+    /// List the tags of every watched repo with their commit, tagger and date, newest first
     List {},
+}
+
+#[derive(Subcommand, Debug)]
+enum StashCmds {
+    /// List the stash entries of every watched repo with the branch they were made on
+    List {},
+}
+
+#[derive(Subcommand, Debug)]
+enum BranchCmds {
+    /// View a list of all local branches in all watched repos with their tip author and owner
+    List {
+        /// List remote-tracking branches instead of local ones
+        #[clap(short, long)]
+        remote: bool,
+        /// List both local and remote-tracking branches
+        #[clap(short, long, conflicts_with = "remote")]
+        all: bool,
+        /// Also show branch descriptions
+        #[clap(short, long)]
+        verbose: bool,
+        /// One row per repo with its branches in a single wrapped cell
+        #[clap(short, long, conflicts_with = "verbose")]
+        compact: bool,
+        /// Only branches whose tip commit author's name or email contains this, case-insensitive
+        #[clap(long, value_name = "PATTERN")]
+        tip_author: Option<String>,
+    },
+    /// Show or set the description of a branch in one repo (git's branch.<name>.description)
+    #[clap(arg_required_else_help = true)]
+    Describe {
+        /// Name of the watched repo
+        repo: String,
+        branch: String,
+        /// New description, an empty string removes it
+        text: Option<String>,
+    },
+    /// Branches across watched repos whose tip commit belongs to an author
+    #[clap(arg_required_else_help = true)]
+    ByAuthor {
+        /// Part of the author name or email, case-insensitive
+        name: String,
+    },
     /// Get a list of current branches all watched repos are on
     #[clap(alias = "cur", alias = "curr")]
-    Current {},
+    Current {
+        /// Optional: switch every repo currently on the --from branch to this branch
+        #[clap(long, requires = "from")]
+        set: Option<String>,
+        /// Branch the repos to switch are currently on
+        #[clap(long, requires = "set")]
+        from: Option<String>,
+        /// Only show which repos would be switched
+        #[clap(long, requires = "set")]
+        dry_run: bool,
+    },
+    /// Switch every watched repo to a branch, creating it from origin's branch of that name
+    #[clap(arg_required_else_help = true)]
+    Checkout {
+        /// Branch to switch to
+        name: String,
+        /// Create the branch from the default branch in repos that have no such branch
+        #[clap(long)]
+        create: bool,
+        /// Comma-delimited watched repos to switch, all watched repos when omitted
+        #[clap(long)]
+        repos: Option<String>,
+    },
+    /// Ahead/behind counts of every local branch against its upstream, diverged branches first
+    #[clap(alias = "div")]
+    Divergence {},
+    /// Files changed, insertions and deletions of a branch against the default branch, per repo
+    #[clap(arg_required_else_help = true)]
+    Diffstat {
+        /// Branch to measure
+        branch: String,
+    },
+    /// Local branches whose upstream was deleted on the remote (run a fetch with prune first)
+    Gone {
+        /// Delete the gone branches, except protected and checked out ones and, without --force,
+        /// unmerged ones
+        #[clap(long)]
+        delete: bool,
+    },
+    /// Set missing upstream tracking for current branches when a same-named remote branch exists
+    SetUpstream {
+        /// Remote to track
+        #[clap(long, default_value = "origin")]
+        remote: String,
+    },
 }
 
+// parsed once per run, so the size of the commit search variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand,Debug)]
 enum SearchCmds {
     /// Branch search in all watched repos
     #[clap(alias = "-b", arg_required_else_help = true)]
     Branch {
         /// Search pattern
-        pattern: String
+        pattern: String,
+        /// Match the pattern as a regex
+        #[clap(long)]
+        regex: bool,
+        /// Match the pattern as plain text even when search_regex is set in the config
+        #[clap(long, conflicts_with = "regex")]
+        literal: bool,
+        /// Only match whole words, so add does not match address
+        #[clap(short, long, conflicts_with = "fuzzy")]
+        word_regexp: bool,
+        /// Only list branches the pattern does not match, e.g. ones breaking a naming convention
+        #[clap(short = 'v', long, conflicts_with = "fuzzy")]
+        invert_match: bool,
+        /// Ignore case, so fix also matches Fix and FIX
+        #[clap(short, long)]
+        ignore_case: bool,
+        /// Match case even when ignore_case is set in the config
+        #[clap(long, conflicts_with = "ignore_case")]
+        match_case: bool,
+        /// Tolerate typos, listing the closest branch names first with their similarity
+        #[clap(long, conflicts_with = "regex")]
+        fuzzy: bool,
+        /// Search remote-tracking branches instead of local ones
+        #[clap(short, long)]
+        remote: bool,
+        /// Search both local and remote-tracking branches
+        #[clap(short, long, conflicts_with = "remote")]
+        all: bool,
+        /// Also require a rhai script to return true for each match, see the README
+        #[clap(long, value_name = "FILE")]
+        script: Option<String>,
+        /// Run a shell command per match instead of printing them, with {repo}, {path} (the repo directory, the
+        /// sub-project's for a virtual repo) and {branch}. At most --jobs run at once
+        #[clap(long, value_name = "CMD")]
+        exec_each: Option<String>,
+    },
+    /// Tags whose name matches a pattern in all watched repos
+    #[clap(arg_required_else_help = true)]
+    Tag {
+        /// Search pattern
+        pattern: String,
+        /// Match the pattern as a regex
+        #[clap(long)]
+        regex: bool,
+        /// Match the pattern as plain text even when search_regex is set in the config
+        #[clap(long, conflicts_with = "regex")]
+        literal: bool,
+        /// Only match whole words, so add does not match address
+        #[clap(short, long)]
+        word_regexp: bool,
+        /// Only list tags the pattern does not match
+        #[clap(short = 'v', long)]
+        invert_match: bool,
+        /// Ignore case, so v1 also matches V1
+        #[clap(short, long)]
+        ignore_case: bool,
+        /// Match case even when ignore_case is set in the config
+        #[clap(long, conflicts_with = "ignore_case")]
+        match_case: bool,
     },
-    /// Commit search in all watched repos
-    #[clap(alias = "-c", arg_required_else_help = true)]
-    Commit {
+    /// Commits whose changes add or remove a string, like git log -S
+    #[clap(arg_required_else_help = true)]
+    Diff {
+        /// Text whose number of occurrences the commit changes
+        needle: String,
+        /// Search remote-tracking branches instead of local ones
+        #[clap(short, long)]
+        remote: bool,
+        /// Search both local and remote-tracking branches
+        #[clap(short, long, conflicts_with = "remote")]
+        all: bool,
+        /// Only commits since a date (YYYY-MM-DD) or span (3m, 2w, 10d, 2.weeks)
+        #[clap(long, value_parser = dates::parse_since)]
+        since: Option<i64>,
+        /// Only commits up to a date (YYYY-MM-DD, inclusive) or span back from now
+        #[clap(long, value_parser = dates::parse_until)]
+        until: Option<i64>,
+        /// Keep at most N matches from each repo
+        #[clap(long, value_name = "N")]
+        max_per_repo: Option<usize>,
+    },
+    /// Find the repos that have a commit and the branches that contain it
+    #[clap(arg_required_else_help = true)]
+    Hash {
+        /// Full or abbreviated (at least 4 characters) commit hash
+        #[clap(value_parser = parse_hash)]
+        hash: String,
+        /// Check remote-tracking branches instead of local ones
+        #[clap(short, long)]
+        remote: bool,
+        /// Check both local and remote-tracking branches
+        #[clap(short, long, conflicts_with = "remote")]
+        all: bool,
+    },
+    /// Search file contents at HEAD (or another ref) in all watched repos, like git grep
+    #[clap(arg_required_else_help = true)]
+    Content {
         /// Search pattern
         pattern: String,
-        /// Optional: (true|false) include author name in search
+        /// Branch, tag or commit whose files to search
+        #[clap(long = "ref", default_value = "HEAD")]
+        reference: String,
+        /// Leave out files the checked-out .gitattributes mark linguist-vendored or export-ignore
+        #[clap(long)]
+        no_vendored: bool,
+        /// Also search the vendored_dirs (node_modules, vendor, target, dist by default)
+        #[clap(long, conflicts_with = "no_vendored")]
+        include_vendored: bool,
+        /// Pick a match and open it in your editor at the matching line
+        #[clap(long, conflicts_with = "exec_each")]
+        edit: bool,
+        /// Show each match as a highlighted snippet with this many lines around it
+        #[clap(long, value_name = "LINES", num_args = 0..=1, default_missing_value = "2")]
+        preview: Option<usize>,
+        /// Match the pattern as a regex
+        #[clap(long)]
+        regex: bool,
+        /// Match the pattern as plain text even when search_regex is set in the config
+        #[clap(long, conflicts_with = "regex")]
+        literal: bool,
+        /// Only match whole words, so add does not match address
+        #[clap(short, long)]
+        word_regexp: bool,
+        /// Only list lines the pattern does not match, like grep -v
+        #[clap(short = 'v', long)]
+        invert_match: bool,
+        /// Ignore case, so fix also matches Fix and FIX
         #[clap(short, long)]
+        ignore_case: bool,
+        /// Match case even when ignore_case is set in the config
+        #[clap(long, conflicts_with = "ignore_case")]
+        match_case: bool,
+        /// Run a shell command per match instead of printing them, with {repo}, {path} (the repo directory, the
+        /// containing repo's for a virtual repo, as {file} is relative to it), {file} and {line}. At most --jobs run at once
+        #[clap(long, value_name = "CMD")]
+        exec_each: Option<String>,
+    },
+    /// Commit search in all watched repos
+    #[clap(alias = "-c", arg_required_else_help = true)]
+    Commit {
+        /// Search pattern, may be omitted with --mine, --author or --committer to list all of their commits
+        #[clap(required_unless_present_any = ["mine", "author", "committer"])]
+        pattern: Option<String>,
+        /// Optional: (true|false) include author name in search. The short flag was -i before
+        /// -i became --ignore-case in every search
+        #[clap(short = 'A', long)]
         include_author: bool,
+        /// Match the pattern as a regex against messages (and authors with -A)
+        #[clap(long)]
+        regex: bool,
+        /// Match the pattern as plain text even when search_regex is set in the config
+        #[clap(long, conflicts_with = "regex")]
+        literal: bool,
+        /// Only match whole words, so add does not match address
+        #[clap(short, long, conflicts_with = "fuzzy")]
+        word_regexp: bool,
+        /// Only list commits the pattern does not match, e.g. ones without a ticket reference
+        #[clap(short = 'v', long, requires = "pattern", conflicts_with = "fuzzy")]
+        invert_match: bool,
+        /// Ignore case, so fix also matches Fix and FIX
+        #[clap(short, long)]
+        ignore_case: bool,
+        /// Match case even when ignore_case is set in the config
+        #[clap(long, conflicts_with = "ignore_case")]
+        match_case: bool,
+        /// Tolerate typos, listing the closest messages first with their similarity
+        #[clap(long, requires = "pattern", conflicts_with_all = ["regex", "rank"])]
+        fuzzy: bool,
+        /// Search remote-tracking branches instead of local ones
+        #[clap(short, long)]
+        remote: bool,
+        /// Search both local and remote-tracking branches
+        #[clap(short, long, conflicts_with = "remote")]
+        all: bool,
+        /// Only search these comma-delimited watched repos
+        #[clap(long)]
+        repos: Option<String>,
+        /// Only walk branches matching this name, where `*` matches anything, e.g. release/*
+        #[clap(long, value_name = "PATTERN")]
+        branch: Option<String>,
+        /// Also require a rhai script to return true for each match, see the README
+        #[clap(long, value_name = "FILE")]
+        script: Option<String>,
+        /// Only commits since a date (YYYY-MM-DD) or span (3m, 2w, 10d, 2.weeks)
+        #[clap(long, value_parser = dates::parse_since)]
+        since: Option<i64>,
+        /// Only commits up to a date (YYYY-MM-DD, inclusive) or span back from now
+        #[clap(long, value_parser = dates::parse_until)]
+        until: Option<i64>,
+        /// Only commits authored by you (the global user.email)
+        #[clap(long)]
+        mine: bool,
+        /// Only commits whose author name or email contains this (case-insensitive)
+        #[clap(long, value_name = "NAME")]
+        author: Option<String>,
+        /// Only commits whose committer name or email contains this (case-insensitive)
+        #[clap(long, value_name = "NAME")]
+        committer: Option<String>,
+        /// Fetch more history into shallow clones before searching
+        #[clap(long)]
+        deepen: bool,
+        /// Commits to fetch with --deepen, defaults to the deepen_by setting
+        #[clap(long, value_name = "COMMITS", requires = "deepen")]
+        deepen_by: Option<u32>,
+        /// Write each matching commit as a .patch file into DIR/<repo>/
+        #[clap(long, value_name = "DIR")]
+        export_patches: Option<String>,
+        /// Run a shell command per match instead of printing them, with {hash}, {repo}, {path} (the repo directory, the
+        /// sub-project's for a virtual repo) and {branch}. At most --jobs run at once
+        #[clap(long, value_name = "CMD")]
+        exec_each: Option<String>,
+        /// Keep at most N matches in total, after --skip
+        #[clap(short = 'n', long, value_name = "N")]
+        max_count: Option<usize>,
+        /// Keep at most N matches from each repo
+        #[clap(long, value_name = "N")]
+        max_per_repo: Option<usize>,
+        /// Leave out the first N matches
+        #[clap(long, value_name = "N", default_value_t = 0)]
+        skip: usize,
+        /// List a commit once per branch it is on instead of once with all its branches
+        #[clap(long)]
+        per_branch: bool,
+        /// Order matches by relevance: subject before body matches, whole words before parts
+        /// of words, newer before older
+        #[clap(long)]
+        rank: bool,
+        /// Keep only the N most relevant matches, implies --rank (or the N closest with --fuzzy)
+        #[clap(long, value_name = "N")]
+        top: Option<usize>,
     }
 }
 
+#[derive(Subcommand, Debug)]
+enum OwnersCmds {
+    /// Most frequent recent committers to paths matching a glob, per repo
+    #[clap(arg_required_else_help = true)]
+    Recent {
+        /// Path glob, e.g. 'src/api/**' or '*.proto'
+        path_glob: String,
+        /// Only count commits since a date (YYYY-MM-DD) or span (3m, 2w, 10d)
+        #[clap(long, default_value = "3m", value_parser = dates::parse_since)]
+        since: i64,
+        /// Number of committers to show per repo
+        #[clap(long, default_value_t = 3)]
+        top: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum LintCmds {
+    /// Check recent commit messages on HEAD against the commit_lint rules in the config file
+    Commits {
+        /// Only check commits since a date (YYYY-MM-DD) or span (3m, 2w, 10d)
+        #[clap(long, default_value = "2w", value_parser = dates::parse_since)]
+        since: i64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HooksCmds {
+    /// Copy (or symlink) a shared set of git hooks into every watched repo
+    #[clap(arg_required_else_help = true)]
+    Install {
+        /// Directory containing the shared hooks
+        #[clap(long)]
+        source: String,
+        /// Symlink hooks to the source instead of copying them
+        #[clap(long)]
+        symlink: bool,
+    },
+    /// Audit which watched repos have the shared hooks installed
+    #[clap(arg_required_else_help = true)]
+    Status {
+        /// Directory containing the shared hooks
+        #[clap(long)]
+        source: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum GitconfigCmds {
+    /// Show the effective value of a git config key in every watched repo
+    #[clap(arg_required_else_help = true)]
+    Get {
+        /// Config key, e.g. user.email
+        key: String,
+    },
+    /// Set a git config key in the local config of every watched repo
+    #[clap(arg_required_else_help = true)]
+    Set {
+        /// Config key, e.g. pull.rebase
+        key: String,
+        /// Value to set
+        value: String,
+        /// Only show the current values and what would change
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuditCmds {
+    /// Show user.name/user.email per repo and flag emails outside allowed_email_domains
+    Identity {},
+    /// Show which repos borrow object stores through alternates and which repos share theirs
+    Alternates {},
+}
+
+#[derive(Subcommand, Debug)]
+enum ExportCmds {
+    /// Write a Graphviz DOT file of watched repos, their branches, upstreams and merges
+    Graph {
+        /// Also connect watched repos that share a root commit (forks of one project)
+        #[clap(long)]
+        forks: bool,
+        /// Write to this file instead of stdout
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum StatsCmds {
+    /// When commits are made: by hour of day and day of week, in each author's timezone
+    Hours {
+        /// Only count commits since a date (YYYY-MM-DD) or span (3m, 2w, 10d)
+        #[clap(long, default_value = "3m", value_parser = dates::parse_since)]
+        since: i64,
+        /// One row per author or per repo
+        #[clap(long, value_enum, default_value = "author")]
+        by: stats::StatsGroup,
+    },
+    /// Age, first commit date, commit count and birth author of each repo, oldest first
+    Age {},
+    /// Branches created and deleted per repo, estimated from the reflogs
+    BranchChurn {
+        /// Only count changes since a date (YYYY-MM-DD) or span (3m, 2w, 10d)
+        #[clap(long, default_value = "1m", value_parser = dates::parse_since)]
+        since: i64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCmds {
+    /// Manage the branches that branch deletion refuses to touch, even with --force
+    #[clap(subcommand)]
+    Protect(ProtectCmds),
+}
+
+#[derive(Subcommand, Debug)]
+enum ProtectCmds {
+    /// Protect branches matching a name or pattern, e.g. 'release/*'
+    #[clap(arg_required_else_help = true)]
+    Add { pattern: String },
+    /// Stop protecting a pattern
+    #[clap(arg_required_else_help = true)]
+    Remove { pattern: String },
+    /// List the protected branch patterns
+    List {},
+    /// Dry run of the patterns: list the branches of the watched repos that branch
+    /// deletion would refuse to touch
+    Check {},
+}
+
+#[derive(Subcommand, Debug)]
+enum SyncCmds {
+    /// Copy shared files (CI config, CODEOWNERS, linters) into watched repos where they
+    /// differ, committing them on a new branch per repo
+    #[clap(arg_required_else_help = true)]
+    Files {
+        /// Directory holding the shared files
+        #[clap(long)]
+        source: String,
+        /// YAML file mapping source files to their path in each repo (`files:`), and
+        /// optionally the `branch:` and `message:` to commit with. Without it every file
+        /// below --source goes to the same relative path.
+        #[clap(long)]
+        map: Option<String>,
+        /// Branch to commit on
+        #[clap(long)]
+        branch: Option<String>,
+        /// Commit message
+        #[clap(short, long)]
+        message: Option<String>,
+        /// Only report which repos are out of sync
+        #[clap(long)]
+        check: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MaintenanceCmds {
+    /// Pack refs, prune old unreachable objects and repack all watched repos
+    Run {
+        /// Only prune unreachable objects older than this many days
+        #[clap(long, default_value_t = 14)]
+        prune_days: u32,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Show/set base directory of repos
@@ -109,6 +734,10 @@ enum Commands {
         path: Option<String>,
     },
 
+    /// Change settings in the config file
+    #[clap(subcommand)]
+    Config(ConfigCmds),
+
     /// Show a list of settings saved
     ShowConfig {},
 
@@ -123,238 +752,1950 @@ enum Commands {
     #[clap(subcommand, alias = "b")]
     Branch(BranchCmds),
 
+    /// Commands for git tags in watched repos
+    #[clap(subcommand)]
+    Tag(GitTagCmds),
+
+    /// Commands for stashes in watched repos
+    #[clap(subcommand)]
+    Stash(StashCmds),
+
     /// Search commands
     #[clap(subcommand, alias = "s")]
     Search(SearchCmds),
 
     /// Replaces the watched repo list with a list from current base directory
     #[clap(alias = "sbd")]
-    ScanBaseDir {},
-}
+    ScanBaseDir {
+        /// Pick which repos to watch from the ones found, with the watched ones checked
+        #[clap(short, long, conflicts_with_all = ["merge", "prune"])]
+        interactive: bool,
+        /// Add the repos found to the watch list instead of replacing it
+        #[clap(long)]
+        merge: bool,
+        /// Drop watched entries whose directory no longer exists, without adding any unless
+        /// --merge is given too
+        #[clap(long)]
+        prune: bool,
+    },
 
+    /// Find who has recently been changing paths across watched repos
+    #[clap(subcommand)]
+    Owners(OwnersCmds),
 
-fn get_config() -> Result<ConfigFile, ConfyError> {
-    match confy::load(env!("CARGO_PKG_NAME"), None) {
-        Ok(cf) => Ok(cf),
-        Err(ConfyError::BadYamlData(_)) => {
-            let newcfg = ConfigFile {
-                ..Default::default()
-            };
-            confy::store("grepo", None, &newcfg)?;
-            Ok(newcfg)
-        }
-        Err(e) => Err(e),
-    }
-}
-fn concatenate_values(values: &[String]) -> String {
-    values.join("\n")
-}
+    /// Check commit hygiene across watched repos
+    #[clap(subcommand)]
+    Lint(LintCmds),
 
-fn main() {
-    let args = Cli::parse();
-    let mut cfg = get_config().expect("Retrieving config file failed");
-    match args.command {
-        Commands::BaseDir { path } => match path {
-            None => {
-                let bold = ansi_term::Style::new().bold();
-                println!("\n{} {}\n", bold.paint("Base Directory:"),&cfg.base_path);
-            }
-            Some(new_path) => {
-                let new_cfg = ConfigFile {
-                    base_path: Rc::from(new_path),
-                    ..cfg
-                };
-                confy::store(env!("CARGO_PKG_NAME"), None, new_cfg).expect("Error writing to config file");
-                let updated_cfg = get_config().expect("Config file update failed");
-                println!(
-                    "\nUpdated base path from {} to {}",
-                    cfg.base_path, updated_cfg.base_path
-                );
-            }
-        }
+    /// Keep git hooks consistent across watched repos
+    #[clap(subcommand)]
+    Hooks(HooksCmds),
 
-        Commands::ShowConfig {} => {
-            let bold = ansi_term::Style::new().bold();
-            println!("\n{} {}\n{}\n{}", bold.paint("Base Path:"), cfg.base_path, bold.paint("Watched Repos:"),cfg.repos.join("\n"))
-        }
+    /// Read or set a local git config key across watched repos
+    #[clap(subcommand)]
+    Gitconfig(GitconfigCmds),
 
-        Commands::ConfigPath {} => {
-            let file = confy::get_configuration_file_path(env!("CARGO_PKG_NAME"), None)
-                .expect("Failed to retrieve config file path");
-            let bold = ansi_term::Style::new().bold();
-            println!("\n{} {}\n", bold.paint("Config Path:"), file.to_string_lossy());
-        }
+    /// Audit settings across watched repos
+    #[clap(subcommand)]
+    Audit(AuditCmds),
 
-        Commands::Repo(RepoCmds::Add { names, reset_watched }) => {
-            let mut repos = HashSet::new();
-            if !reset_watched {
-                repos = cfg.clone().repos.into_iter().collect()
-            }
+    /// List watched repos with unresolved index conflicts and the conflicted paths
+    Conflicts {},
 
-            let valid_repos = names.split(',')
-                .map(|name| name.trim().to_string())
-                .filter(|name| {
-                    if git::get_valid_repo(cfg.clone(), name.to_string()) {
-                        true
-                    } else {
-                        println!("Skipping {}: Not a valid repo", name);
-                        false
-                    }
-                })
-                .collect::<HashSet<String>>();
-            let mut new_repos = repos;
-            new_repos.extend(valid_repos);
-            cfg.repos = new_repos.into_iter().collect();
+    /// Apply a patch or diff file to watched repos, reporting where it did not apply
+    #[clap(arg_required_else_help = true)]
+    Apply {
+        /// Patch file, e.g. one written by `search commit --export-patches`
+        patch: String,
+        /// Comma-delimited watched repos to apply to, all watched repos when omitted
+        #[clap(long)]
+        repos: Option<String>,
+        /// Commit the change with the patch's author and message, like git am
+        #[clap(long)]
+        commit: bool,
+        /// Commit message to use instead of the patch's subject
+        #[clap(short, long, requires = "commit")]
+        message: Option<String>,
+    },
 
-            confy::store(env!("CARGO_PKG_NAME"), None, &cfg).unwrap();
+    /// Replace a regex in tracked files across watched repos, previewing the diff before writing
+    #[clap(arg_required_else_help = true)]
+    Sed {
+        /// Regex to replace
+        #[clap(value_parser = parse_regex)]
+        pattern: regex::Regex,
+        /// Replacement text, where $1 or ${name} refer to capture groups
+        replacement: String,
+        /// Only touch files matching this path glob, e.g. 'src/**' or '*.toml'
+        #[clap(long)]
+        path_glob: Option<String>,
+        /// Commit the changes in each repo
+        #[clap(long, requires = "message")]
+        commit: bool,
+        /// Commit message
+        #[clap(short, long, requires = "commit")]
+        message: Option<String>,
+        /// Create and switch to this branch before committing
+        #[clap(long, requires = "commit")]
+        branch: Option<String>,
+    },
 
-            let mut output_repos = cfg.repos.clone();
-            output_repos.sort();
+    /// Find commits matching a filter expression over author, email, message, repo, branch and date
+    #[clap(arg_required_else_help = true, after_help = "Example: grepo query 'author =~ \"alice\" and date > 2024-01-01 and repo in (api, web)'")]
+    Query {
+        /// Tests like `author = x`, `message =~ regex`, `repo in (a, b)` or `date > 2w`,
+        /// combined with and, or, not and parentheses
+        expr: String,
+        /// Also search remote-tracking branches
+        #[clap(short, long)]
+        all: bool,
+    },
 
-            let bold = ansi_term::Style::new().bold();
-            println!("{}\n{}",bold.paint("Updated Watched Repos:"), output_repos.join("\n"))
-        }
+    /// Draw the recent commit graph of local branches in one watched repo
+    #[clap(arg_required_else_help = true)]
+    Graph {
+        /// Name of the watched repo
+        repo: String,
+        /// Number of commits to draw
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+    },
 
-        Commands::Repo(RepoCmds::Remove { names }) => {
-            for name in names.split(',') {
-                if let Some(pos) = cfg.repos.iter().position(|s| *s == name) {
-                    cfg.repos.remove(pos);
-                } else {
-                    println!("Repo {} is not found", name);
-                }
-            }
-            
-            confy::store(env!("CARGO_PKG_NAME"), None, &cfg).unwrap();
+    /// Show commit-graph status of watched repos, or run housekeeping commands
+    Maintenance {
+        /// Generate commit-graph files for all watched repos to speed up searches
+        #[clap(long)]
+        write_commit_graph: bool,
+        #[clap(subcommand)]
+        command: Option<MaintenanceCmds>,
+    },
 
-            let mut output_repos = cfg.repos;
-            output_repos.is_empty().then(|| output_repos.push("** No Repos Found **".to_string()));
-            output_repos.sort();
+    /// Summarize commits, merged branches and new branches per author and repo
+    Digest {
+        /// Cover activity since a date (YYYY-MM-DD) or span (1w, 2w, 1m)
+        #[clap(long, default_value = "1w", value_parser = dates::parse_since)]
+        since: i64,
+        /// Top-level sections of the digest
+        #[clap(long, value_enum, default_value = "author")]
+        group_by: digest::DigestGroup,
+        /// Output format
+        #[clap(long, value_enum, default_value = "markdown")]
+        format: digest::DigestFormat,
+    },
 
+    /// Export watched repo data for other tools
+    #[clap(subcommand)]
+    Export(ExportCmds),
 
-            let bold = ansi_term::Style::new().bold();
-            println!("{}\n{}",bold.paint("Updated Watched Repos:"), output_repos.join("\n"))
+    /// Reports aggregated over the history of all watched repos
+    #[clap(subcommand)]
+    Stats(StatsCmds),
 
-        }
+    /// Keep shared files in step across watched repos
+    #[clap(subcommand)]
+    Sync(SyncCmds),
 
-        Commands::Repo(RepoCmds::List {}) => {
-            let mut output_repos = cfg.repos;
-            output_repos.is_empty().then(|| output_repos.push("** No Repos Found **".to_string()));
-            output_repos.sort();
-            
-            let bold = ansi_term::Style::new().bold();
-            println!("{}\n{}",bold.paint("Watched Repos:"), output_repos.join("\n"))
+    /// Check the watch list for entries that no longer resolve and suggest fixes
+    Doctor {
+        /// Re-link entries to the repo in the base directory with the same origin
+        #[clap(long)]
+        fix: bool,
+    },
 
-        }
+    /// Print a previous result set again without re-running it, e.g. with --json
+    Last {
+        /// Which result set to print, 1 is the most recent
+        #[clap(default_value_t = 1)]
+        n: usize,
+        /// List the recorded result sets instead of printing one
+        #[clap(short, long)]
+        list: bool,
+    },
 
-        Commands::Branch(BranchCmds::List {}) => {
-            git::get_repo_branch_names(cfg).into_iter().for_each(|blist| {
-                let mut output_branches = blist.branch_names();
-                output_branches.is_empty().then(|| output_branches.push("** No Branches Found **".to_string()));
-                output_branches.sort();
+    /// Print a shell completion script, completing watched repo and group names as they are now
+    #[clap(arg_required_else_help = true)]
+    Completions {
+        shell: clap_complete::Shell,
+    },
 
-                let bold = ansi_term::Style::new().bold();
-                println!(
-                    "\n{}",
-                    Table::new(output_branches)
-                        .with(Style::empty())
-                        .with(Panel::header(format!("{} {}", bold.paint("Repo:"), bold.paint(blist.repo.to_string()))))
-                        .with(Disable::row(Rows::single(1)))
-                        .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
-                )
-            })
-        }
+    /// Any other command runs the `grepo-<command>` executable on PATH, passing the
+    /// selected repos as JSON on stdin and in GREPO_* environment variables
+    #[clap(external_subcommand)]
+    External(Vec<String>),
+}
 
-        Commands::Branch(BranchCmds::Current {}) => {
-            let bold = ansi_term::Style::new().bold();
-            println!(
-                "{}",
-                Table::new(git::get_current_branch_name(cfg))
-                    .with(Style::empty())
-                    .with(Disable::row(Rows::single(0)))
-                    .with(Modify::new(Columns::single(0))
-                    .with(Alignment::left()))
-                    .with(Modify::new(Columns::first()).with(Format::content(|s| bold.paint(s).to_string())))
-                    .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
-            );
+impl Commands {
+    /// Commands that run against the watched repos, as opposed to editing the watch list or settings
+    fn uses_repo_scope(&self) -> bool {
+        match self {
+            Commands::Branch(cmd) => !matches!(cmd, BranchCmds::Describe { .. }),
+            Commands::Repo(cmd) => matches!(cmd, RepoCmds::Status {} | RepoCmds::Info {} | RepoCmds::Stale { .. } | RepoCmds::Exec { .. } | RepoCmds::Fetch { .. } | RepoCmds::Pull { .. }),
+            Commands::Config(cmd) => matches!(cmd, ConfigCmds::Protect(ProtectCmds::Check {})),
+            Commands::BaseDir { .. } | Commands::ShowConfig {} | Commands::ConfigPath {} | Commands::ScanBaseDir { .. } | Commands::Graph { .. } | Commands::Doctor { .. } | Commands::Last { .. } | Commands::Completions { .. } => false,
+            _ => true,
         }
+    }
 
-        Commands::ScanBaseDir {} => {
-            if Confirm::new().with_prompt(format!("This will reset your current watched repos with directories found in the base path ({}). Are you sure?",cfg.base_path.clone())).interact().unwrap() {
-                let mut new_config = ConfigFile {
-                    base_path: cfg.base_path.clone(),
-                    repos: vec![],
-                };
-                new_config.repos = fs::read_dir(cfg.clone().base_path.to_string())
-                    .unwrap()
-                    .filter_map(|path|
-                        if path.as_ref().unwrap().path().is_dir() {
-                            let out = &path.unwrap().file_name().into_string().unwrap();
-                            Some(out.clone())
-                        } else {
-                            None
-                        }
-                    )
-                    .filter_map(|repo| {
-                        if git::get_valid_repo(cfg.clone(), repo.to_owned()) {
-                            println!("Found repo: {}", repo);
-                            Some(repo)
-                        } else {
-                            println!("Skipping {}: Not a valid repo", repo);
-                            None
-                        }
-                    })
-                    .collect::<Vec<String>>();
-                confy::store(env!("CARGO_PKG_NAME"), None, &new_config).expect("Error writing to config file");
+    /// Commands whose work is split across the watched repos, so an interrupt can stop
+    /// starting new ones while the ones in progress finish
+    fn runs_per_repo(&self) -> bool {
+        self.uses_repo_scope() && !matches!(self, Commands::External(_))
+    }
 
-                let mut output_repos = new_config.repos;
-                output_repos.is_empty().then(|| output_repos.push("** No Repos Found **".to_string()));
-                output_repos.sort();
-                
-                println!(
-                    "{}", 
-                    Table::new(output_repos)
-                    .with(Style::re_structured_text())
-                    .with(Panel::header("Watched Repos:"))
-                    .with(Disable::row(Rows::single(1)))
-                    .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
-                )
-            }
+    /// Commands that change the watched repos rather than only reading them
+    fn modifies_repos(&self) -> bool {
+        match self {
+            Commands::Branch(cmd) => matches!(cmd, BranchCmds::Checkout { .. } | BranchCmds::Gone { delete: true } | BranchCmds::SetUpstream { .. }),
+            Commands::Repo(cmd) => matches!(cmd, RepoCmds::Exec { .. } | RepoCmds::Fetch { .. } | RepoCmds::Pull { .. }),
+            Commands::Hooks(cmd) => matches!(cmd, HooksCmds::Install { .. }),
+            Commands::Gitconfig(cmd) => matches!(cmd, GitconfigCmds::Set { .. }),
+            Commands::Sync(_) | Commands::Apply { .. } | Commands::Sed { .. } => true,
+            Commands::Maintenance { command, write_commit_graph } => *write_commit_graph || matches!(command, Some(MaintenanceCmds::Run { .. })),
+            _ => false,
         }
-        Commands::Search(SearchCmds::Branch { pattern}) => {
-            let found_in_repo = git::search_repos(cfg.clone(), pattern.clone());
-            let mut tables = Vec::new();
-            found_in_repo.iter().for_each(|(_,value)| {
+    }
+}
+
+fn validate_date_format(value: &str) -> Result<String, String> {
+    dates::DateFormat::parse(value).map(|_| value.to_string())
+}
+
+fn parse_regex(value: &str) -> Result<regex::Regex, String> {
+    regex::Regex::new(value).map_err(|e| e.to_string())
+}
+
+fn parse_hash(value: &str) -> Result<String, String> {
+    if (4..=40).contains(&value.len()) && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(value.to_lowercase())
+    } else {
+        Err("expected 4 to 40 hex digits".to_string())
+    }
+}
+
+/// Exit when --until is before --since instead of searching for nothing
+fn check_window(since: Option<i64>, until: Option<i64>) {
+    dates::check_window(since, until).unwrap_or_else(|e| {
+        eprintln!("Grepo Error: {}", e);
+        std::process::exit(1)
+    });
+}
+
+/// Build the matcher for a search pattern, exiting when it does not compile
+fn search_matcher(pattern: &str, options: MatchOptions) -> Matcher {
+    Matcher::new(pattern, options).unwrap_or_else(|e| {
+        eprintln!("Grepo Error: {}", e);
+        std::process::exit(1)
+    })
+}
+
+/// Offer the watched repos for `repo`/`--repos` arguments and the groups for `--group`
+/// in completion scripts. The names are fixed when the script is generated.
+fn with_completed_names(command: clap::Command, repos: &[String], groups: &[String]) -> clap::Command {
+    let subcommands = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect::<Vec<_>>();
+    let command = command.mut_args(|arg| match arg.get_id().as_str() {
+        "repo" | "repos" => arg.value_parser(clap::builder::PossibleValuesParser::new(repos.iter().map(clap::builder::PossibleValue::new))),
+        "group" | "tag" => arg.value_parser(clap::builder::PossibleValuesParser::new(groups.iter().map(clap::builder::PossibleValue::new))),
+        _ => arg,
+    });
+    subcommands.iter().fold(command, |command, name| {
+        command.mut_subcommand(name, |sub| with_completed_names(sub, repos, groups))
+    })
+}
+
+/// A watch list entry as `repo remove` compares it, ignoring case and stray whitespace
+fn normalized_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Watched entries close to a name that matched none of them: containing it, or at
+/// most two edits away
+fn near_misses(name: &str, repos: &[String]) -> Vec<String> {
+    let wanted = normalized_name(name);
+    repos.iter()
+        .filter(|repo| {
+            let repo = normalized_name(repo);
+            repo.contains(&wanted) || wanted.contains(&repo) || edit_distance(&repo, &wanted) <= 2
+        })
+        .cloned()
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Footer of `branch list`: branch totals and spread over the repos that were listed
+fn branch_summary(lists: &[git::BranchOwnerList]) -> String {
+    let counts = lists.iter().map(|blist| blist.owners.len()).collect::<Vec<_>>();
+    let total = counts.iter().sum::<usize>();
+    let (Some(min), Some(max)) = (counts.iter().min(), counts.iter().max()) else {
+        return format!("{} 0 repos", style::bold("Total:"));
+    };
+    let most = lists.iter().find(|blist| blist.owners.len() == *max).map_or("-", |blist| &blist.repo);
+    format!(
+        "{} {} repos, {} branches   {} {}   {} {} ({})   {} {:.1}",
+        style::bold("Total:"), lists.len(), total,
+        style::bold("Min:"), min,
+        style::bold("Max:"), max, most,
+        style::bold("Avg:"), total as f64 / lists.len() as f64,
+    )
+}
+
+/// Footer for `stats age`: repo and commit totals and the oldest and newest repos, from
+/// infos sorted oldest first
+fn age_summary(infos: &[git::RepoInfo]) -> String {
+    let commits = infos.iter().map(|info| info.commits).sum::<usize>();
+    let born = infos.iter().filter(|info| info.born.is_some()).collect::<Vec<_>>();
+    let mut summary = format!("{} {} repos, {} commits", style::bold("Total:"), infos.len(), commits);
+    if let (Some(oldest), Some(newest)) = (born.first(), born.last()) {
+        summary.push_str(&format!(
+            "   {} {} ({})   {} {} ({})",
+            style::bold("Oldest:"), oldest.repo, oldest.age,
+            style::bold("Newest:"), newest.repo, newest.age,
+        ));
+    }
+    summary
+}
+
+/// Whether two watch list entries name the same directory
+fn same_repo_name(a: &str, b: &str) -> bool {
+    if CASE_INSENSITIVE_PATHS {
+        a.to_lowercase() == b.to_lowercase()
+    } else {
+        a == b
+    }
+}
+
+/// Repo names given as separate arguments, comma-delimited or both. Each name is
+/// trimmed, so `"api, web"` and `api web` are the same.
+fn split_names(names: &[String]) -> Vec<String> {
+    names.iter()
+        .flat_map(|arg| arg.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Keep only the watched (or virtual) repos named in a comma-delimited --repos list
+fn select_repos(cfg: &mut ConfigFile, repos: &str) {
+    let selected = repos.split(',').map(str::trim).filter(|name| !name.is_empty()).collect::<Vec<_>>();
+    let known = git::watched_repo_names(cfg);
+    for name in selected.iter().filter(|name| !known.iter().any(|repo| repo == *name)) {
+        eprintln!("Skipping {}: not a watched repo", name);
+    }
+    cfg.repos = known.into_iter().filter(|repo| selected.contains(&repo.as_str())).collect();
+    cfg.runtime.scoped = true;
+}
+
+/// Narrow or extend the repos a command runs against for this invocation only
+fn apply_repo_scope(cfg: &mut ConfigFile, paths: &[String], repos_from: Option<&str>, scope_from_last: bool) -> Result<(), String> {
+    cfg.runtime.scoped = scope_from_last || repos_from.is_some();
+    if scope_from_last {
+        cfg.repos = cache::load_last_scope()?;
+    }
+    if let Some(source) = repos_from {
+        let listing = if source == "-" {
+            std::io::read_to_string(std::io::stdin())
+        } else {
+            fs::read_to_string(source)
+        }
+        .map_err(|e| format!("could not read repos from {}: {}", source, e))?;
+        let mut repos = Vec::new();
+        // first tab-separated field, so tabular output from other tools can be piped in
+        for entry in listing.lines().filter_map(|line| line.split('\t').next()).map(str::trim) {
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+            let entry = if cfg.repos.iter().any(|repo| repo == entry) || !Path::new(entry).is_dir() {
+                entry.to_string()
+            } else {
+                std::path::absolute(entry).map(|p| p.display().to_string()).unwrap_or_else(|_| entry.to_string())
+            };
+            if !repos.contains(&entry) {
+                repos.push(entry);
+            }
+        }
+        cfg.repos = repos;
+    }
+    for path in paths {
+        let path = std::path::absolute(path).map(|p| p.display().to_string()).unwrap_or_else(|_| path.clone());
+        if !cfg.repos.contains(&path) {
+            cfg.repos.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Move a watched repo on disk (unless `no_move`) and re-point its watch list and virtual
+/// repo entries. The directory is moved back when the config cannot be written.
+fn move_repo(cfg: &mut ConfigFile, name: &str, new_path: &str, no_move: bool) -> Result<String, String> {
+    if !cfg.repos.iter().any(|repo| repo == name) {
+        return Err(format!("repo {} is not watched", name));
+    }
+    let base = Path::new(&*cfg.base_path);
+    let from = base.join(name);
+    let to = base.join(new_path);
+    // store paths inside the base directory by name, like entries added with repo add
+    let entry = to.strip_prefix(base).map(|p| p.display().to_string()).unwrap_or_else(|_| to.display().to_string());
+
+    if no_move {
+        if !git::get_valid_repo(cfg.clone(), entry.clone()) {
+            return Err(format!("{} is not a valid repo", to.display()));
+        }
+    } else {
+        if to.exists() {
+            return Err(format!("{} already exists", to.display()));
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("could not create {}: {}", parent.display(), e))?;
+        }
+        fs::rename(&from, &to).map_err(|e| format!("could not move {} to {}: {}", from.display(), to.display(), e))?;
+    }
+
+    cfg.repos.iter_mut().filter(|repo| *repo == name).for_each(|repo| *repo = entry.clone());
+    cfg.virtual_repos.iter_mut().filter(|v| v.repo == name).for_each(|v| v.repo = entry.clone());
+    if let Some(fingerprint) = cfg.origin_fingerprints.remove(name) {
+        cfg.origin_fingerprints.insert(entry.clone(), fingerprint);
+    }
+    if let Err(e) = config::store(cfg) {
+        if !no_move {
+            let _ = fs::rename(&to, &from);
+        }
+        return Err(format!("could not write config, nothing was changed: {}", e));
+    }
+    Ok(entry)
+}
+
+/// Unwrap the results of a multi-repo command, exiting when a strict run failed
+fn unwrap_results<T>(results: Result<git::RepoResults<T>, git::GrepoError>) -> git::RepoResults<T> {
+    let results = results.unwrap_or_else(|e| {
+        eprintln!("Grepo Error: {}", e);
+        std::process::exit(1)
+    });
+    cache::save_last_scope(&results.matched);
+    results
+}
+
+/// Run the `--exec-each` commands, printing their output in match order and a summary
+/// of any that failed. Returns whether all of them succeeded.
+fn exec_each_match(cfg: &ConfigFile, commands: Vec<String>) -> bool {
+    let total = commands.len();
+    let outcomes = exec::run_each(commands, cfg.runtime.job_count());
+    let mut failed = Vec::new();
+    for outcome in &outcomes {
+        let _ = std::io::stdout().write_all(&outcome.stdout);
+        let _ = std::io::stderr().write_all(&outcome.stderr);
+        if let Some(reason) = &outcome.failure {
+            failed.push(format!("{}: {}", outcome.command, reason));
+        }
+    }
+    if !failed.is_empty() {
+        eprintln!("\n{}\n{}", style::bold(format!("{} of {} commands failed:", failed.len(), outcomes.len())), failed.join("\n"));
+    }
+    if git::interrupted() {
+        eprintln!("\n{}", style::bold(format!("** Interrupted: {} of {} commands were not started **", total - outcomes.len(), total)));
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    failed.is_empty()
+}
+
+/// Open a file at a line with the editor_command setting or $VISUAL/$EDITOR
+fn open_in_editor(cfg: &ConfigFile, file: &Path, line: usize) {
+    let template = cfg.editor_command.clone().or_else(|| {
+        std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).ok()
+            .filter(|editor| !editor.is_empty())
+            .map(|editor| format!("{} +{{line}} {{file}}", editor))
+    });
+    let Some(template) = template else {
+        eprintln!("Grepo Error: set $EDITOR or the editor_command setting to use --edit");
+        std::process::exit(1);
+    };
+    let command = exec::expand(&template, &[("file", &file.display().to_string()), ("line", &line.to_string())]);
+    match exec::run_attached(&command) {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("Grepo Error: {} exited with {}", command, status);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Grepo Error: could not run {}: {}", command, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn load_script(path: &str) -> script::Filter {
+    script::Filter::load(Path::new(path)).unwrap_or_else(|e| {
+        eprintln!("Grepo Error: {}", e);
+        std::process::exit(1)
+    })
+}
+
+/// The defaults a group sets, for `repo tag` output
+fn group_defaults(group: &grepo::RepoGroup) -> String {
+    let defaults = [(group.exclude_from_fetch, "excluded from fetch"), (group.head_only, "head-only searches")]
+        .into_iter()
+        .filter_map(|(set, label)| set.then_some(label))
+        .collect::<Vec<_>>();
+    if defaults.is_empty() { "no defaults".to_string() } else { defaults.join(", ") }
+}
+
+fn load_config() -> ConfigFile {
+    let mut cfg = config::load().unwrap_or_else(|e| {
+        eprintln!("Grepo Error: {}", e);
+        std::process::exit(EXIT_CONFIG_FAILURE)
+    });
+    let dropped = cfg.normalize_repos();
+    if !dropped.is_empty() {
+        eprintln!("{} ignoring duplicate watch list entries: {}", style::bold("Warning:"), dropped.join(", "));
+    }
+    cfg
+}
+
+fn save_config(cfg: &ConfigFile) {
+    if let Err(e) = config::store(cfg) {
+        eprintln!("Grepo Error: {}", e);
+        std::process::exit(EXIT_CONFIG_FAILURE);
+    }
+}
+
+/// Ask a yes/no question, answering yes without asking with --yes. Without a terminal to
+/// ask on it exits instead of guessing.
+fn confirm(yes: bool, prompt: impl Into<String>) -> bool {
+    if yes {
+        return true;
+    }
+    let prompt = prompt.into();
+    require_terminal(&format!("'{}' needs an answer, pass --yes to answer yes", prompt));
+    Confirm::new().with_prompt(prompt).interact().unwrap_or_else(|e| {
+        eprintln!("Grepo Error: could not read an answer: {}", e);
+        std::process::exit(1)
+    })
+}
+
+/// Exit with `reason` unless stdin is a terminal prompts can be answered on
+fn require_terminal(reason: &str) {
+    if !std::io::stdin().is_terminal() {
+        eprintln!("Grepo Error: no terminal to prompt on: {}", reason);
+        std::process::exit(1);
+    }
+}
+
+/// Warn when a repo about to be dropped from the watch list has work that is not on any remote
+fn warn_unpushed_work(cfg: ConfigFile, name: &str) {
+    if let Ok(unpushed) = git::unpushed_work(cfg, name.to_string()) {
+        if unpushed.is_empty() {
+            return;
+        }
+        println!("{} repo {} has local commits not on any remote:", style::bold("Warning:"), name);
+        unpushed.iter().for_each(|b| println!("  {} ({} commit(s))", b.branch, b.commits));
+    }
+}
+
+/// Print a repo's unified diff with added lines in green and removed lines in red
+fn print_diff(repo: &str, diff: &str) {
+    println!("{}", style::bold(format!("== {} ==", repo)));
+    for line in diff.lines() {
+        match line.chars().next() {
+            Some('+') if !line.starts_with("+++") => println!("{}", style::green(line)),
+            Some('-') if !line.starts_with("---") => println!("{}", style::red(line)),
+            _ => println!("{}", line),
+        }
+    }
+}
+
+/// Print a footer listing repos that failed and exit with the partial-failure code
+fn report_failures(failures: &[git::RepoFailure]) {
+    if git::interrupted() {
+        eprintln!("\n{}", style::bold("** Interrupted: these are partial results, some repos were not searched to the end **"));
+    }
+    if failures.is_empty() {
+        if git::interrupted() {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        return;
+    }
+    eprintln!(
+        "\n{}\n{}",
+        style::bold(format!("{} of the watched repos failed:", failures.len())),
+        Table::new(failures)
+            .with(Style::empty())
+            .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+    );
+    std::process::exit(if git::interrupted() { EXIT_INTERRUPTED } else { EXIT_PARTIAL_FAILURE });
+}
+
+fn main() {
+    let args = Cli::parse();
+    style::init(args.no_color);
+    // the first Ctrl-C lets the repos in progress finish so partial results get printed,
+    // a second one quits at once. Other commands keep the default of quitting at once.
+    if args.command.runs_per_repo() {
+        let message = if args.command.modifies_repos() {
+            "\nInterrupted, finishing the repos in progress (Ctrl-C again to quit now, which may leave them half done)"
+        } else {
+            "\nInterrupted, printing what was found so far (Ctrl-C again to quit now)"
+        };
+        let _ = ctrlc::set_handler(move || {
+            if git::interrupted() {
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+            git::interrupt();
+            eprintln!("{}", message);
+        });
+    }
+    let mut cfg = load_config();
+    cfg.runtime.strict = args.strict;
+    cfg.runtime.force = args.force;
+    cfg.runtime.jobs = args.jobs.map_or(0, usize::from);
+    if let Some(format) = &args.date_format {
+        cfg.date_format = format.clone();
+    } else if let Err(e) = dates::DateFormat::parse(&cfg.date_format) {
+        eprintln!("Grepo Error: date_format in the config file: {}", e);
+        std::process::exit(1);
+    }
+    let out = output::Output { json: args.json, history_size: cfg.history_size, date_format: cfg.date_format(), no_pager: args.no_pager };
+    if !args.paths.is_empty() || args.repos_from.is_some() || args.scope_from_last || args.group.is_some() {
+        if !args.command.uses_repo_scope() {
+            eprintln!("Grepo Error: --path, --repos-from, --scope-from-last and --group only apply to commands that run against repos");
+            std::process::exit(1);
+        }
+        if let Some(group) = &args.group {
+            if !cfg.groups.contains_key(group) {
+                eprintln!("Grepo Error: no group named {}, see 'grepo repo tag list'", group);
+                std::process::exit(1);
+            }
+        }
+        cfg.runtime.group = args.group.clone();
+        apply_repo_scope(&mut cfg, &args.paths, args.repos_from.as_deref(), args.scope_from_last).unwrap_or_else(|e| {
+            eprintln!("Grepo Error: {}", e);
+            std::process::exit(1)
+        });
+    }
+    match args.command {
+        Commands::BaseDir { path } => match path {
+            None => {
+                println!("\n{} {}\n", style::bold("Base Directory:"),&cfg.base_path);
+            }
+            Some(new_path) => {
+                let new_cfg = ConfigFile {
+                    base_path: Arc::from(new_path),
+                    ..cfg
+                };
+                save_config(&new_cfg);
+                let updated_cfg = load_config();
+                println!(
+                    "\nUpdated base path from {} to {}",
+                    cfg.base_path, updated_cfg.base_path
+                );
+            }
+        }
+
+        Commands::Config(ConfigCmds::Protect(ProtectCmds::Add { pattern })) => {
+            if cfg.protected_branches.contains(&pattern) {
+                println!("{} is already protected", pattern);
+                return;
+            }
+            cfg.protected_branches.push(pattern.clone());
+            save_config(&cfg);
+            println!("Protected {}", pattern);
+        }
+
+        Commands::Config(ConfigCmds::Protect(ProtectCmds::Remove { pattern })) => {
+            if !cfg.protected_branches.contains(&pattern) {
+                eprintln!("Grepo Error: {} is not a protected branch pattern", pattern);
+                std::process::exit(1);
+            }
+            cfg.protected_branches.retain(|p| p != &pattern);
+            save_config(&cfg);
+            println!("Removed protection for {}", pattern);
+        }
+
+        Commands::Config(ConfigCmds::Protect(ProtectCmds::List {})) => {
+            if cfg.protected_branches.is_empty() {
+                println!("** No Protected Branches **");
+            }
+            cfg.protected_branches.iter().for_each(|pattern| println!("{}", pattern));
+        }
+
+        Commands::Config(ConfigCmds::Protect(ProtectCmds::Check {})) => {
+            let found = unwrap_results(git::list_protected_branches(cfg));
+            let protected = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if protected.is_empty() && !out.json {
+                println!("** No protected branches in the watched repos **");
+            } else {
+                out.table(protected);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::ShowConfig {} => {
+            println!("\n{} {}\n{} {}\n{} {}\n{}\n{}", style::bold("Base Path:"), cfg.base_path, style::bold("Prefer Git CLI:"), cfg.prefer_cli, style::bold("Archive Directory:"), cfg.archive_dir, style::bold("Watched Repos:"),cfg.repos.join("\n"))
+        }
+
+        Commands::Completions { shell } => {
+            let repos = git::watched_repo_names(&cfg);
+            let groups = cfg.groups.keys().cloned().collect::<Vec<_>>();
+            let mut command = with_completed_names(Cli::command(), &repos, &groups);
+            clap_complete::generate(shell, &mut command, "grepo", &mut std::io::stdout());
+        }
+
+        Commands::External(words) => {
+            let name = &words[0];
+            let Some(program) = plugin::find(name) else {
+                eprintln!("Grepo Error: no such command '{}' and no {}{} on PATH", name, plugin::PREFIX, name);
+                std::process::exit(1);
+            };
+            let config_path = config::path().ok();
+            let input = plugin::input(&cfg, config_path);
+            match plugin::run(&program, &words[1..], &input) {
+                Ok(code) => std::process::exit(code),
+                Err(e) => {
+                    eprintln!("Grepo Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::ConfigPath {} => {
+            let file = config::path().unwrap_or_else(|e| {
+                eprintln!("Grepo Error: {}", e);
+                std::process::exit(EXIT_CONFIG_FAILURE)
+            });
+            println!("\n{} {}\n", style::bold("Config Path:"), file.to_string_lossy());
+        }
+
+        Commands::Repo(RepoCmds::Add { names, reset_watched }) => {
+            let names = split_names(&names).iter().map(|name| cfg.normalize_entry(name)).collect::<Vec<_>>();
+            let mut candidates = if args.fast {
+                names.iter()
+                    .map(|name| git::AddCandidate { repo: Arc::from(name.as_str()), exists: None, git_repo: None, bare: None, watched: None, status: Arc::from("") })
+                    .collect()
+            } else {
+                git::inspect_candidates(&cfg, &names)
+            };
+
+            let watched = std::mem::take(&mut cfg.repos);
+            let mut new_repos = if reset_watched { Vec::new() } else { watched.clone() };
+            for candidate in candidates.iter_mut() {
+                let name = candidate.repo.to_string();
+                candidate.watched = Some(watched.contains(&name));
+                let status = if let Some(existing) = new_repos.iter().find(|s| **s == name) {
+                    if watched.contains(existing) { "already watched".to_string() } else { "listed twice".to_string() }
+                } else if let Some(existing) = new_repos.iter().find(|s| same_repo_name(s, &name)) {
+                    format!("skipped, same directory as {}", existing)
+                } else if candidate.exists == Some(false) {
+                    "skipped, no such directory".to_string()
+                } else if candidate.git_repo == Some(false) {
+                    "skipped, not a git repo".to_string()
+                } else {
+                    new_repos.push(name);
+                    "added".to_string()
+                };
+                candidate.status = Arc::from(status);
+            }
+            cfg.repos = new_repos;
+            if args.fast {
+                cfg.origin_fingerprints.retain(|name, _| cfg.repos.contains(name));
+            } else {
+                doctor::record_new_fingerprints(&mut cfg);
+            }
+
+            save_config(&cfg);
+            out.table(&candidates);
+
+            let mut output_repos = cfg.repos.clone();
+            output_repos.sort();
+
+            println!("{}\n{}",style::bold("Updated Watched Repos:"), output_repos.join("\n"))
+        }
+
+        Commands::Repo(RepoCmds::Move { name, new_path, no_move }) => {
+            match move_repo(&mut cfg, &name, &new_path, no_move) {
+                Ok(entry) => {
+                    println!("{} {} -> {}", style::bold("Moved repo:"), name, entry);
+                }
+                Err(e) => {
+                    eprintln!("Grepo Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Repo(RepoCmds::Rename { old, new }) => {
+            match move_repo(&mut cfg, &old, &new, true) {
+                Ok(entry) => {
+                    println!("{} {} -> {}", style::bold("Renamed repo:"), old, entry);
+                }
+                Err(e) => {
+                    eprintln!("Grepo Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Repo(RepoCmds::Remove { names, exact }) => {
+            for name in split_names(&names) {
+                let name = if exact { name } else { cfg.normalize_entry(&name) };
+                let matching = cfg.repos.iter()
+                    .filter(|entry| if exact { **entry == name } else { normalized_name(entry) == normalized_name(&name) })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if matching.is_empty() {
+                    let suggestions = near_misses(&name, &cfg.repos);
+                    if suggestions.is_empty() {
+                        println!("Repo {} is not found", name);
+                    } else {
+                        let quoted = suggestions.iter().map(|entry| format!("'{}'", entry)).collect::<Vec<_>>();
+                        println!("Repo {} is not found, did you mean {}?", name, quoted.join(", "));
+                    }
+                    continue;
+                }
+                for entry in &matching {
+                    if !args.fast {
+                        warn_unpushed_work(cfg.clone(), entry);
+                    }
+                }
+                cfg.repos.retain(|entry| !matching.contains(entry));
+            }
+            cfg.origin_fingerprints.retain(|name, _| cfg.repos.contains(name));
+            
+            save_config(&cfg);
+
+            let mut output_repos = cfg.repos;
+            output_repos.is_empty().then(|| output_repos.push("** No Repos Found **".to_string()));
+            output_repos.sort();
+
+
+            println!("{}\n{}",style::bold("Updated Watched Repos:"), output_repos.join("\n"))
+
+        }
+
+        Commands::Repo(RepoCmds::Tag(TagCmds::Add { tag, names })) => {
+            let known = git::watched_repo_names(&cfg);
+            let entry = cfg.groups.entry(tag.clone()).or_default();
+            for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                if !known.iter().any(|repo| repo == name) {
+                    eprintln!("Skipping {}: not a watched repo", name);
+                } else if !entry.repos.iter().any(|repo| repo == name) {
+                    entry.repos.push(name.to_string());
+                    println!("Tagged {} with {}", name, tag);
+                }
+            }
+            if entry.repos.is_empty() {
+                cfg.groups.remove(&tag);
+            }
+            save_config(&cfg);
+        }
+
+        Commands::Repo(RepoCmds::Tag(TagCmds::Remove { tag, names })) => {
+            let Some(entry) = cfg.groups.get_mut(&tag) else {
+                eprintln!("Grepo Error: no group named {}", tag);
+                std::process::exit(1);
+            };
+            let names = names.split(',').map(str::trim).collect::<Vec<_>>();
+            entry.repos.retain(|repo| !names.contains(&repo.as_str()));
+            if entry.repos.is_empty() {
+                cfg.groups.remove(&tag);
+                println!("Removed group {}", tag);
+            } else {
+                println!("Untagged {} from {}", names.join(", "), tag);
+            }
+            save_config(&cfg);
+        }
+
+        Commands::Repo(RepoCmds::Tag(TagCmds::Set { tag, exclude_from_fetch, head_only })) => {
+            let Some(entry) = cfg.groups.get_mut(&tag) else {
+                eprintln!("Grepo Error: no group named {}", tag);
+                std::process::exit(1);
+            };
+            if let Some(exclude) = exclude_from_fetch {
+                entry.exclude_from_fetch = exclude;
+            }
+            if let Some(head_only) = head_only {
+                entry.head_only = head_only;
+            }
+            println!("{}: {}", tag, group_defaults(entry));
+            save_config(&cfg);
+        }
+
+        Commands::Repo(RepoCmds::Tag(TagCmds::List {})) => {
+            if cfg.groups.is_empty() {
+                println!("** No Groups **");
+            }
+            for (name, group) in &cfg.groups {
+                println!("{} {} ({})", style::bold(format!("{}:", name)), group.repos.join(", "), group_defaults(group));
+            }
+        }
+
+        Commands::Repo(RepoCmds::List {}) => {
+            let mut output_repos = cfg.repos;
+            output_repos.is_empty().then(|| output_repos.push("** No Repos Found **".to_string()));
+            output_repos.sort();
+            
+            println!("{}\n{}",style::bold("Watched Repos:"), output_repos.join("\n"));
+            if !cfg.virtual_repos.is_empty() {
+                let virtual_repos = cfg.virtual_repos.iter()
+                    .map(|v| format!("{} ({}:{})", v.name, v.repo, v.path))
+                    .collect::<Vec<_>>();
+                println!("{}\n{}", style::bold("Virtual Repos:"), virtual_repos.join("\n"));
+            }
+        }
+
+        Commands::Repo(RepoCmds::Archive { name, reference, format, output_dir, no_vendored }) => {
+            let output_dir = output_dir.unwrap_or_else(|| cfg.archive_dir.to_string());
+            match git::archive_repo(cfg, name, &reference, format, &output_dir, no_vendored) {
+                Ok(path) => {
+                    println!("{} {}", style::bold("Archive written to:"), path.display());
+                }
+                Err(e) => {
+                    eprintln!("Grepo Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Repo(RepoCmds::Info {}) => {
+            let found = unwrap_results(git::repo_infos(cfg));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Repo(RepoCmds::Exec { command }) => {
+            let command = exec::command_line(&command);
+            let repos = git::watched_repo_names(&cfg);
+            let dirs = repos.iter().map(|repo| cfg.repo_dir(repo)).collect::<Vec<_>>();
+            let failed = Mutex::new(Vec::new());
+            let finished = Mutex::new(vec![false; repos.len()]);
+            exec::run_in_dirs(&command, &dirs, cfg.runtime.job_count(), |index, outcome| {
+                finished.lock().unwrap_or_else(PoisonError::into_inner)[index] = true;
+                // one repo's output at a time, as each finishes
+                let mut failed = failed.lock().unwrap_or_else(PoisonError::into_inner);
+                println!("{} {}", style::bold("Repo:"), style::bold(&repos[index]));
+                let _ = std::io::stdout().write_all(&outcome.stdout);
+                let _ = std::io::stderr().write_all(&outcome.stderr);
+                println!();
+                if let Some(reason) = outcome.failure {
+                    failed.push(format!("{}: {}", repos[index], reason));
+                }
+            });
+            let failed = failed.into_inner().unwrap_or_else(PoisonError::into_inner);
+            if !failed.is_empty() {
+                eprintln!("{}\n{}", style::bold(format!("{} of {} repos failed:", failed.len(), repos.len())), failed.join("\n"));
+            }
+            if git::interrupted() {
+                let finished = finished.into_inner().unwrap_or_else(PoisonError::into_inner);
+                let skipped = repos.iter().zip(finished).filter(|(_, done)| !done).map(|(repo, _)| repo.as_str()).collect::<Vec<_>>();
+                eprintln!("\n{}\n{}", style::bold("** Interrupted: the command was not run in these repos **"), skipped.join(", "));
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+            if !failed.is_empty() {
+                std::process::exit(EXIT_PARTIAL_FAILURE);
+            }
+        }
+
+        Commands::Repo(RepoCmds::Stale { months }) => {
+            let since = dates::parse_since(&format!("{}m", months)).unwrap_or_else(|e| {
+                eprintln!("Grepo Error: {}", e);
+                std::process::exit(1)
+            });
+            let found = unwrap_results(git::stale_repos(cfg, since));
+            let stale = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if stale.is_empty() {
+                println!("** No repos without commits in the last {} months **", months);
+            } else {
+                out.table(&stale);
+                eprintln!("Stop watching with `grepo repo remove <names>`, or put them in a group excluded from fetch");
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Repo(RepoCmds::Status {}) => {
+            let found = unwrap_results(git::repo_statuses(cfg));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Repo(RepoCmds::Fetch { remote, prune, prune_tags }) => {
+            let found = unwrap_results(git::fetch_repos(cfg, &remote, prune, prune_tags));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Repo(RepoCmds::Pull { ff_only }) => {
+            let found = unwrap_results(git::pull_repos(cfg, ff_only));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Branch(BranchCmds::Describe { repo, branch, text: None }) => {
+            match git::branch_description(cfg, repo, &branch) {
+                Ok(Some(description)) => println!("{}", description.trim()),
+                Ok(None) => println!("** No description for {} **", branch),
+                Err(e) => {
+                    eprintln!("Grepo Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Branch(BranchCmds::Describe { repo, branch, text: Some(text) }) => {
+            if let Err(e) = git::describe_branch(cfg, repo.clone(), &branch, &text) {
+                eprintln!("Grepo Error: {}", e);
+                std::process::exit(1);
+            }
+            if text.is_empty() {
+                println!("Removed the description of {} in {}", branch, repo);
+            } else {
+                println!("Described {} in {}", branch, repo);
+            }
+        }
+
+        Commands::Branch(BranchCmds::List { remote, all, verbose, compact, tip_author }) => {
+            let scope = git::BranchScope::from_flags(remote, all);
+            let mut found = unwrap_results(git::branch_owners(cfg, scope));
+            if let Some(pattern) = &tip_author {
+                let author = pattern.to_lowercase();
+                found.results.iter_mut().for_each(|blist| blist.owners.retain(|owner| owner.tip_authored_by(&author)));
+                found.results.retain(|blist| !blist.owners.is_empty());
+                if found.results.is_empty() && !out.json {
+                    println!("** No branches with tips by {} **", pattern);
+                    report_failures(&found.failures);
+                    return;
+                }
+            }
+            if compact {
+                let set = out.record(found.results.iter().map(|blist| blist.compact()));
+                if out.json {
+                    out.print(&set);
+                } else {
+                    let width = console::Term::stdout().size_checked().map_or(120, |(_, columns)| usize::from(columns));
+                    println!("{}", set.table().with(Width::wrap(width).priority::<PriorityMax>().keep_words()));
+                    println!("\n{}", branch_summary(&found.results));
+                }
+                report_failures(&found.failures);
+                return;
+            }
+            let set = out.record(found.results.iter().flat_map(|blist| blist.owners.iter()));
+            if out.json {
+                out.print(&set);
+                report_failures(&found.failures);
+                return;
+            }
+            found.results.iter().for_each(|blist| {
+                let panel = Panel::header(format!("{} {}", style::bold("Repo:"), style::bold(blist.repo.to_string())));
+                if blist.owners.is_empty() {
+                    println!(
+                        "\n{}",
+                        Table::new(["** No Branches Found **"])
+                            .with(Style::empty())
+                            .with(panel)
+                            .with(Disable::row(Rows::single(1)))
+                            .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+                    );
+                    return;
+                }
+                let mut table = Table::new(&blist.owners);
+                if scope == git::BranchScope::Local {
+                    table.with(Disable::column(ByColumnName::new("Remote")));
+                }
+                if !verbose {
+                    table.with(Disable::column(ByColumnName::new("Description")));
+                }
+                println!(
+                    "\n{}",
+                    table
+                        .with(Style::empty())
+                        .with(Disable::column(Columns::first()))
+                        .with(panel)
+                        .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+                )
+            });
+            println!("\n{}", branch_summary(&found.results));
+            report_failures(&found.failures);
+        }
+
+        Commands::Branch(BranchCmds::ByAuthor { name }) => {
+            let found = unwrap_results(git::branches_by_author(cfg, &name));
+            let branches = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if branches.is_empty() {
+                println!("** No branches with tips by {} **", name);
+            } else {
+                out.table(branches);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Branch(BranchCmds::Current { set: Some(to), from: Some(from), dry_run }) => {
+            let planned = unwrap_results(git::plan_branch_switch(cfg.clone(), &from, &to));
+            if planned.results.is_empty() {
+                println!("** No repos to switch from {} to {} **", from, to);
+                report_failures(&planned.failures);
+                return;
+            }
+            out.table(&planned.results);
+            if dry_run {
+                report_failures(&planned.failures);
+                return;
+            }
+            if confirm(args.yes, format!("Switch {} repo(s) from {} to {}?", planned.results.len(), from, to)) {
+                let switched = unwrap_results(git::switch_branches(cfg, &from, &to));
+                out.table(&switched.results);
+                report_failures(&switched.failures);
+            }
+        }
+
+        Commands::Branch(BranchCmds::Current { .. }) => {
+            let found = unwrap_results(git::get_current_branch_name(cfg));
+            let set = out.record(&found.results);
+            if out.json {
+                out.print(&set);
+            } else {
+                println!(
+                "{}",
+                Table::new(&found.results)
+                    .with(Style::empty())
+                    .with(Disable::row(Rows::single(0)))
+                    .with(Modify::new(Columns::single(0))
+                    .with(Alignment::left()))
+                    .with(Modify::new(Columns::first()).with(Format::content(|s| style::bold(s))))
+                    .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+                );
+                let behind = found.results.iter().filter(|info| info.behind.is_some_and(|count| count > 0)).count();
+                if behind > 0 {
+                    println!("\nBehind upstream in {} of {} repos", behind, found.results.len());
+                }
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Branch(BranchCmds::Checkout { name, create, repos }) => {
+            if let Some(repos) = repos {
+                select_repos(&mut cfg, &repos);
+            }
+            let found = unwrap_results(git::checkout_branches(cfg, &name, create));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Branch(BranchCmds::Divergence {}) => {
+            let found = unwrap_results(git::branch_divergence(cfg));
+            let mut rows = found.results.into_iter().flatten().collect::<Vec<_>>();
+            rows.sort_by(|a, b| a.priority.cmp(&b.priority).then_with(|| a.repo.cmp(&b.repo)));
+            let set = out.record(&rows);
+            if out.json {
+                out.print(&set);
+                report_failures(&found.failures);
+                return;
+            }
+            println!(
+                "{}",
+                Table::new(rows)
+                    .with(Style::empty())
+                    .with(Modify::new(Columns::last()).with(Format::content(|s| {
+                        if s.trim() == "diverged" { style::bold(s) } else { s.to_string() }
+                    })))
+                    .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+            );
+            report_failures(&found.failures);
+        }
+
+        Commands::Branch(BranchCmds::Diffstat { branch }) => {
+            let found = unwrap_results(git::branch_diffstats(cfg, &branch));
+            let stats = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if stats.is_empty() {
+                println!("** No repos with branch {} **", branch);
+            } else {
+                out.table(stats);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Branch(BranchCmds::Gone { delete }) => {
+            let found = unwrap_results(git::gone_branches(cfg.clone(), false));
+            let gone = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if gone.is_empty() {
+                println!("** No branches with a gone upstream **");
+                report_failures(&found.failures);
+                return;
+            }
+            out.table(&gone);
+            let deletable = gone.iter().filter(|g| &*g.action == "gone").count();
+            if !delete || deletable == 0 {
+                report_failures(&found.failures);
+                return;
+            }
+            if confirm(args.yes, format!("Delete {} branch(es)?", deletable)) {
+                let deleted = unwrap_results(git::gone_branches(cfg, true));
+                out.table(deleted.results.into_iter().flatten());
+                report_failures(&deleted.failures);
+            }
+        }
+
+        Commands::Branch(BranchCmds::SetUpstream { remote }) => {
+            let found = unwrap_results(git::set_missing_upstreams(cfg, &remote));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::ScanBaseDir { interactive, merge, prune } => {
+            let replace = !merge && !prune;
+            if replace && !interactive && !confirm(args.yes, format!("This will reset your current watched repos with directories found in the base path ({}). Are you sure?",cfg.base_path.clone())) {
+                return;
+            }
+            let mut found = Vec::new();
+            if merge || !prune {
+                let entries = fs::read_dir(&*cfg.base_path).unwrap_or_else(|e| {
+                    eprintln!("Grepo Error: could not read base path {}: {}", cfg.base_path, e);
+                    std::process::exit(1)
+                });
+                found = entries
+                    .filter_map(Result::ok)
+                    .filter(|entry| entry.path().is_dir())
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .filter_map(|repo| {
+                        if git::get_valid_repo(cfg.clone(), repo.to_owned()) {
+                            if replace && !interactive {
+                                println!("Found repo: {}", repo);
+                            }
+                            Some(repo)
+                        } else {
+                            if !interactive {
+                                println!("Skipping {}: Not a valid repo", repo);
+                            }
+                            None
+                        }
+                    })
+                    .collect::<Vec<String>>();
+            }
+            if interactive {
+                found.sort();
+                // watched entries outside the base path stay pickable
+                found.extend(cfg.repos.iter().filter(|repo| !found.iter().any(|f| same_repo_name(f, repo))).cloned().collect::<Vec<_>>());
+                let checked = found.iter().map(|repo| cfg.repos.iter().any(|watched| same_repo_name(watched, repo))).collect::<Vec<_>>();
+                require_terminal("--interactive picks repos in a prompt");
+                let picked = dialoguer::MultiSelect::new()
+                    .with_prompt("Repos to watch (space to toggle, enter to save)")
+                    .items(&found)
+                    .defaults(&checked)
+                    .interact_opt()
+                    .unwrap_or_else(|e| {
+                        eprintln!("Grepo Error: could not read an answer: {}", e);
+                        std::process::exit(1)
+                    });
+                let Some(picked) = picked else { return };
+                found = picked.into_iter().map(|index| found[index].clone()).collect();
+            }
+            if !replace {
+                let mut repos = cfg.repos.clone();
+                if prune {
+                    let base = Path::new(&*cfg.base_path);
+                    repos.retain(|entry| {
+                        let exists = base.join(entry).is_dir();
+                        if !exists {
+                            println!("Pruned {}: directory no longer exists", entry);
+                        }
+                        exists
+                    });
+                }
+                for repo in found {
+                    if !repos.iter().any(|watched| same_repo_name(watched, &repo)) {
+                        println!("Added {}", repo);
+                        repos.push(repo);
+                    }
+                }
+                found = repos;
+            }
+            let mut new_config = ConfigFile {
+                repos: found,
+                ..cfg.clone()
+            };
+            if prune {
+                new_config.origin_fingerprints.retain(|name, _| new_config.repos.contains(name));
+            }
+            doctor::record_new_fingerprints(&mut new_config);
+            save_config(&new_config);
+
+            let mut output_repos = new_config.repos;
+            output_repos.is_empty().then(|| output_repos.push("** No Repos Found **".to_string()));
+            output_repos.sort();
+
+            println!(
+                "{}",
+                Table::new(output_repos)
+                .with(Style::re_structured_text())
+                .with(Panel::header("Watched Repos:"))
+                .with(Disable::row(Rows::single(1)))
+                .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+            )
+        }
+        Commands::Search(SearchCmds::Branch { pattern, regex, literal, word_regexp, invert_match, ignore_case, match_case, fuzzy, remote, all, script, exec_each }) => {
+            let matcher = search_matcher(&pattern, MatchOptions {
+                regex: !fuzzy && (regex || (cfg.search_regex && !literal)),
+                fuzzy,
+                word: word_regexp,
+                ignore_case: ignore_case || (cfg.ignore_case && !match_case),
+                invert: invert_match,
+            });
+            let scope = git::BranchScope::from_flags(remote, all);
+            let script = script.map(|path| load_script(&path));
+            let found_in_repo = unwrap_results(git::search_repos(cfg.clone(), &matcher, scope, script.as_ref()));
+            let mut tables = Vec::new();
+            found_in_repo.results.iter().for_each(|value| {
                 tables.extend(value)
             });
             tables.sort();
+            let score = |binfo: &git::BranchInfo| matcher.similarity(&binfo.branch).unwrap_or_default();
+            if fuzzy {
+                tables.sort_by(|a, b| score(b).total_cmp(&score(a)));
+            }
+            let local = scope == git::BranchScope::Local;
+            let set = match (fuzzy, local) {
+                (true, true) => out.record(tables.iter().map(|binfo| output::LocalOnly(output::Scored { score: score(binfo), row: binfo }))),
+                (true, false) => out.record(tables.iter().map(|binfo| output::Scored { score: score(binfo), row: binfo })),
+                (false, true) => out.record(tables.iter().map(output::LocalOnly)),
+                (false, false) => out.record(&tables),
+            };
+            if let Some(template) = &exec_each {
+                let commands = tables.iter().map(|found| exec::expand(template, &[
+                    ("repo", &found.repo),
+                    ("path", &cfg.repo_dir(&found.repo).display().to_string()),
+                    ("branch", &found.branch),
+                ])).collect();
+                let ok = exec_each_match(&cfg, commands);
+                report_failures(&found_in_repo.failures);
+                std::process::exit(if ok { 0 } else { EXIT_PARTIAL_FAILURE });
+            }
+            if out.json {
+                out.print(&set);
+                report_failures(&found_in_repo.failures);
+                return;
+            }
 
-            let bold = ansi_term::Style::new().bold();
+            let mut table = match (fuzzy, local) {
+                (true, true) => Table::new(tables.iter().map(|binfo| output::LocalOnly(output::Scored { score: score(binfo), row: binfo }))),
+                (true, false) => Table::new(tables.iter().map(|binfo| output::Scored { score: score(binfo), row: binfo })),
+                (false, true) => Table::new(tables.iter().map(output::LocalOnly)),
+                (false, false) => Table::new(tables),
+            };
             println!(
                 " {} '{}' {}\n{}",
-                bold.paint("Search Pattern"),
+                style::bold(if invert_match { "Not Matching" } else { "Search Pattern" }),
                 pattern,
-                bold.paint("found in repos:"),
-                Table::new(tables)
+                style::bold("found in repos:"),
+                table
                     .with(Style::empty())
                     .with(Disable::row(Rows::single(0)))
                     .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
-            )
+            );
+            report_failures(&found_in_repo.failures);
+        }
+        Commands::Tag(GitTagCmds::List {}) => {
+            let found = unwrap_results(git::list_tags(cfg, None));
+            let tags = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if tags.is_empty() && !out.json {
+                println!("** No tags in the watched repos **");
+            } else {
+                out.table(tags);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Stash(StashCmds::List {}) => {
+            let found = unwrap_results(git::list_stashes(cfg));
+            let stashes = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if stashes.is_empty() && !out.json {
+                println!("** No stashes in the watched repos **");
+            } else {
+                out.table(stashes);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Search(SearchCmds::Tag { pattern, regex, literal, word_regexp, invert_match, ignore_case, match_case }) => {
+            let matcher = search_matcher(&pattern, MatchOptions {
+                regex: regex || (cfg.search_regex && !literal),
+                word: word_regexp,
+                ignore_case: ignore_case || (cfg.ignore_case && !match_case),
+                invert: invert_match,
+                ..Default::default()
+            });
+            let found = unwrap_results(git::list_tags(cfg, Some(&matcher)));
+            let tags = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if tags.is_empty() && !out.json {
+                println!("** No tags match '{}' **", pattern);
+            } else {
+                out.table(tags);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Search(SearchCmds::Diff { needle, remote, all, since, until, max_per_repo }) => {
+            check_window(since, until);
+            let query = git::DiffQuery { needle, branches: git::BranchScope::from_flags(remote, all), since, until, limit: max_per_repo };
+            let found = unwrap_results(git::search_diffs(cfg, &query));
+            let matches = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if matches.is_empty() && !out.json {
+                println!("** No commits change '{}' **", query.needle);
+            } else {
+                out.table(matches);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Search(SearchCmds::Hash { hash, remote, all }) => {
+            let found = unwrap_results(git::search_hash(cfg.clone(), &hash, git::BranchScope::from_flags(remote, all)));
+            let matches = found.results.into_iter().flatten().collect::<Vec<_>>();
+            let missing = git::watched_repo_names(&cfg)
+                .into_iter()
+                .filter(|name| !matches.iter().any(|found| &*found.repo == name.as_str()))
+                .filter(|name| !found.failures.iter().any(|failure| &*failure.repo == name.as_str()))
+                .collect::<Vec<_>>();
+            if matches.is_empty() && !out.json {
+                println!("** No watched repo has commit {} **", hash);
+            } else {
+                out.table(matches);
+                if !missing.is_empty() && !out.json {
+                    println!("\n{} {}", style::bold("Not in:"), missing.join(", "));
+                }
+            }
+            report_failures(&found.failures);
         }
-        Commands::Search(SearchCmds::Commit{ pattern, include_author }) => {
-            let bold = ansi_term::Style::new().bold();
-            match git::search_commits(cfg.clone(), pattern.clone(), include_author) {
-                Ok(results) => {
-                    println!(
-                        "{} '{}' {}\n{}",
-                        bold.paint("Search Pattern"),
-                        pattern,
-                        bold.paint("found in repos:"),
-                        ExtendedTable::new(results)
-                    )
-                 },
-                Err(e) => println!("Grepo Error: {}", e)
-            };
 
+        Commands::Search(SearchCmds::Content { pattern, reference, no_vendored, include_vendored, edit, preview, regex, literal, word_regexp, invert_match, ignore_case, match_case, exec_each }) => {
+            let query = git::ContentQuery {
+                pattern: search_matcher(&pattern, MatchOptions {
+                    regex: regex || (cfg.search_regex && !literal),
+                    word: word_regexp,
+                    invert: invert_match,
+                    ignore_case: ignore_case || (cfg.ignore_case && !match_case),
+                    ..Default::default()
+                }),
+                reference,
+                skip_vendored: no_vendored,
+                include_vendored,
+                context: preview.unwrap_or(0),
+            };
+            let found = unwrap_results(git::search_content(cfg.clone(), &query));
+            let matches = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if let Some(template) = &exec_each {
+                let commands = matches.iter().map(|found| exec::expand(template, &[
+                    ("repo", &found.repo),
+                    ("path", &cfg.repo_root(&found.repo).display().to_string()),
+                    ("file", &found.path),
+                    ("line", &found.line.to_string()),
+                ])).collect();
+                let ok = exec_each_match(&cfg, commands);
+                report_failures(&found.failures);
+                std::process::exit(if ok { 0 } else { EXIT_PARTIAL_FAILURE });
+            }
+            if edit && !matches.is_empty() {
+                let chosen = match matches.as_slice() {
+                    [only] => only,
+                    _ => {
+                        let items = matches.iter().map(|m| format!("{}:{}:{}  {}", m.repo, m.path, m.line, m.text)).collect::<Vec<_>>();
+                        require_terminal("--edit asks which of several matches to open");
+                        let picked = dialoguer::Select::new().with_prompt("Open which match?").items(&items).default(0).interact_opt()
+                            .unwrap_or_else(|e| {
+                                eprintln!("Grepo Error: could not read an answer: {}", e);
+                                std::process::exit(1)
+                            });
+                        match picked {
+                            Some(index) => &matches[index],
+                            None => return,
+                        }
+                    }
+                };
+                let file = cfg.repo_root(&chosen.repo).join(&*chosen.path);
+                open_in_editor(&cfg, &file, chosen.line);
+                report_failures(&found.failures);
+                return;
+            }
+            if matches.is_empty() {
+                println!("** No Matches Found **");
+            } else if preview.is_some() && !out.json {
+                out.record(&matches);
+                for found in &matches {
+                    println!("{}", style::bold(format!("{}:{}:{}", found.repo, found.path, found.line)));
+                    println!("{}", preview::snippet(&found.path, &found.context, found.context_start, found.line));
+                }
+            } else {
+                out.table(&matches);
+            }
+            report_failures(&found.failures);
+        }
+        Commands::Search(SearchCmds::Commit{ pattern, include_author, regex, literal, word_regexp, invert_match, ignore_case, match_case, fuzzy, remote, all, repos, branch, script, since, until, mine, author, committer, deepen, deepen_by, export_patches, exec_each, max_count, max_per_repo, skip, per_branch, rank, top }) => {
+            check_window(since, until);
+            let author_email = mine.then(|| git::global_user_email().unwrap_or_else(|| {
+                eprintln!("Grepo Error: --mine needs user.email set in your global git config");
+                std::process::exit(1)
+            }));
+            let pattern = pattern.unwrap_or_default();
+            let matcher = search_matcher(&pattern, MatchOptions {
+                regex: !fuzzy && (regex || (cfg.search_regex && !literal)),
+                fuzzy,
+                word: word_regexp,
+                ignore_case: ignore_case || (cfg.ignore_case && !match_case),
+                invert: invert_match,
+            });
+            let branches = git::BranchScope::from_flags(remote, all);
+            let script = script.map(|path| Arc::new(load_script(&path)));
+            let rank = !fuzzy && (rank || top.is_some());
+            // no repo contributes more than skip + max_count matches to what is kept, unless
+            // ranking needs every match to pick the best
+            let limit = [max_per_repo, max_count.filter(|_| !rank && !fuzzy).map(|max| max + skip)].into_iter().flatten().min();
+            let branch_pattern = branch.map(|pattern| config::glob(&pattern));
+            let query = git::CommitQuery { pattern: matcher, include_author, author_email, author, committer, branches, branch_pattern, since, until, filter: None, script, limit, per_branch };
+            if let Some(repos) = repos {
+                select_repos(&mut cfg, &repos);
+            }
+            if deepen {
+                let deepened = unwrap_results(git::deepen_shallow_repos(cfg.clone(), deepen_by.unwrap_or(cfg.deepen_by)));
+                deepened.results.iter().flatten().for_each(|repo| eprintln!("Deepened shallow clone {}", repo));
+            }
+            let shallow = git::shallow_repos(cfg.clone());
+            if !shallow.is_empty() {
+                eprintln!(
+                    "{} shallow clones only cover their fetched history, use --deepen to fetch more: {}",
+                    style::bold("Warning:"),
+                    shallow.join(", ")
+                );
+            }
+            let found = unwrap_results(git::search_commits(cfg.clone(), &query));
+            let mut failures = found.failures;
+            let mut commits = found.results.iter().flatten().collect::<Vec<_>>();
+            if rank {
+                let now = chrono::Local::now().timestamp();
+                let mut scored = commits.into_iter().map(|commit| (commit.relevance(&query.pattern, now), commit)).collect::<Vec<_>>();
+                scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+                commits = scored.into_iter().map(|(_, commit)| commit).collect();
+            }
+            // the closest of message and, with -A, author
+            let score = |commit: &git::RepoBranchCommit| {
+                let message = query.pattern.similarity(&commit.message).unwrap_or_default();
+                if include_author { message.max(query.pattern.similarity(&commit.author).unwrap_or_default()) } else { message }
+            };
+            if fuzzy {
+                commits.sort_by(|a, b| score(b).total_cmp(&score(a)));
+            }
+            let keep = [max_count, top].into_iter().flatten().min().unwrap_or(usize::MAX);
+            let commits = commits.into_iter().skip(skip).take(keep).collect::<Vec<_>>();
+            if let Some(dir) = &export_patches {
+                let exported = git::export_patches(cfg.clone(), &commits, Path::new(dir)).unwrap_or_else(|e| {
+                    eprintln!("Grepo Error: {}", e);
+                    std::process::exit(1)
+                });
+                exported.results.iter().for_each(|export| eprintln!("Wrote {} patch(es) for {} to {}", export.patches, export.repo, export.dir.display()));
+                failures.extend(exported.failures);
+            }
+            // local-only searches leave out the remote column, which would always be empty
+            let local = query.branches == git::BranchScope::Local;
+            let set = match (fuzzy, local) {
+                (true, true) => out.record(commits.iter().map(|commit| output::LocalOnly(output::Scored { score: score(commit), row: *commit }))),
+                (true, false) => out.record(commits.iter().map(|commit| output::Scored { score: score(commit), row: *commit })),
+                (false, true) => out.record(commits.iter().copied().map(output::LocalOnly)),
+                (false, false) => out.record(commits.iter().copied()),
+            };
+            if let Some(template) = &exec_each {
+                let commands = commits.iter().map(|commit| exec::expand(template, &[
+                    ("hash", &commit.commit),
+                    ("repo", &commit.repo),
+                    ("path", &cfg.repo_dir(&commit.repo).display().to_string()),
+                    ("branch", &commit.branch),
+                ])).collect();
+                let ok = exec_each_match(&cfg, commands);
+                report_failures(&failures);
+                std::process::exit(if ok { 0 } else { EXIT_PARTIAL_FAILURE });
+            }
+            if out.json {
+                out.print(&set);
+                report_failures(&failures);
+                return;
+            }
+            let by = [query.author_email.as_deref(), query.author.as_deref()].into_iter().flatten().collect::<Vec<_>>().join(", ");
+            let mut heading = if pattern.is_empty() && (!by.is_empty() || query.committer.is_some()) {
+                style::bold("Commits")
+            } else {
+                format!("{} '{}'", style::bold(if invert_match { "Not Matching" } else { "Search Pattern" }), pattern)
+            };
+            if !by.is_empty() {
+                heading.push_str(&format!(" {} {}", style::bold("by"), by));
+            }
+            if let Some(committer) = &query.committer {
+                heading.push_str(&format!(" {} {}", style::bold("committed by"), committer));
+            }
+            let table = match (fuzzy, local) {
+                (true, true) => ExtendedTable::new(commits.iter().map(|commit| output::LocalOnly(output::Scored { score: score(commit), row: *commit }))),
+                (true, false) => ExtendedTable::new(commits.iter().map(|commit| output::Scored { score: score(commit), row: *commit })),
+                (false, true) => ExtendedTable::new(commits.into_iter().map(output::LocalOnly)),
+                (false, false) => ExtendedTable::new(commits),
+            };
+            out.page(&format!("{} {}\n{}", heading, style::bold("found in repos:"), table));
+            report_failures(&failures);
         },
+
+        Commands::Maintenance { command: Some(MaintenanceCmds::Run { prune_days }), .. } => {
+            let found = unwrap_results(git::run_maintenance(cfg, prune_days));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+        Commands::Maintenance { write_commit_graph, command: None } => {
+            let found = unwrap_results(git::commit_graph_status(cfg, write_commit_graph));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+        Commands::Owners(OwnersCmds::Recent { path_glob, since, top }) => {
+            let found = unwrap_results(git::recent_owners(cfg, &path_glob, since, top));
+            let owners = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if owners.is_empty() {
+                println!("** No recent commits touching {} **", path_glob);
+            } else {
+                out.table(owners);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Lint(LintCmds::Commits { since }) => {
+            let found = unwrap_results(git::lint_commits(cfg, since));
+            let violations = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if violations.is_empty() {
+                println!("** No commit message violations found **");
+            } else {
+                out.table(violations);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Hooks(cmd) => {
+            let found = unwrap_results(match cmd {
+                HooksCmds::Install { source, symlink } => git::install_hooks(cfg, &source, symlink),
+                HooksCmds::Status { source } => git::hooks_status(cfg, &source),
+            });
+            out.table(found.results.into_iter().flatten());
+            report_failures(&found.failures);
+        }
+
+        Commands::Gitconfig(GitconfigCmds::Get { key }) => {
+            let found = unwrap_results(git::get_config_values(cfg, &key));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Gitconfig(GitconfigCmds::Set { key, value, dry_run }) => {
+            let planned = unwrap_results(git::plan_config_values(cfg.clone(), &key, &value));
+            out.table(&planned.results);
+            let changes = planned.results.iter().filter(|r| &*r.action == "change").count();
+            if dry_run || changes == 0 {
+                report_failures(&planned.failures);
+                return;
+            }
+            if confirm(args.yes, format!("Set {} = {} in {} repo(s)?", key, value, changes)) {
+                let updated = unwrap_results(git::set_config_values(cfg, &key, &value));
+                out.table(&updated.results);
+                report_failures(&updated.failures);
+            }
+        }
+
+        Commands::Audit(AuditCmds::Alternates {}) => {
+            let found = unwrap_results(git::audit_alternates(cfg));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Audit(AuditCmds::Identity {}) => {
+            let found = unwrap_results(git::audit_identity(cfg));
+            let set = out.record(&found.results);
+            if out.json {
+                out.print(&set);
+                report_failures(&found.failures);
+                return;
+            }
+            println!(
+                "{}",
+                Table::new(&found.results)
+                    .with(Style::empty())
+                    .with(Modify::new(Columns::last().not(Rows::first())).with(Format::content(|s| {
+                        if s.trim() == "ok" { s.to_string() } else { style::bold(s) }
+                    })))
+                    .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+            );
+            report_failures(&found.failures);
+        }
+
+        Commands::Conflicts {} => {
+            let found = unwrap_results(git::find_conflicts(cfg));
+            let conflicts = found.results.into_iter().flatten().collect::<Vec<_>>();
+            if conflicts.is_empty() {
+                println!("** No Conflicts Found **");
+            } else {
+                out.table(conflicts);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Stats(StatsCmds::Hours { since, by }) => {
+            let found = unwrap_results(git::commit_times(cfg, since));
+            let times = found.results.into_iter().flatten().collect::<Vec<_>>();
+            let rows = stats::hours(&times, by);
+            if rows.is_empty() {
+                println!("** No Commits Found **");
+            } else {
+                out.table(&rows);
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Stats(StatsCmds::Age {}) => {
+            let found = unwrap_results(git::repo_infos(cfg));
+            let mut infos = found.results;
+            infos.sort_by_key(|info| info.born.unwrap_or(i64::MAX));
+            out.table(&infos);
+            if !out.json {
+                println!("\n{}", age_summary(&infos));
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Stats(StatsCmds::BranchChurn { since }) => {
+            let found = unwrap_results(git::branch_churn(cfg, since));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Digest { since, group_by, format } => {
+            let found = unwrap_results(git::digest(cfg, since));
+            let entries = found.results.into_iter().flatten().collect::<Vec<_>>();
+            let set = out.record(&entries);
+            if out.json {
+                out.print(&set);
+            } else {
+                let period = chrono::Local.timestamp_opt(since, 0).single()
+                    .map(|dt| format!("since {}", dt.format("%Y-%m-%d")))
+                    .unwrap_or_default();
+                print!("{}", digest::render(&entries, group_by, format, &period));
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Apply { patch, repos, commit, message } => {
+            let contents = fs::read(&patch).unwrap_or_else(|e| {
+                eprintln!("Grepo Error: could not read {}: {}", patch, e);
+                std::process::exit(1)
+            });
+            if let Some(repos) = repos {
+                select_repos(&mut cfg, &repos);
+            }
+            let found = unwrap_results(git::apply_patch(cfg, &contents, commit, message.as_deref()));
+            out.table(&found.results);
+            report_failures(&found.failures);
+        }
+
+        Commands::Sed { pattern, replacement, path_glob, commit, message, branch } => {
+            let preview = unwrap_results(git::sed(cfg.clone(), &pattern, &replacement, path_glob.as_deref(), git::SedMode::Preview));
+            let changed = preview.results.iter().filter(|info| info.files > 0).collect::<Vec<_>>();
+            if changed.is_empty() {
+                println!("** No Matches Found **");
+                report_failures(&preview.failures);
+                return;
+            }
+            if !out.json {
+                changed.iter().for_each(|info| print_diff(&info.repo, &info.diff));
+            }
+            out.table(&changed);
+            let files = changed.iter().map(|info| info.files).sum::<usize>();
+            let prompt = format!("Write the changes to {} file(s) in {} repo(s)?", files, changed.len());
+            if !confirm(args.yes, prompt) {
+                report_failures(&preview.failures);
+                return;
+            }
+            let mode = match &message {
+                Some(message) if commit => git::SedMode::Commit { message, branch: branch.as_deref() },
+                _ => git::SedMode::Write,
+            };
+            cfg.runtime.scoped = true;
+            cfg.repos = changed.iter().map(|info| info.repo.to_string()).collect();
+            let written = unwrap_results(git::sed(cfg, &pattern, &replacement, path_glob.as_deref(), mode));
+            out.table(&written.results);
+            report_failures(&[preview.failures, written.failures].concat());
+        }
+
+        Commands::Sync(SyncCmds::Files { source, map, branch, message, check }) => {
+            let source = Path::new(&source);
+            let files = sync::load_map(source, map.as_deref().map(Path::new))
+                .and_then(|map| sync::read_files(source, &map).map(|files| (map, files)));
+            let (map, files) = files.unwrap_or_else(|e| {
+                eprintln!("Grepo Error: {}", e);
+                std::process::exit(1)
+            });
+            let checked = unwrap_results(git::sync_files(cfg.clone(), &files, None));
+            let stale = checked.results.iter().filter(|info| !info.out_of_sync.is_empty()).collect::<Vec<_>>();
+            if stale.is_empty() {
+                println!("** All repos in sync **");
+                report_failures(&checked.failures);
+                return;
+            }
+            out.table(&stale);
+            let prompt = format!("Commit the shared files in {} repo(s)?", stale.len());
+            if check || !confirm(args.yes, prompt) {
+                report_failures(&checked.failures);
+                return;
+            }
+            let branch = branch.or(map.branch).unwrap_or_else(|| sync::DEFAULT_BRANCH.to_string());
+            let message = message.or(map.message).unwrap_or_else(|| sync::DEFAULT_MESSAGE.to_string());
+            cfg.runtime.scoped = true;
+            cfg.repos = stale.iter().map(|info| info.repo.to_string()).collect();
+            let synced = unwrap_results(git::sync_files(cfg, &files, Some((&branch, &message))));
+            out.table(&synced.results);
+            report_failures(&[checked.failures, synced.failures].concat());
+        }
+
+        Commands::Query { expr, all } => {
+            let filter = query::parse(&expr).unwrap_or_else(|e| {
+                eprintln!("Grepo Error: invalid query: {}", e);
+                std::process::exit(1)
+            });
+            // repo tests every match must pass narrow the repos that get opened at all
+            if let Some(repos) = filter.repos() {
+                cfg.repos = git::watched_repo_names(&cfg).into_iter().filter(|name| repos.contains(name)).collect();
+                cfg.runtime.scoped = true;
+            }
+            let query = git::CommitQuery {
+                branches: git::BranchScope::from_flags(false, all),
+                since: filter.since(),
+                filter: Some(filter),
+                ..git::CommitQuery::default()
+            };
+            let found = unwrap_results(git::search_commits(cfg, &query));
+            let set = out.record(found.results.iter().flatten());
+            if out.json {
+                out.print(&set);
+            } else if found.results.iter().all(|commits| commits.is_empty()) {
+                println!("** No Commits Found **");
+            } else {
+                println!("{}", ExtendedTable::new(found.results.iter().flatten()));
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Graph { repo, limit } => {
+            match git::graph_commits(cfg, repo, limit) {
+                Ok(commits) => graph::render_ascii(&commits).iter().for_each(|line| println!("{}", line)),
+                Err(e) => {
+                    eprintln!("Grepo Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Export(ExportCmds::Graph { forks, output }) => {
+            let found = unwrap_results(git::branch_graphs(cfg));
+            let dot = graph::render_dot(&found.results, forks);
+            match output {
+                Some(path) => {
+                    fs::write(&path, dot).unwrap_or_else(|e| {
+                        eprintln!("Grepo Error: could not write {}: {}", path, e);
+                        std::process::exit(1)
+                    });
+                    eprintln!("{} {}", style::bold("Graph written to:"), path);
+                }
+                None => print!("{}", dot),
+            }
+            report_failures(&found.failures);
+        }
+
+        Commands::Doctor { fix } => {
+            let known = cfg.origin_fingerprints.clone();
+            doctor::refresh_fingerprints(&mut cfg);
+            if cfg.origin_fingerprints != known {
+                save_config(&cfg);
+            }
+            let mut findings = doctor::diagnose(&cfg);
+            if fix {
+                for finding in findings.iter_mut() {
+                    let Some(dir) = finding.relink.clone() else { continue };
+                    finding.suggestion = match move_repo(&mut cfg, &finding.repo, &dir, true) {
+                        Ok(entry) => Arc::from(format!("re-linked to {}", entry)),
+                        Err(e) => Arc::from(format!("re-link failed: {}", e)),
+                    };
+                }
+            }
+            if findings.is_empty() {
+                println!("** No Problems Found **");
+            } else {
+                out.table(findings);
+            }
+        }
+
+        Commands::Last { n, list } => {
+            let history = cache::load_results();
+            if list {
+                if history.is_empty() {
+                    println!("** No Recorded Results **");
+                    return;
+                }
+                let mut builder = tabled::builder::Builder::default();
+                builder.set_header(["#", "Recorded", "Rows", "Command"]);
+                history.iter().enumerate().for_each(|(i, set)| {
+                    builder.push_record([(i + 1).to_string(), out.date_format.format_local(set.recorded_at), set.rows.len().to_string(), set.command.clone()]);
+                });
+                println!(
+                    "{}",
+                    builder.build()
+                        .with(Style::empty())
+                        .with(Modify::new(Columns::first()).with(Padding::new(0,0,0,0)))
+                );
+                return;
+            }
+            match n.checked_sub(1).and_then(|i| history.get(i)) {
+                Some(set) => out.print(set),
+                None => {
+                    eprintln!("Grepo Error: no recorded result #{} ({} available)", n, history.len());
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_repo_name_matches_identical_names() {
+        assert!(same_repo_name("api", "api"));
+        assert!(!same_repo_name("api", "web"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn case_only_differences_collide_on_windows() {
+        assert!(same_repo_name("Api", "api"));
+        assert!(same_repo_name("Team\\API", "team\\api"));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn case_only_differences_collide_on_macos() {
+        assert!(same_repo_name("Api", "api"));
+        assert!(same_repo_name("team/API", "team/api"));
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn case_only_differences_are_distinct_repos_elsewhere() {
+        assert!(!same_repo_name("Api", "api"));
+        assert!(!same_repo_name("team/API", "team/api"));
     }
 }
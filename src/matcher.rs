@@ -0,0 +1,206 @@
+use crate::fuzzy;
+
+/// How a search pattern is matched, from the flags the search commands share
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchOptions {
+    /// Take the pattern as a regex instead of plain text
+    pub regex: bool,
+    /// Tolerate typos, see `fuzzy::similarity`
+    pub fuzzy: bool,
+    /// Only match between word boundaries, like grep -w
+    pub word: bool,
+    pub ignore_case: bool,
+    /// Match the text the pattern does not match
+    pub invert: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Literal(String),
+    Regex(regex::Regex),
+    Fuzzy(String),
+}
+
+/// Pattern of a branch, commit, content or tag search
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    kind: Kind,
+    invert: bool,
+}
+
+impl Default for Matcher {
+    fn default() -> Self {
+        Matcher::literal("")
+    }
+}
+
+impl Matcher {
+    /// Case-sensitive plain text match
+    pub fn literal(pattern: &str) -> Self {
+        Matcher { kind: Kind::Literal(pattern.to_string()), invert: false }
+    }
+
+    pub fn new(pattern: &str, options: MatchOptions) -> Result<Self, String> {
+        let kind = if options.fuzzy {
+            if options.regex || options.word {
+                return Err("a fuzzy pattern cannot also be a regex or whole-word pattern".to_string());
+            }
+            Kind::Fuzzy(pattern.to_string())
+        } else if !options.regex && !options.word && !options.ignore_case {
+            Kind::Literal(pattern.to_string())
+        } else {
+            let source = if options.regex { pattern.to_string() } else { regex::escape(pattern) };
+            let source = if options.word { format!(r"\b(?:{})\b", source) } else { source };
+            let regex = regex::RegexBuilder::new(&source)
+                .case_insensitive(options.ignore_case)
+                .build()
+                .map_err(|e| format!("invalid regex '{}': {}", pattern, e))?;
+            Kind::Regex(regex)
+        };
+        Ok(Matcher { kind, invert: options.invert })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.found_in(text) != self.invert
+    }
+
+    /// Whether the pattern is found in any of the texts, or with `invert` in none of them
+    pub fn matches_any<'a>(&self, texts: impl IntoIterator<Item = &'a str>) -> bool {
+        texts.into_iter().any(|text| self.found_in(text)) != self.invert
+    }
+
+    fn found_in(&self, text: &str) -> bool {
+        match &self.kind {
+            Kind::Literal(pattern) => text.contains(pattern.as_str()),
+            Kind::Regex(regex) => regex.is_match(text),
+            Kind::Fuzzy(pattern) => fuzzy::similarity(pattern, text) >= fuzzy::MIN_SIMILARITY,
+        }
+    }
+
+    /// Byte ranges of the non-overlapping matches in `text`, none for an inverted or
+    /// fuzzy pattern, which match no particular place in the text
+    pub fn match_ranges(&self, text: &str) -> Vec<(usize, usize)> {
+        if self.invert {
+            return Vec::new();
+        }
+        match &self.kind {
+            Kind::Literal(pattern) if pattern.is_empty() => Vec::new(),
+            Kind::Literal(pattern) => text.match_indices(pattern.as_str()).map(|(start, found)| (start, start + found.len())).collect(),
+            Kind::Regex(regex) => regex.find_iter(text).filter(|found| !found.is_empty()).map(|found| (found.start(), found.end())).collect(),
+            Kind::Fuzzy(_) => Vec::new(),
+        }
+    }
+
+    /// How closely `text` matches a fuzzy pattern, None for other patterns
+    pub fn similarity(&self, text: &str) -> Option<f64> {
+        match &self.kind {
+            Kind::Fuzzy(pattern) => Some(fuzzy::similarity(pattern, text)),
+            _ => None,
+        }
+    }
+
+    pub fn is_fuzzy(&self) -> bool {
+        matches!(self.kind, Kind::Fuzzy(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: &str, options: MatchOptions) -> Matcher {
+        Matcher::new(pattern, options).expect("pattern compiles")
+    }
+
+    #[test]
+    fn literal_is_case_sensitive_substring() {
+        let m = matcher("fix", MatchOptions::default());
+        assert!(m.is_match("hotfix for login"));
+        assert!(!m.is_match("Fix login"));
+        assert_eq!(m.match_ranges("fix the fix"), vec![(0, 3), (8, 11)]);
+    }
+
+    #[test]
+    fn empty_pattern_matches_everything_without_ranges() {
+        let m = matcher("", MatchOptions::default());
+        assert!(m.is_match("anything"));
+        assert!(m.is_match(""));
+        assert!(m.match_ranges("anything").is_empty());
+        assert!(Matcher::default().is_match("anything"));
+    }
+
+    #[test]
+    fn regex() {
+        let m = matcher(r"JIRA-\d+", MatchOptions { regex: true, ..Default::default() });
+        assert!(m.is_match("Fix JIRA-42 crash"));
+        assert!(!m.is_match("Fix JIRA crash"));
+        assert_eq!(m.match_ranges("JIRA-1 and JIRA-22"), vec![(0, 6), (11, 18)]);
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let err = Matcher::new("fix(", MatchOptions { regex: true, ..Default::default() }).unwrap_err();
+        assert!(err.starts_with("invalid regex 'fix('"), "{}", err);
+    }
+
+    #[test]
+    fn literal_pattern_is_not_a_regex() {
+        let m = matcher("a.c", MatchOptions::default());
+        assert!(m.is_match("a.c"));
+        assert!(!m.is_match("abc"));
+        let m = matcher("a.c", MatchOptions { ignore_case: true, ..Default::default() });
+        assert!(m.is_match("A.C"));
+        assert!(!m.is_match("abc"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let m = matcher("fix", MatchOptions { ignore_case: true, ..Default::default() });
+        assert!(m.is_match("Fix"));
+        assert!(m.is_match("FIX"));
+        let m = matcher("^fix", MatchOptions { regex: true, ignore_case: true, ..Default::default() });
+        assert!(m.is_match("FIX login"));
+        assert!(!m.is_match("hotfix"));
+    }
+
+    #[test]
+    fn word_boundaries() {
+        let m = matcher("fix", MatchOptions { word: true, ..Default::default() });
+        assert!(m.is_match("fix login"));
+        assert!(m.is_match("bug-fix"));
+        assert!(!m.is_match("hotfix"));
+        assert!(!m.is_match("fixes"));
+        let m = matcher("fix|bug", MatchOptions { regex: true, word: true, ..Default::default() });
+        assert!(m.is_match("a bug here"));
+        assert!(!m.is_match("debugging"));
+    }
+
+    #[test]
+    fn invert() {
+        let m = matcher("fix", MatchOptions { invert: true, ..Default::default() });
+        assert!(!m.is_match("hotfix"));
+        assert!(m.is_match("feature"));
+        assert!(m.match_ranges("feature fix").is_empty());
+        assert!(m.matches_any(["feature", "docs"]));
+        assert!(!m.matches_any(["feature", "fix"]));
+        let m = matcher("", MatchOptions { invert: true, ..Default::default() });
+        assert!(!m.is_match("anything"));
+    }
+
+    #[test]
+    fn fuzzy() {
+        let m = matcher("paymetn retry", MatchOptions { fuzzy: true, ..Default::default() });
+        assert!(m.is_fuzzy());
+        assert!(m.is_match("Retry failed payments"));
+        assert!(!m.is_match("Update the README"));
+        assert!(m.match_ranges("payment retry").is_empty());
+        assert_eq!(m.similarity("paymetn retry"), Some(1.0));
+        assert_eq!(matcher("fix", MatchOptions::default()).similarity("fix"), None);
+    }
+
+    #[test]
+    fn fuzzy_cannot_be_regex_or_word() {
+        assert!(Matcher::new("fix", MatchOptions { fuzzy: true, regex: true, ..Default::default() }).is_err());
+        assert!(Matcher::new("fix", MatchOptions { fuzzy: true, word: true, ..Default::default() }).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{cache, exec};
+use crate::dates::DateFormat;
+use chrono::Local;
+use std::borrow::Cow;
+use tabled::builder::Builder;
+use tabled::settings::object::Columns;
+use tabled::settings::{Modify, Padding, Style};
+use tabled::{Table, Tabled};
+
+/// A result table captured as plain text cells, so it can be printed as a table or
+/// JSON and replayed later by `grepo last` without re-running any git work
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultSet {
+    pub command: String,
+    pub recorded_at: i64,
+    /// Process that recorded the set, so several tables from one command replace each other
+    #[serde(default)]
+    pub invocation: u32,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl ResultSet {
+    pub fn new<T: Tabled>(rows: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            command: std::env::args().skip(1).collect::<Vec<String>>().join(" "),
+            recorded_at: Local::now().timestamp(),
+            invocation: std::process::id(),
+            headers: T::headers().into_iter().map(|h| h.to_string()).collect(),
+            rows: rows
+                .into_iter()
+                .map(|row| row.fields().into_iter().map(|f| f.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    /// Standard grepo table layout: no borders, header row, flush left
+    pub fn table(&self) -> Table {
+        let mut builder = Builder::default();
+        builder.set_header(self.headers.clone());
+        for row in &self.rows {
+            builder.push_record(row.clone());
+        }
+        let mut table = builder.build();
+        table
+            .with(Style::empty())
+            .with(Modify::new(Columns::first()).with(Padding::new(0, 0, 0, 0)));
+        table
+    }
+
+    /// Rows as a JSON array of objects keyed by header
+    pub fn to_json(&self) -> String {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                self.headers
+                    .iter()
+                    .map(|h| h.to_lowercase().replace(' ', "_"))
+                    .zip(row.iter().map(|cell| serde_json::Value::from(cell.as_str())))
+                    .collect::<serde_json::Map<String, serde_json::Value>>()
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string_pretty(&rows).unwrap_or_else(|_| "[]".to_string())
+    }
+}
+
+/// How results are printed and remembered for the current invocation
+pub struct Output {
+    pub json: bool,
+    pub history_size: usize,
+    pub date_format: DateFormat,
+    /// Print long output directly instead of through the pager
+    pub no_pager: bool,
+}
+
+/// A result row led by the similarity it matched a `--fuzzy` pattern with
+#[derive(Tabled)]
+pub struct Scored<T: Tabled> {
+    #[tabled(rename = "score", display_with = "display_score")]
+    pub score: f64,
+    #[tabled(inline)]
+    pub row: T,
+}
+
+fn display_score(score: &f64) -> String {
+    format!("{:.2}", score)
+}
+
+/// A result row without its `remote` column, for searches of local branches, where the
+/// column would be empty on every row
+pub struct LocalOnly<T: Tabled>(pub T);
+
+impl<T: Tabled> Tabled for LocalOnly<T> {
+    const LENGTH: usize = T::LENGTH - 1;
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        let remote = T::headers().iter().position(|header| header == "remote");
+        self.0.fields().into_iter().enumerate().filter(|(index, _)| Some(*index) != remote).map(|(_, field)| field).collect()
+    }
+
+    fn headers() -> Vec<Cow<'static, str>> {
+        T::headers().into_iter().filter(|header| header != "remote").collect()
+    }
+}
+
+impl Output {
+    /// Remember rows for `grepo last` and return them as a result set
+    pub fn record<T: Tabled>(&self, rows: impl IntoIterator<Item = T>) -> ResultSet {
+        let set = ResultSet::new(rows);
+        cache::save_result(&set, self.history_size);
+        set
+    }
+
+    /// Print a result set in the requested format
+    pub fn print(&self, set: &ResultSet) {
+        if self.json {
+            println!("{}", set.to_json());
+        } else {
+            println!("{}", set.table());
+        }
+    }
+
+    /// Print text, through `$PAGER` (less by default) when stdout is a terminal the text
+    /// does not fit on
+    pub fn page(&self, text: &str) {
+        let term = console::Term::stdout();
+        let fits = term.size_checked().is_none_or(|(rows, _)| text.lines().count() < usize::from(rows));
+        if self.no_pager || !term.is_term() || fits {
+            println!("{}", text);
+            return;
+        }
+        let pager = std::env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty()).unwrap_or_else(|| "less".to_string());
+        if std::env::var_os("LESS").is_none() {
+            // keep colors and leave the output on screen after quitting
+            std::env::set_var("LESS", "FRX");
+        }
+        if exec::run_with_input(&pager, &format!("{}\n", text)).is_err() {
+            println!("{}", text);
+        }
+    }
+
+    /// Record rows and print them in the standard layout (or JSON)
+    pub fn table<T: Tabled>(&self, rows: impl IntoIterator<Item = T>) {
+        let set = self.record(rows);
+        self.print(&set);
+    }
+}
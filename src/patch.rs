@@ -0,0 +1,58 @@
+/// Author and message taken from the email headers of a format-patch file
+#[derive(Debug, Clone, Default)]
+pub struct PatchHeader {
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    /// Subject without the `[PATCH n/m]` prefix, followed by the body when there is one
+    pub message: Option<String>,
+}
+
+/// Read the From/Subject headers and commit body of a format-patch style file. Plain
+/// diffs without headers yield an empty header.
+pub fn parse_header(patch: &str) -> PatchHeader {
+    let mut header = PatchHeader::default();
+    let mut lines = patch.lines().peekable();
+    let mut subject = None;
+    while let Some(line) = lines.next() {
+        if line.is_empty() || line.starts_with("diff --git") || line == "---" {
+            break;
+        }
+        if let Some(from) = line.strip_prefix("From: ") {
+            match from.rsplit_once('<') {
+                Some((name, email)) => {
+                    header.author_name = Some(name.trim().trim_matches('"').to_string());
+                    header.author_email = Some(email.trim_end_matches('>').trim().to_string());
+                }
+                None => header.author_email = Some(from.trim().to_string()),
+            }
+        } else if let Some(value) = line.strip_prefix("Subject: ") {
+            let mut value = value.to_string();
+            // folded header lines continue with whitespace
+            while let Some(next) = lines.peek().filter(|next| next.starts_with([' ', '\t'])) {
+                value.push(' ');
+                value.push_str(next.trim());
+                lines.next();
+            }
+            subject = Some(strip_patch_prefix(&value).to_string());
+        }
+    }
+    let Some(subject) = subject else { return header };
+
+    // the body runs from the blank line after the headers up to the "---" separator
+    let body = lines
+        .take_while(|line| *line != "---" && !line.starts_with("diff --git"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let body = body.trim();
+    header.message = Some(if body.is_empty() { subject } else { format!("{}\n\n{}", subject, body) });
+    header
+}
+
+/// "[PATCH 2/3] Fix parser" -> "Fix parser"
+fn strip_patch_prefix(subject: &str) -> &str {
+    let subject = subject.trim();
+    match subject.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((tag, rest)) if tag.starts_with("PATCH") => rest.trim_start(),
+        _ => subject,
+    }
+}
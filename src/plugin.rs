@@ -0,0 +1,72 @@
+use crate::{git, ConfigFile};
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Prefix of the executables `grepo <name>` runs for subcommands it does not know
+pub const PREFIX: &str = "grepo-";
+
+/// What a plugin receives as JSON on stdin
+#[derive(Debug, Serialize)]
+pub struct PluginInput {
+    /// Path of the grepo config file, also in GREPO_CONFIG
+    pub config_path: Option<PathBuf>,
+    pub base_path: String,
+    /// Repos the invocation selected, honouring --path, --repos-from and --scope-from-last
+    pub repos: Vec<PluginRepo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PluginRepo {
+    pub name: String,
+    /// Directory of the repo, or of the repo containing it for a virtual repo
+    pub path: PathBuf,
+    /// Sub-project path inside the repo for a virtual repo
+    pub subpath: Option<String>,
+}
+
+/// The `grepo-<name>` executable on PATH, if there is one
+pub fn find(name: &str) -> Option<PathBuf> {
+    let file = format!("{}{}{}", PREFIX, name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file))
+        .find(|candidate| candidate.is_file())
+}
+
+pub fn input(cfg: &ConfigFile, config_path: Option<PathBuf>) -> PluginInput {
+    let base = Path::new(&*cfg.base_path);
+    let repos = git::watched_repo_names(cfg)
+        .into_iter()
+        .map(|name| match cfg.virtual_repos.iter().find(|v| v.name == name) {
+            Some(v) => PluginRepo { path: base.join(&v.repo), subpath: Some(v.path.clone()), name },
+            None => PluginRepo { path: base.join(&name), subpath: None, name },
+        })
+        .collect();
+    PluginInput { config_path, base_path: cfg.base_path.to_string(), repos }
+}
+
+/// Run a plugin with the remaining arguments and wait for it. Besides the JSON on stdin
+/// it gets GREPO_CONFIG, GREPO_BASE_PATH and GREPO_REPOS (one repo name per line).
+/// Returns the plugin's exit code.
+pub fn run(program: &Path, args: &[String], input: &PluginInput) -> Result<i32, String> {
+    let json = serde_json::to_string(input).map_err(|e| format!("could not encode the plugin input: {}", e))?;
+    let repos: Vec<&str> = input.repos.iter().map(|repo| repo.name.as_str()).collect();
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env("GREPO_BASE_PATH", &input.base_path)
+        .env("GREPO_REPOS", repos.join("\n"))
+        .stdin(Stdio::piped());
+    if let Some(path) = &input.config_path {
+        command.env("GREPO_CONFIG", path);
+    }
+    let mut child = command.spawn().map_err(|e| format!("could not run {}: {}", program.display(), e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // plugins that ignore stdin may exit before reading it
+        let _ = stdin.write_all(json.as_bytes());
+    }
+    let status = child.wait().map_err(|e| format!("{} did not finish: {}", program.display(), e))?;
+    // killed by a signal
+    Ok(status.code().unwrap_or(1))
+}
@@ -0,0 +1,43 @@
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME: OnceLock<Theme> = OnceLock::new();
+    THEME.get_or_init(|| ThemeSet::load_defaults().themes.remove("base16-ocean.dark").unwrap_or_default())
+}
+
+/// Number and syntax-highlight the lines of a content search snippet, picking the syntax
+/// from the file extension and marking the matching line with `>`
+pub fn snippet(path: &str, lines: &[String], first_line: usize, matched_line: usize) -> String {
+    let syntaxes = syntaxes();
+    let syntax = path
+        .rsplit_once('.')
+        .and_then(|(_, extension)| syntaxes.find_syntax_by_extension(extension))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, theme());
+    let width = (first_line + lines.len()).to_string().len();
+    let mut out = String::new();
+    for (offset, line) in lines.iter().enumerate() {
+        let number = first_line + offset;
+        let marker = if number == matched_line { '>' } else { ' ' };
+        if !crate::style::enabled() {
+            out.push_str(&format!("{} {:>width$} | {}\n", marker, number, line, width = width));
+            continue;
+        }
+        let line = format!("{}\n", line);
+        let highlighted = highlighter
+            .highlight_line(&line, syntaxes)
+            .map(|ranges| as_24_bit_terminal_escaped(&ranges, false))
+            .unwrap_or(line);
+        out.push_str(&format!("{} {:>width$} | {}\x1b[0m\n", marker, number, highlighted.trim_end_matches('\n'), width = width));
+    }
+    out
+}
@@ -0,0 +1,433 @@
+use crate::dates;
+
+/// A parsed `grepo query` filter over commit fields, e.g.
+/// `author =~ "alice" and date > 2024-01-01 and repo in (api, web)`
+#[derive(Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Text { field: Field, test: TextTest },
+    Date { op: DateOp, timestamp: i64 },
+}
+
+/// Commit fields a query can test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Author,
+    Email,
+    Message,
+    Repo,
+    Branch,
+}
+
+#[derive(Debug, Clone)]
+pub enum TextTest {
+    Equals(String),
+    Matches(regex::Regex),
+    In(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DateOp {
+    Before,
+    AtOrBefore,
+    After,
+    AtOrAfter,
+}
+
+/// The fields of one commit on one branch, as a query sees them
+pub struct CommitFields<'a> {
+    pub repo: &'a str,
+    pub branch: &'a str,
+    /// "Name <email>"
+    pub author: &'a str,
+    pub email: &'a str,
+    pub message: &'a str,
+    pub time: i64,
+}
+
+impl Expr {
+    pub fn matches(&self, commit: &CommitFields) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(commit) && right.matches(commit),
+            Expr::Or(left, right) => left.matches(commit) || right.matches(commit),
+            Expr::Not(inner) => !inner.matches(commit),
+            Expr::Text { field, test } => {
+                let value = match field {
+                    Field::Author => commit.author,
+                    Field::Email => commit.email,
+                    Field::Message => commit.message,
+                    Field::Repo => commit.repo,
+                    Field::Branch => commit.branch,
+                };
+                match test {
+                    TextTest::Equals(expected) => value == expected,
+                    TextTest::Matches(regex) => regex.is_match(value),
+                    TextTest::In(values) => values.iter().any(|expected| value == expected),
+                }
+            }
+            Expr::Date { op, timestamp } => match op {
+                DateOp::Before => commit.time < *timestamp,
+                DateOp::AtOrBefore => commit.time <= *timestamp,
+                DateOp::After => commit.time > *timestamp,
+                DateOp::AtOrAfter => commit.time >= *timestamp,
+            },
+        }
+    }
+
+    /// Lower bound on commit time that every match must meet, so the revwalk can stop
+    /// early. Only date tests joined to the rest of the query by `and` give one.
+    pub fn since(&self) -> Option<i64> {
+        match self {
+            Expr::And(left, right) => match (left.since(), right.since()) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+            Expr::Date { op: DateOp::After | DateOp::AtOrAfter, timestamp } => Some(*timestamp),
+            _ => None,
+        }
+    }
+
+    /// Repos every match must be in, so other repos need not be opened. Only `repo =`
+    /// and `repo in` tests joined to the rest of the query by `and` give them.
+    pub fn repos(&self) -> Option<Vec<String>> {
+        match self {
+            Expr::And(left, right) => match (left.repos(), right.repos()) {
+                (Some(a), Some(b)) => Some(a.into_iter().filter(|repo| b.contains(repo)).collect()),
+                (a, b) => a.or(b),
+            },
+            Expr::Text { field: Field::Repo, test: TextTest::Equals(repo) } => Some(vec![repo.clone()]),
+            Expr::Text { field: Field::Repo, test: TextTest::In(repos) } => Some(repos.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+const OPERATORS: [&str; 8] = ["=~", "!~", "!=", "<=", ">=", "=", "<", ">"];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if c == '(' || c == ')' || c == ',' {
+            tokens.push(match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                _ => Token::Comma,
+            });
+            rest = &rest[1..];
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..].find(c).ok_or_else(|| format!("unterminated string starting at {}", rest.chars().take(20).collect::<String>()))?;
+            tokens.push(Token::Quoted(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "()=!<>,\"'".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("unexpected '{}'", c));
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parse a query expression. Fields are author, email, message, repo, branch and date;
+/// text fields take `=`, `!=`, `=~`, `!~` (regex), `in (...)` and `not in (...)`, date
+/// takes `<`, `<=`, `>`, `>=` with a date (YYYY-MM-DD) or span (2w, 3m). Tests combine
+/// with `and`, `or`, `not` and parentheses.
+pub fn parse(input: &str) -> Result<Expr, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or_expr()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {} after a complete expression", describe(token))),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and_expr()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not_expr()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not_expr()?));
+        }
+        Ok(expr)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        if self.tokens.get(self.pos) == Some(&Token::LParen) {
+            self.pos += 1;
+            let expr = self.or_expr()?;
+            return match self.next() {
+                Some(Token::RParen) => Ok(expr),
+                other => Err(format!("expected ')' but found {}", found(other.as_ref()))),
+            };
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let name = match self.next() {
+            Some(Token::Word(word)) => word.to_lowercase(),
+            other => return Err(format!("expected a field name but found {}", found(other.as_ref()))),
+        };
+        let field = match name.as_str() {
+            "author" => Field::Author,
+            "email" => Field::Email,
+            "message" => Field::Message,
+            "repo" => Field::Repo,
+            "branch" => Field::Branch,
+            "date" => return self.date_comparison(),
+            _ => return Err(format!("unknown field '{}', expected author, email, message, repo, branch or date", name)),
+        };
+
+        let negated = self.keyword("not");
+        if self.keyword("in") {
+            let test = Expr::Text { field, test: TextTest::In(self.value_list()?) };
+            return Ok(if negated { Expr::Not(Box::new(test)) } else { test });
+        }
+        if negated {
+            return Err(format!("expected 'in' after '{} not'", name));
+        }
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            other => return Err(format!("expected an operator after '{}' but found {}", name, found(other.as_ref()))),
+        };
+        let value = self.value()?;
+        let (test, negated) = match op {
+            "=" => (TextTest::Equals(value), false),
+            "!=" => (TextTest::Equals(value), true),
+            "=~" | "!~" => {
+                let regex = regex::Regex::new(&value).map_err(|e| format!("invalid regex '{}': {}", value, e))?;
+                (TextTest::Matches(regex), op == "!~")
+            }
+            _ => return Err(format!("'{}' only works with date, use =, !=, =~, !~ or in with {}", op, name)),
+        };
+        let test = Expr::Text { field, test };
+        Ok(if negated { Expr::Not(Box::new(test)) } else { test })
+    }
+
+    fn date_comparison(&mut self) -> Result<Expr, String> {
+        let op = match self.next() {
+            Some(Token::Op("<")) => DateOp::Before,
+            Some(Token::Op("<=")) => DateOp::AtOrBefore,
+            Some(Token::Op(">")) => DateOp::After,
+            Some(Token::Op(">=")) => DateOp::AtOrAfter,
+            other => return Err(format!("expected <, <=, > or >= after 'date' but found {}", found(other.as_ref()))),
+        };
+        let value = self.value()?;
+        // a date on the right of <= or > means the end of that day
+        let timestamp = match op {
+            DateOp::AtOrBefore | DateOp::After => dates::parse_until(&value)?,
+            DateOp::Before | DateOp::AtOrAfter => dates::parse_since(&value)?,
+        };
+        Ok(Expr::Date { op, timestamp })
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => Ok(value),
+            other => Err(format!("expected a value but found {}", found(other.as_ref()))),
+        }
+    }
+
+    fn value_list(&mut self) -> Result<Vec<String>, String> {
+        if self.next() != Some(Token::LParen) {
+            return Err("expected '(' after 'in'".to_string());
+        }
+        let mut values = vec![self.value()?];
+        loop {
+            match self.next() {
+                Some(Token::Comma) => values.push(self.value()?),
+                Some(Token::RParen) => return Ok(values),
+                other => return Err(format!("expected ',' or ')' in list but found {}", found(other.as_ref()))),
+            }
+        }
+    }
+}
+
+fn found(token: Option<&Token>) -> String {
+    token.map_or("the end of the query".to_string(), describe)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("'{}'", word),
+        Token::Quoted(text) => format!("\"{}\"", text),
+        Token::Op(op) => format!("'{}'", op),
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::Comma => "','".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit<'a>(repo: &'a str, author: &'a str, message: &'a str, time: i64) -> CommitFields<'a> {
+        CommitFields { repo, branch: "main", author, email: "alice@example.com", message, time }
+    }
+
+    fn query(input: &str) -> Expr {
+        parse(input).unwrap_or_else(|e| panic!("{}: {}", input, e))
+    }
+
+    fn error(input: &str) -> String {
+        parse(input).expect_err(input)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = query("repo = api or repo = web and author = bob");
+        assert!(expr.matches(&commit("api", "alice", "fix", 0)));
+        assert!(!expr.matches(&commit("web", "alice", "fix", 0)));
+        assert!(expr.matches(&commit("web", "bob", "fix", 0)));
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let expr = query("(repo = api or repo = web) and author = bob");
+        assert!(!expr.matches(&commit("api", "alice", "fix", 0)));
+        assert!(expr.matches(&commit("api", "bob", "fix", 0)));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let expr = query("not repo = api and author = bob");
+        assert!(expr.matches(&commit("web", "bob", "fix", 0)));
+        assert!(!expr.matches(&commit("api", "bob", "fix", 0)));
+        assert!(!expr.matches(&commit("web", "alice", "fix", 0)));
+    }
+
+    #[test]
+    fn keywords_ignore_case() {
+        let expr = query("repo = api OR Repo = web");
+        assert!(expr.matches(&commit("web", "alice", "fix", 0)));
+    }
+
+    #[test]
+    fn text_operators() {
+        let fix = commit("api", "alice", "Fix login", 0);
+        assert!(query("author = alice").matches(&fix));
+        assert!(!query("author != alice").matches(&fix));
+        assert!(query("message =~ '^Fix'").matches(&fix));
+        assert!(!query("message !~ \"log.n\"").matches(&fix));
+        assert!(query("email = 'alice@example.com'").matches(&fix));
+        assert!(query("branch = main").matches(&fix));
+    }
+
+    #[test]
+    fn in_and_not_in() {
+        let expr = query("repo in (api, web)");
+        assert!(expr.matches(&commit("web", "alice", "fix", 0)));
+        assert!(!expr.matches(&commit("billing", "alice", "fix", 0)));
+        let expr = query("repo not in (api, web)");
+        assert!(!expr.matches(&commit("web", "alice", "fix", 0)));
+        assert!(expr.matches(&commit("billing", "alice", "fix", 0)));
+    }
+
+    #[test]
+    fn date_operators_treat_a_date_as_a_whole_day() {
+        let midnight = dates::parse_since("2024-01-31").unwrap();
+        let end_of_day = midnight + 24 * 60 * 60 - 1;
+        let noon = commit("api", "alice", "fix", midnight + 12 * 60 * 60);
+        assert!(query("date <= 2024-01-31").matches(&noon));
+        assert!(!query("date > 2024-01-31").matches(&noon));
+        assert!(!query("date < 2024-01-31").matches(&noon));
+        assert!(query("date >= 2024-01-31").matches(&noon));
+        assert!(query("date <= 2024-01-31").matches(&commit("api", "alice", "fix", end_of_day)));
+        assert!(query("date > 2024-01-31").matches(&commit("api", "alice", "fix", end_of_day + 1)));
+    }
+
+    #[test]
+    fn since_comes_from_and_joined_date_tests_only() {
+        let midnight = dates::parse_since("2024-01-01").unwrap();
+        assert_eq!(query("date >= 2024-01-01 and author = bob").since(), Some(midnight));
+        assert_eq!(query("date >= 2023-01-01 and date >= 2024-01-01").since(), Some(midnight));
+        assert_eq!(query("date > 2024-01-01").since(), Some(midnight + 24 * 60 * 60 - 1));
+        assert_eq!(query("date >= 2024-01-01 or author = bob").since(), None);
+        assert_eq!(query("date < 2024-01-01").since(), None);
+    }
+
+    #[test]
+    fn repos_come_from_and_joined_repo_tests_only() {
+        assert_eq!(query("repo = api and author = bob").repos(), Some(vec!["api".to_string()]));
+        assert_eq!(query("repo in (api, web) and repo in (web, billing)").repos(), Some(vec!["web".to_string()]));
+        assert_eq!(query("repo = api or author = bob").repos(), None);
+        assert_eq!(query("repo != api").repos(), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("colour = red"), "unknown field 'colour', expected author, email, message, repo, branch or date");
+        assert_eq!(error("author"), "expected an operator after 'author' but found the end of the query");
+        assert_eq!(error("author < bob"), "'<' only works with date, use =, !=, =~, !~ or in with author");
+        assert_eq!(error("date = 2024-01-01"), "expected <, <=, > or >= after 'date' but found '='");
+        assert_eq!(error("repo not = api"), "expected 'in' after 'repo not'");
+        assert_eq!(error("repo in api"), "expected '(' after 'in'");
+        assert_eq!(error("repo in (api web)"), "expected ',' or ')' in list but found 'web'");
+        assert_eq!(error("(repo = api"), "expected ')' but found the end of the query");
+        assert_eq!(error("repo = api repo"), "unexpected 'repo' after a complete expression");
+        assert!(error("message =~ 'fix('").starts_with("invalid regex 'fix('"));
+        assert!(error("date > soon").starts_with("'soon' is not a date"));
+    }
+
+    #[test]
+    fn unterminated_string_excerpt_respects_char_boundaries() {
+        let err = error("message = \"日本語日本語日本語日本語");
+        assert_eq!(err, "unterminated string starting at \"日本語日本語日本語日本語");
+        let err = error("message = 'aaaaaaaaaaaaaaaaaaaaaaaaa");
+        assert_eq!(err, "unterminated string starting at 'aaaaaaaaaaaaaaaaaaa");
+    }
+}
@@ -0,0 +1,63 @@
+use crate::query::CommitFields;
+use rhai::{Engine, EvalAltResult, Scope, AST};
+use std::path::Path;
+
+/// Operations one evaluation may run before it is stopped, so a script that loops forever
+/// fails its repo instead of hanging the search
+const MAX_OPERATIONS: u64 = 1_000_000;
+/// Function call depth one evaluation may reach, so runaway recursion fails the same way
+const MAX_CALL_LEVELS: usize = 64;
+
+/// A `--script` filter: a rhai script whose value decides whether a commit or branch
+/// matches. Commit scripts see `repo`, `branch`, `author`, `email`, `message` and `time`
+/// (unix seconds), branch scripts see `repo`, `branch` and `remote`, e.g.
+/// `message.len() > 72 && !author.contains("bot")`.
+#[derive(Debug)]
+pub struct Filter {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Filter {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS).set_max_call_levels(MAX_CALL_LEVELS);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("could not load script {}: {}", path.display(), e))?;
+        Ok(Self { engine, ast })
+    }
+
+    pub fn matches_commit(&self, commit: &CommitFields) -> Result<bool, String> {
+        let mut scope = Scope::new();
+        scope
+            .push_constant("repo", commit.repo.to_string())
+            .push_constant("branch", commit.branch.to_string())
+            .push_constant("author", commit.author.to_string())
+            .push_constant("email", commit.email.to_string())
+            .push_constant("message", commit.message.to_string())
+            .push_constant("time", commit.time);
+        self.eval(&mut scope)
+    }
+
+    pub fn matches_branch(&self, repo: &str, branch: &str, remote: &str) -> Result<bool, String> {
+        let mut scope = Scope::new();
+        scope
+            .push_constant("repo", repo.to_string())
+            .push_constant("branch", branch.to_string())
+            .push_constant("remote", remote.to_string());
+        self.eval(&mut scope)
+    }
+
+    fn eval(&self, scope: &mut Scope) -> Result<bool, String> {
+        self.engine.eval_ast_with_scope::<bool>(scope, &self.ast).map_err(|e| match *e {
+            EvalAltResult::ErrorTooManyOperations(_) => {
+                format!("script stopped after {} operations, it may loop forever", MAX_OPERATIONS)
+            }
+            EvalAltResult::ErrorStackOverflow(_) => {
+                format!("script stopped at {} nested function calls, it may recurse forever", MAX_CALL_LEVELS)
+            }
+            e => e.to_string(),
+        })
+    }
+}
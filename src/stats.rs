@@ -0,0 +1,94 @@
+use crate::git::CommitTime;
+use chrono::{Datelike, FixedOffset, TimeZone, Timelike};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tabled::Tabled;
+
+/// First and last hour (exclusive) of the working day, in the committer's own timezone
+const WORK_HOURS: std::ops::Range<u32> = 9..18;
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const BARS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// What the rows of a stats report are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatsGroup {
+    Author,
+    Repo,
+}
+
+/// When one author (or the authors of one repo) commit, in each committer's local time
+#[derive(Tabled, Clone)]
+pub struct HoursInfo {
+    #[tabled(rename = "Group")]
+    pub group: Arc<str>,
+    #[tabled(rename = "Commits")]
+    pub commits: usize,
+    #[tabled(rename = "Peak Hour")]
+    pub peak_hour: String,
+    #[tabled(rename = "Peak Day")]
+    pub peak_day: &'static str,
+    #[tabled(rename = "After Hours")]
+    pub after_hours: String,
+    #[tabled(rename = "Weekend")]
+    pub weekend: String,
+    #[tabled(rename = "Hours 00-23")]
+    pub by_hour: String,
+    #[tabled(rename = "Mon-Sun")]
+    pub by_day: String,
+}
+
+/// Bucket commit times by hour of day and day of week. Commits on weekends or outside
+/// working hours on weekdays count as after hours.
+pub fn hours(times: &[CommitTime], group_by: StatsGroup) -> Vec<HoursInfo> {
+    let mut grouped: BTreeMap<&str, ([usize; 24], [usize; 7], usize)> = BTreeMap::new();
+    for time in times {
+        let key = match group_by {
+            StatsGroup::Author => &*time.author,
+            StatsGroup::Repo => &*time.repo,
+        };
+        let Some(local) = FixedOffset::east_opt(time.offset_minutes * 60)
+            .and_then(|offset| offset.timestamp_opt(time.seconds, 0).single())
+        else {
+            continue;
+        };
+        let (hour, day) = (local.hour() as usize, local.weekday().num_days_from_monday() as usize);
+        let (by_hour, by_day, after_hours) = grouped.entry(key).or_insert(([0; 24], [0; 7], 0));
+        by_hour[hour] += 1;
+        by_day[day] += 1;
+        if day >= 5 || !WORK_HOURS.contains(&(hour as u32)) {
+            *after_hours += 1;
+        }
+    }
+
+    grouped
+        .into_iter()
+        .map(|(group, (by_hour, by_day, after_hours))| {
+            let commits = by_day.iter().sum::<usize>();
+            HoursInfo {
+                group: Arc::from(group),
+                commits,
+                peak_hour: format!("{:02}:00", peak(&by_hour)),
+                peak_day: DAY_NAMES[peak(&by_day)],
+                after_hours: percent(after_hours, commits),
+                weekend: percent(by_day[5] + by_day[6], commits),
+                by_hour: sparkline(&by_hour),
+                by_day: sparkline(&by_day),
+            }
+        })
+        .collect()
+}
+
+/// Index of the busiest bucket, the earliest one on ties
+fn peak(buckets: &[usize]) -> usize {
+    buckets.iter().enumerate().max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0))).map_or(0, |(index, _)| index)
+}
+
+fn percent(part: usize, total: usize) -> String {
+    format!("{}%", (part * 100).checked_div(total).unwrap_or(0))
+}
+
+/// One bar per bucket, scaled to the busiest one
+fn sparkline(buckets: &[usize]) -> String {
+    let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+    buckets.iter().map(|&count| BARS[(count * (BARS.len() - 1)).div_ceil(max)]).collect()
+}
@@ -0,0 +1,43 @@
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Decide whether output is styled. `--no-color` and a non-empty NO_COLOR turn styling
+/// off, CLICOLOR_FORCE (other than "0") keeps it on when stdout is not a terminal.
+pub fn init(no_color: bool) {
+    let value = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let enabled = if no_color || value("NO_COLOR").is_some() {
+        false
+    } else if value("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+        true
+    } else {
+        std::io::stdout().is_terminal()
+    };
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn paint(style: ansi_term::Style, text: impl Display) -> String {
+    if enabled() {
+        style.paint(text.to_string()).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn bold(text: impl Display) -> String {
+    paint(ansi_term::Style::new().bold(), text)
+}
+
+pub fn green(text: impl Display) -> String {
+    paint(ansi_term::Colour::Green.normal(), text)
+}
+
+pub fn red(text: impl Display) -> String {
+    paint(ansi_term::Colour::Red.normal(), text)
+}
@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Branch `sync files` commits on when neither --branch nor the map file names one
+pub const DEFAULT_BRANCH: &str = "grepo/sync-files";
+/// Commit message `sync files` uses when neither --message nor the map file gives one
+pub const DEFAULT_MESSAGE: &str = "Sync shared files";
+
+/// Contents of a `sync files --map` file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SyncMap {
+    /// Source file, relative to --source, mapped to its path in each repo
+    pub files: BTreeMap<String, String>,
+    pub branch: Option<String>,
+    pub message: Option<String>,
+}
+
+/// A shared file and where it goes in each repo
+pub struct SyncFile {
+    pub dest: String,
+    pub contents: Vec<u8>,
+}
+
+/// Read the map file, or without one a map sending every file below `source` to the
+/// same relative path
+pub fn load_map(source: &Path, map: Option<&Path>) -> Result<SyncMap, String> {
+    if let Some(map) = map {
+        let text = fs::read_to_string(map).map_err(|e| format!("could not read {}: {}", map.display(), e))?;
+        return serde_yaml::from_str(&text).map_err(|e| format!("invalid map file {}: {}", map.display(), e));
+    }
+    let mut files = BTreeMap::new();
+    collect_files(source, source, &mut files).map_err(|e| format!("could not read {}: {}", source.display(), e))?;
+    Ok(SyncMap { files, ..SyncMap::default() })
+}
+
+/// Read each mapped source file
+pub fn read_files(source: &Path, map: &SyncMap) -> Result<Vec<SyncFile>, String> {
+    map.files
+        .iter()
+        .map(|(from, dest)| {
+            let path = source.join(from);
+            fs::read(&path)
+                .map(|contents| SyncFile { dest: dest.clone(), contents })
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))
+        })
+        .collect()
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut BTreeMap<String, String>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name == ".git") {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let relative = relative.display().to_string();
+            files.insert(relative.clone(), relative);
+        }
+    }
+    Ok(())
+}